@@ -0,0 +1,61 @@
+use crate::sudo::SudoStatus;
+use crate::websocket::{CompletionReason, PingReport, WebSocketMessage};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use ulid::Ulid;
+
+/// A process was started (by any client).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ProcessStarted {
+    pub id: Ulid,
+    pub command_id: Ulid,
+}
+
+/// A process has finished, for whatever reason.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ProcessFinished {
+    pub id: Ulid,
+    pub command_id: Ulid,
+    pub code: i32,
+    pub reason: CompletionReason,
+}
+
+/// A dependency was installed, or removed.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DependencyChanged {
+    pub name: String,
+    pub installed: bool,
+}
+
+/// Something that happened on the server, which every client should
+/// know about.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Event {
+    ProcessStarted(ProcessStarted),
+    ProcessFinished(ProcessFinished),
+    DependencyChanged(DependencyChanged),
+    /// Sudo was acquired, lost or is asking for the password.
+    SudoStatus(SudoStatus),
+    /// New logins were enabled (true), or disabled after a login (false).
+    LoginAllowed(bool),
+    /// The session (by its id) was logged out, so every client of it must
+    /// log in again. It is only sent to the clients of that session.
+    SessionRevoked(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// Enum of message types that the server may send on the event socket.
+pub enum EventServerMsg {
+    Ping,
+    Pong,
+    PingReport(PingReport),
+    Event(Event),
+}
+
+impl WebSocketMessage for EventServerMsg {
+    const PING: Self = EventServerMsg::Ping;
+    const PONG: Self = EventServerMsg::Pong;
+    fn ping_report(duration: Duration) -> Self {
+        EventServerMsg::PingReport(PingReport { duration })
+    }
+}
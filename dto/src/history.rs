@@ -0,0 +1,55 @@
+use crate::script::UnmetRequirement;
+use crate::websocket::CompletionReason;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+/// What started a run.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub enum RunTrigger {
+    /// Started from the terminal of a client.
+    Manual,
+    /// A step of the workflow run with this id.
+    Workflow(Ulid),
+    /// Started by the schedule with this name (when it was due, or when
+    /// it was triggered by hand).
+    Schedule(String),
+}
+
+/// One run of a library script, as kept in the run history.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct RunRecord {
+    /// The process id.
+    pub id: Ulid,
+    /// The name of the library script, eg. `InstallDependencies`.
+    pub command: String,
+    pub command_id: Ulid,
+    pub trigger: RunTrigger,
+    /// The environment profile it ran with.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// The environment it ran with, with the values of secrets masked.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Milliseconds since the unix epoch.
+    pub started_ms: u64,
+    /// None while it is still running.
+    pub finished_ms: Option<u64>,
+    pub code: Option<i32>,
+    pub reason: Option<CompletionReason>,
+    /// Why it was not run, if it was rejected.
+    #[serde(default)]
+    pub unmet_requirements: Vec<UnmetRequirement>,
+    #[serde(default)]
+    pub outputs: BTreeMap<String, String>,
+    /// The last lines of its output (of every stream).
+    #[serde(default)]
+    pub output_tail: Vec<String>,
+}
+
+impl RunRecord {
+    pub fn succeeded(&self) -> bool {
+        self.code == Some(0)
+    }
+}
@@ -1,5 +1,15 @@
 pub mod docs;
+pub mod events;
+pub mod history;
+pub mod logs;
+pub mod profile;
+pub mod queue;
+pub mod schedule;
 pub mod script;
+pub mod secrets;
 pub mod session;
+pub mod sudo;
+pub mod trust;
 pub mod websocket;
+pub mod workflow;
 pub mod workstation;
@@ -0,0 +1,67 @@
+use crate::websocket::{PingReport, WebSocketMessage};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use strum::{AsRefStr, Display, EnumIter, EnumString};
+
+/// Log levels, ordered from most to least severe.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+    EnumIter,
+    AsRefStr,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// A single server log event, as kept in the server's ring buffer.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LogEvent {
+    /// Milliseconds since the unix epoch.
+    pub timestamp_ms: u64,
+    pub level: LogLevel,
+    /// The module path the event was logged from.
+    pub target: String,
+    /// The message, followed by any other fields as `key=value`.
+    pub message: String,
+}
+
+impl LogEvent {
+    /// Case insensitive search of the target and message.
+    pub fn matches(&self, search: &str) -> bool {
+        let search = search.to_lowercase();
+        self.message.to_lowercase().contains(&search)
+            || self.target.to_lowercase().contains(&search)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// Enum of message types that the server may send to the log viewer.
+pub enum LogServerMsg {
+    Ping,
+    Pong,
+    PingReport(PingReport),
+    Log(LogEvent),
+}
+
+impl WebSocketMessage for LogServerMsg {
+    const PING: Self = LogServerMsg::Ping;
+    const PONG: Self = LogServerMsg::Pong;
+    fn ping_report(duration: Duration) -> Self {
+        LogServerMsg::PingReport(PingReport { duration })
+    }
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use utoipa::ToSchema;
+
+/// Shown in place of the value of a secret variable.
+pub const MASK: &str = "********";
+
+/// Variables with any of these in their name are taken to be secrets,
+/// even if they are not listed as such:
+const SECRET_NAMES: &[&str] = &[
+    "PASSWORD",
+    "PASSWD",
+    "SECRET",
+    "TOKEN",
+    "API_KEY",
+    "PRIVATE_KEY",
+    "CREDENTIAL",
+];
+
+/// Does the name of the variable look like it holds a secret?
+pub fn is_secret_name(name: &str) -> bool {
+    let name = name.to_uppercase();
+    SECRET_NAMES.iter().any(|secret| name.contains(secret))
+}
+
+/// A named set of environment variables, that a run may pick, eg:
+///
+///   {"name": "staging", "vars": {"DOCKER_CONTEXT": "staging",
+///    "ROOT_DIR": "/srv/d.rymcg.tech"}}
+///
+/// Its variables are added to the (sanitized) environment of the server.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq, Default)]
+pub struct EnvProfile {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+    /// The variables whose values are never shown (besides those that
+    /// look like secrets, see is_secret_name).
+    #[serde(default)]
+    pub secrets: BTreeSet<String>,
+}
+
+impl EnvProfile {
+    pub fn is_secret(&self, name: &str) -> bool {
+        self.secrets.contains(name) || is_secret_name(name)
+    }
+
+    /// The profile, with the values of its secrets replaced by MASK.
+    pub fn masked(&self) -> Self {
+        Self {
+            vars: mask_env(&self.vars, |name| self.is_secret(name)),
+            ..self.clone()
+        }
+    }
+}
+
+/// The environment, with the values of its secrets replaced by MASK.
+pub fn mask_env(
+    env: &BTreeMap<String, String>,
+    is_secret: impl Fn(&str) -> bool,
+) -> BTreeMap<String, String> {
+    env.iter()
+        .map(|(name, value)| match is_secret(name) {
+            true => (name.clone(), MASK.to_string()),
+            false => (name.clone(), value.clone()),
+        })
+        .collect()
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+/// Stands for the concurrency limit in QueuedRun::waiting_for (see
+/// --max-concurrent-runs).
+pub const CONCURRENCY_LIMIT: &str = "concurrency limit";
+
+/// A run that holds its locks (and counts towards the concurrency
+/// limit) until it finishes.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct RunningRun {
+    /// The process id.
+    pub id: Ulid,
+    /// The name of the library script, eg. `InstallDependencies`.
+    pub command: String,
+    pub locks: Vec<String>,
+    /// Milliseconds since the unix epoch.
+    pub started_ms: u64,
+}
+
+/// A run waiting in the queue for its turn.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct QueuedRun {
+    /// The process id.
+    pub id: Ulid,
+    pub command: String,
+    pub locks: Vec<String>,
+    /// Milliseconds since the unix epoch.
+    pub queued_ms: u64,
+    /// The locks that are held by a running run (or wanted by a run
+    /// ahead of it in the queue), and CONCURRENCY_LIMIT if there are as
+    /// many runs as there may be.
+    pub waiting_for: Vec<String>,
+}
+
+/// The runs of library scripts that are running, and those that are
+/// waiting for them, in order.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq, Default)]
+pub struct RunQueueStatus {
+    /// The most runs at once (None for no limit).
+    pub max_concurrent_runs: Option<usize>,
+    pub running: Vec<RunningRun>,
+    pub queued: Vec<QueuedRun>,
+}
+
+/// A new order for the queue: the ids of queued runs, the first to
+/// run first. The runs that are not listed follow them, in the order
+/// they were in.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct QueueOrder {
+    pub order: Vec<Ulid>,
+}
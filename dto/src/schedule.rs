@@ -0,0 +1,39 @@
+use crate::history::RunRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// A library script that runs by itself, whenever its cron expression
+/// is due (in the server's local time), eg:
+///
+///   {"name": "prune", "cron": "0 3 * * SUN", "script": "PruneImages"}
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct Schedule {
+    pub name: String,
+    /// A crontab expression: minute, hour, day of month, month and day
+    /// of week (or with seconds first, and an optional year last).
+    pub cron: String,
+    /// The name of the library script, eg. `InstallDependencies`.
+    pub script: String,
+    /// Environment variables passed to the script.
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+    /// The environment profile to run it with (see EnvProfile).
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// A paused schedule does not run until it is resumed (but it may
+    /// still be triggered by hand).
+    #[serde(default)]
+    pub paused: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct ScheduleStatus {
+    pub schedule: Schedule,
+    /// When it will next run (milliseconds since the unix epoch), unless
+    /// it is paused.
+    pub next_run_ms: Option<u64>,
+    pub running: bool,
+    /// The most recent run, from the run history.
+    pub last_run: Option<RunRecord>,
+}
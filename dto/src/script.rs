@@ -1,6 +1,7 @@
 use dry_console_common::token::generate_deterministic_ulid_from_seed;
 use indoc::indoc;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use ulid::Ulid;
 use utoipa::ToSchema;
 
@@ -9,6 +10,282 @@ pub struct ScriptEntry {
     pub id: Ulid,
     pub description: String,
     pub script: String,
+    pub requirements: ScriptRequirements,
+    #[serde(default)]
+    pub limits: ScriptLimits,
+    /// Filled in by the server: the requirements this workstation
+    /// does not currently meet. The script will not be run until
+    /// this is empty.
+    #[serde(default)]
+    pub unmet_requirements: Vec<UnmetRequirement>,
+    /// Filled in by the server: the problems found by `bash -n` (and
+    /// shellcheck, when it is installed). The script will not be run
+    /// while any of these are errors.
+    #[serde(default)]
+    pub diagnostics: Vec<ScriptDiagnostic>,
+    /// The shell libraries the script includes (`# include: common`).
+    #[serde(default)]
+    pub includes: Vec<String>,
+    /// Is the script a template, rendered with the facts of the
+    /// workstation before it is run (`# template: yes`)?
+    #[serde(default)]
+    pub template: bool,
+    /// The named locks the script holds while it runs (`# locks:
+    /// package-manager`). A run waits in the queue while another run
+    /// holds any of them.
+    #[serde(default)]
+    pub locks: Vec<String>,
+    /// Filled in by the server, if the script includes any libraries,
+    /// or is a template: the script as it is run.
+    #[serde(default)]
+    pub rendered: Option<String>,
+}
+
+/// A problem found in a script before it is run. The line numbers are
+/// those of the script as it is run (see ScriptEntry::rendered),
+/// without the description.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct ScriptDiagnostic {
+    pub line: usize,
+    pub column: Option<usize>,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    /// What found it: `bash`, or the shellcheck code (eg. `SC2086`).
+    pub source: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Info,
+    Style,
+}
+
+impl fmt::Display for DiagnosticSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticSeverity::Error => write!(f, "error"),
+            DiagnosticSeverity::Warning => write!(f, "warning"),
+            DiagnosticSeverity::Info => write!(f, "info"),
+            DiagnosticSeverity::Style => write!(f, "style"),
+        }
+    }
+}
+
+impl fmt::Display for ScriptDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "line {}:{column}", self.line)?,
+            None => write!(f, "line {}", self.line)?,
+        }
+        write!(f, ": {}: {} ({})", self.severity, self.message, self.source)
+    }
+}
+
+/// Requirements declared in the script header, eg:
+///
+///   # requires: sudo
+///   # platforms: fedora, debian
+///   # needs: docker, jq
+///   # secrets: GITEA_TOKEN, SMTP_PASSWORD
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq, Default)]
+pub struct ScriptRequirements {
+    /// Root privileges must have been acquired (`requires: sudo`).
+    pub sudo: bool,
+    /// Distributions (or OS types) the script supports, lowercase.
+    /// Empty means any platform.
+    pub platforms: Vec<String>,
+    /// Programs that must be installed.
+    pub needs: Vec<String>,
+    /// Secrets from the vault, passed to the script as environment
+    /// variables of the same names.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// Directives with an invalid value (eg. `timeout: 10x`), which
+    /// keep the script from running until they are fixed.
+    #[serde(default)]
+    pub invalid_directives: Vec<String>,
+}
+
+impl ScriptRequirements {
+    /// Parse just the requirements from a script's header.
+    pub fn from_source(source: &str) -> Self {
+        extract_source_and_description(source)
+            .map(|(_, _, header)| header.requirements)
+            .unwrap_or_default()
+    }
+}
+
+/// Resource limits declared in the script header, eg:
+///
+///   # timeout: 10m
+///   # cpu: 50%
+///   # memory: 512M
+///
+/// Any limit that is not declared falls back to the server's default.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Default)]
+pub struct ScriptLimits {
+    /// The longest the script may run.
+    pub timeout_seconds: Option<u64>,
+    /// The CPU time the script may use, as a percentage of one CPU
+    /// (eg. 200 for two whole CPUs).
+    pub cpu_percent: Option<u32>,
+    /// The most memory the script may use, in bytes.
+    pub memory_bytes: Option<u64>,
+}
+
+impl ScriptLimits {
+    /// Parse just the limits from a script's header.
+    pub fn from_source(source: &str) -> Self {
+        extract_source_and_description(source)
+            .map(|(_, _, header)| header.limits)
+            .unwrap_or_default()
+    }
+
+    /// These limits, with any that are missing taken from defaults.
+    pub fn or(self, defaults: ScriptLimits) -> Self {
+        Self {
+            timeout_seconds: self.timeout_seconds.or(defaults.timeout_seconds),
+            cpu_percent: self.cpu_percent.or(defaults.cpu_percent),
+            memory_bytes: self.memory_bytes.or(defaults.memory_bytes),
+        }
+    }
+}
+
+/// Parse just the names of the libraries included by a script's (or a
+/// library's) header, in order.
+pub fn script_includes(source: &str) -> Vec<String> {
+    extract_source_and_description(source)
+        .map(|(_, _, header)| header.includes)
+        .unwrap_or_default()
+}
+
+/// Parse just the names of the locks declared by a script's header.
+pub fn script_locks(source: &str) -> Vec<String> {
+    extract_source_and_description(source)
+        .map(|(_, _, header)| header.locks)
+        .unwrap_or_default()
+}
+
+/// Is the script (the full source) a template (`# template: yes`)?
+pub fn is_template(source: &str) -> bool {
+    extract_source_and_description(source).is_some_and(|(_, _, header)| header.template)
+}
+
+/// Parse a duration like 90, 90s, 10m or 2h into seconds.
+pub fn parse_duration_seconds(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(value.trim_end_matches(char::is_alphabetic).len());
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Unknown unit of time: {unit} (use s, m, h or d)")),
+    };
+    let number = number
+        .trim()
+        .parse::<u64>()
+        .map_err(|e| format!("Invalid duration: {value} ({e})"))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Invalid duration: {value} (too long)"))
+}
+
+/// Parse a size like 1048576, 1024K, 512M or 2G into bytes.
+pub fn parse_bytes(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(value.trim_end_matches(char::is_alphabetic).len());
+    let multiplier = match unit.to_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("Unknown unit of size: {unit} (use K, M, G or T)")),
+    };
+    let number = number
+        .trim()
+        .parse::<u64>()
+        .map_err(|e| format!("Invalid size: {value} ({e})"))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Invalid size: {value} (too large)"))
+}
+
+/// Parse a CPU quota like 50% or 200 into a percentage of one CPU.
+pub fn parse_cpu_percent(value: &str) -> Result<u32, String> {
+    let value = value.trim();
+    match value.trim_end_matches('%').trim().parse::<u32>() {
+        Ok(0) => Err(format!(
+            "Invalid CPU percentage: {value} (must be more than 0)"
+        )),
+        Ok(percent) => Ok(percent),
+        Err(e) => Err(format!("Invalid CPU percentage: {value} ({e})")),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub enum UnmetRequirement {
+    Sudo,
+    Platform {
+        supported: Vec<String>,
+        detected: String,
+    },
+    Dependency(String),
+    InvalidDirective(String),
+    /// The script has this many errors (see its diagnostics).
+    ScriptErrors(usize),
+    /// This version of the script is not builtin, and has not been
+    /// approved (see ScriptReview).
+    Unapproved,
+    /// The included libraries could not be found, or include each other.
+    Include(String),
+    /// The script is a template, that could not be rendered.
+    Template(String),
+    /// There is no environment profile of this name.
+    UnknownProfile(String),
+    /// The script needs secrets, but the vault is locked.
+    VaultLocked,
+    /// There is no secret of this name in the vault.
+    Secret(String),
+}
+
+impl fmt::Display for UnmetRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnmetRequirement::Sudo => write!(f, "root privileges (sudo) have not been acquired"),
+            UnmetRequirement::Platform {
+                supported,
+                detected,
+            } => write!(
+                f,
+                "this platform ({detected}) is not supported, only: {}",
+                supported.join(", ")
+            ),
+            UnmetRequirement::Dependency(name) => write!(f, "{name} is not installed"),
+            UnmetRequirement::InvalidDirective(message) => {
+                write!(f, "the header has an invalid directive, {message}")
+            }
+            UnmetRequirement::ScriptErrors(1) => write!(f, "the script has an error"),
+            UnmetRequirement::ScriptErrors(count) => write!(f, "the script has {count} errors"),
+            UnmetRequirement::Unapproved => {
+                write!(f, "this version of the script has not been approved")
+            }
+            UnmetRequirement::Include(message) => {
+                write!(f, "the included libraries could not be expanded: {message}")
+            }
+            UnmetRequirement::Template(message) => {
+                write!(f, "the template could not be rendered: {message}")
+            }
+            UnmetRequirement::UnknownProfile(name) => {
+                write!(f, "there is no environment profile named {name}")
+            }
+            UnmetRequirement::VaultLocked => write!(f, "the secrets vault is locked"),
+            UnmetRequirement::Secret(name) => write!(f, "there is no secret named {name}"),
+        }
+    }
 }
 
 impl Default for ScriptEntry {
@@ -19,29 +296,66 @@ impl Default for ScriptEntry {
             echo \"Failed to find command in Command Library && exit 1\"
         "};
         let id = Ulid::default();
-        let (description, script) = extract_source_and_description(script)
+        let (description, script, header) = extract_source_and_description(script)
             .expect("error parsing shell script source and/or description");
         Self {
             id,
             description,
             script,
+            requirements: header.requirements,
+            limits: header.limits,
+            unmet_requirements: Vec::new(),
+            diagnostics: Vec::new(),
+            includes: header.includes,
+            template: header.template,
+            locks: header.locks,
+            rendered: None,
         }
     }
 }
 
 impl ScriptEntry {
-    pub fn from_source(source: String) -> Self {
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == DiagnosticSeverity::Error)
+            .count()
+    }
+
+    /// Parse a script (the full source). It must start with a header:
+    /// the comment lines of its description and directives.
+    pub fn from_source(source: String) -> Result<Self, String> {
         let id = generate_deterministic_ulid_from_seed(&source);
-        let (description, script) = extract_source_and_description(&source)
-            .expect("error parsing shell script source / description");
-        Self {
+        let (description, script, header) = extract_source_and_description(&source)
+            .ok_or_else(|| "the script has no header (comment lines at the top)".to_string())?;
+        Ok(Self {
             id,
             description,
             script,
-        }
+            requirements: header.requirements,
+            limits: header.limits,
+            unmet_requirements: Vec::new(),
+            diagnostics: Vec::new(),
+            includes: header.includes,
+            template: header.template,
+            locks: header.locks,
+            rendered: None,
+        })
     }
 }
 
+/// The script without its header, as it is checked (see
+/// ScriptDiagnostic).
+pub fn script_body(source: &str) -> String {
+    extract_source_and_description(source)
+        .map(|(_, script, _)| script)
+        .unwrap_or_else(|| source.trim_start().to_string())
+}
+
 #[allow(clippy::manual_strip)]
 fn trim_single_starting_space(line: &str) -> &str {
     if line.starts_with(' ') {
@@ -51,16 +365,105 @@ fn trim_single_starting_space(line: &str) -> &str {
     }
 }
 
-fn extract_source_and_description(script: &str) -> Option<(String, String)> {
+/// Split a comma separated directive value into its lowercase items.
+fn directive_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|v| v.trim().to_lowercase())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// The directives in a script's header.
+#[derive(Default)]
+struct ScriptHeader {
+    requirements: ScriptRequirements,
+    limits: ScriptLimits,
+    includes: Vec<String>,
+    template: bool,
+    locks: Vec<String>,
+}
+
+/// Parse a header line as a directive, returning false if it is just
+/// part of the description. A limit with an invalid value is recorded
+/// in the requirements (see invalid_directives).
+fn parse_directive(line: &str, header: &mut ScriptHeader) -> bool {
+    let Some((key, value)) = line.split_once(':') else {
+        return false;
+    };
+    let requirements = &mut header.requirements;
+    let limits = &mut header.limits;
+    match key.trim() {
+        "requires" => {
+            let required = directive_list(value);
+            requirements.sudo |= required.iter().any(|r| r == "sudo" || r == "root");
+        }
+        "platforms" => requirements.platforms.extend(directive_list(value)),
+        "needs" => requirements.needs.extend(directive_list(value)),
+        // (Names of environment variables, so not lowercased.)
+        "secrets" => requirements.secrets.extend(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string),
+        ),
+        "timeout" => {
+            limits.timeout_seconds = limit(line, parse_duration_seconds(value), requirements)
+        }
+        "cpu" => limits.cpu_percent = limit(line, parse_cpu_percent(value), requirements),
+        "memory" => limits.memory_bytes = limit(line, parse_bytes(value), requirements),
+        "include" => header.includes.extend(directive_list(value)),
+        "locks" => {
+            for lock in directive_list(value) {
+                if !header.locks.contains(&lock) {
+                    header.locks.push(lock);
+                }
+            }
+        }
+        "template" => {
+            header.template = directive_list(value)
+                .iter()
+                .any(|v| v == "yes" || v == "true" || v == "on")
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// The value of a limit directive, or None if it is invalid (which is
+/// recorded, so that the script is not run without the limit).
+fn limit<T>(
+    line: &str,
+    value: Result<T, String>,
+    requirements: &mut ScriptRequirements,
+) -> Option<T> {
+    value
+        .map_err(|e| {
+            let message = format!("`{}`: {e}", line.trim());
+            requirements.invalid_directives.push(message);
+        })
+        .ok()
+}
+
+/// Split a script into its description, the rest of the script, and
+/// the directives of its header. None if it has no header at all (a
+/// header of only directives has an empty description).
+fn extract_source_and_description(script: &str) -> Option<(String, String, ScriptHeader)> {
     let mut description = Vec::new();
     let mut stripped_script = String::new();
+    let mut header = ScriptHeader::default();
+    let mut header_lines = 0;
     let mut in_description = true;
 
     for line in script.lines() {
         if in_description {
             if line.starts_with('#') {
+                header_lines += 1;
                 let comment_content = trim_single_starting_space(line.trim_start_matches('#'));
-                description.push(comment_content.to_string());
+                if !parse_directive(comment_content, &mut header) {
+                    description.push(comment_content.to_string());
+                }
             } else {
                 // End description when the first non-comment, non-empty line is encountered
                 in_description = false;
@@ -74,12 +477,85 @@ fn extract_source_and_description(script: &str) -> Option<(String, String)> {
         }
     }
 
-    if !description.is_empty() {
+    if header_lines > 0 {
         Some((
             description.join("\n"),
             stripped_script.trim_start().to_string(),
+            header,
         ))
     } else {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_in_every_unit() {
+        assert_eq!(parse_duration_seconds("90"), Ok(90));
+        assert_eq!(parse_duration_seconds(" 90s "), Ok(90));
+        assert_eq!(parse_duration_seconds("10m"), Ok(600));
+        assert_eq!(parse_duration_seconds("2h"), Ok(7200));
+        assert_eq!(parse_duration_seconds("1d"), Ok(86400));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        assert!(parse_duration_seconds("").is_err());
+        assert!(parse_duration_seconds("10x").is_err());
+        assert!(parse_duration_seconds("ten minutes").is_err());
+        assert!(parse_duration_seconds("-5s").is_err());
+        assert!(parse_duration_seconds(&format!("{}d", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn sizes_in_every_unit() {
+        assert_eq!(parse_bytes("1048576"), Ok(1 << 20));
+        assert_eq!(parse_bytes("1024K"), Ok(1 << 20));
+        assert_eq!(parse_bytes("512m"), Ok(512 << 20));
+        assert_eq!(parse_bytes("2GB"), Ok(2 << 30));
+        assert_eq!(parse_bytes("1T"), Ok(1 << 40));
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        assert!(parse_bytes("").is_err());
+        assert!(parse_bytes("12Q").is_err());
+        assert!(parse_bytes("1.5G").is_err());
+        assert!(parse_bytes(&format!("{}T", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn cpu_percentages() {
+        assert_eq!(parse_cpu_percent("50%"), Ok(50));
+        assert_eq!(parse_cpu_percent("200"), Ok(200));
+        assert!(parse_cpu_percent("0").is_err());
+        assert!(parse_cpu_percent("0%").is_err());
+        assert!(parse_cpu_percent("half").is_err());
+    }
+
+    #[test]
+    fn limits_from_the_header() {
+        let source = "# Limited\n# timeout: 10m\n# cpu: 50%\n# memory: 512M\necho hi\n";
+        let limits = ScriptLimits::from_source(source);
+        assert_eq!(limits.timeout_seconds, Some(600));
+        assert_eq!(limits.cpu_percent, Some(50));
+        assert_eq!(limits.memory_bytes, Some(512 << 20));
+        assert!(ScriptRequirements::from_source(source)
+            .invalid_directives
+            .is_empty());
+    }
+
+    #[test]
+    fn invalid_limits_are_header_errors() {
+        let source = "# Limited\n# timeout: 10x\n# cpu: 0\n# memory: lots\necho hi\n";
+        assert_eq!(ScriptLimits::from_source(source), ScriptLimits::default());
+        let invalid = ScriptRequirements::from_source(source).invalid_directives;
+        assert_eq!(invalid.len(), 3);
+        assert!(invalid[0].contains("timeout: 10x"));
+        assert!(invalid[1].contains("cpu: 0"));
+        assert!(invalid[2].contains("memory: lots"));
+    }
+}
@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The secrets vault. Values are never sent to the client, only names.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq, Default)]
+pub struct VaultStatus {
+    /// Has the vault been created? (It is created when it is first unlocked.)
+    pub exists: bool,
+    /// Is it unlocked, for this session of the server?
+    pub unlocked: bool,
+    /// The names of the secrets, if it is unlocked.
+    pub names: Vec<String>,
+}
+
+/// Unlock the vault (or create it, with this passphrase).
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct VaultPassphrase {
+    pub passphrase: String,
+    /// The passphrase again, which is needed to create the vault.
+    #[serde(default)]
+    pub confirm: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct SecretValue {
+    pub value: String,
+}
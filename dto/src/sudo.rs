@@ -0,0 +1,126 @@
+use crate::websocket::{PingReport, WebSocketMessage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+use strum::{AsRefStr, Display, EnumIter, EnumString};
+use utoipa::ToSchema;
+
+/// Programs that can run commands as root.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Display,
+    EnumString,
+    EnumIter,
+    AsRefStr,
+    ToSchema,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Escalator {
+    Sudo,
+    Doas,
+    /// systemd's run0 (authenticates with polkit).
+    Run0,
+    Pkexec,
+}
+
+impl Escalator {
+    /// Arguments to run a command without ever prompting for a
+    /// password, failing instead if authentication is required. None
+    /// for pkexec, which has no such option: a desktop polkit agent
+    /// still prompts for the password.
+    pub fn non_interactive_args(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Escalator::Sudo => Some(&["-n"]),
+            Escalator::Doas => Some(&["-n"]),
+            Escalator::Run0 => Some(&["--no-ask-password"]),
+            Escalator::Pkexec => None,
+        }
+    }
+
+    /// The value of `$DRY_SUDO` in scripts, eg. `doas -n`. None if the
+    /// escalator can not be used by scripts (see non_interactive_args).
+    pub fn script_prefix(&self) -> Option<String> {
+        let args = self.non_interactive_args()?;
+        Some(
+            std::iter::once(self.as_ref())
+                .chain(args.iter().copied())
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+    }
+
+    /// Does it cache the credentials for a while (a timestamp that can
+    /// be refreshed)? run0 and pkexec authenticate every command anew.
+    pub fn caches_credentials(&self) -> bool {
+        matches!(self, Escalator::Sudo | Escalator::Doas)
+    }
+
+    /// Can the password be entered in the browser? (sudo -A)
+    pub fn supports_askpass(&self) -> bool {
+        matches!(self, Escalator::Sudo)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub enum SudoState {
+    /// Sudo is not in use (not requested, or disabled with --no-sudo).
+    #[default]
+    Disabled,
+    /// Root access is available, and the sudo session is kept alive.
+    Active,
+    /// The sudo session could not be refreshed, and must be re-authenticated.
+    Expired,
+    /// Sudo is being (re-)acquired.
+    Authenticating,
+    /// Sudo is waiting for the user to enter their password.
+    AwaitingPassword,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, ToSchema)]
+pub struct SudoStatus {
+    pub state: SudoState,
+    /// Explains the state, eg. why authentication failed.
+    pub message: Option<String>,
+    /// Can sudo be (re-)acquired from the browser? (Not with --no-sudo,
+    /// nor with an escalator that does not support askpass)
+    pub can_acquire: bool,
+    /// The escalator in use, or None if none is installed.
+    pub escalator: Option<Escalator>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct SudoPassword {
+    pub password: String,
+}
+
+impl fmt::Debug for SudoPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SudoPassword")
+            .field("password", &"REDACTED")
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// Enum of message types that the server may send to the sudo status socket.
+pub enum SudoServerMsg {
+    Ping,
+    Pong,
+    PingReport(PingReport),
+    Status(SudoStatus),
+}
+
+impl WebSocketMessage for SudoServerMsg {
+    const PING: Self = SudoServerMsg::Ping;
+    const PONG: Self = SudoServerMsg::Pong;
+    fn ping_report(duration: Duration) -> Self {
+        SudoServerMsg::PingReport(PingReport { duration })
+    }
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+use utoipa::ToSchema;
+
+/// A script that is not builtin (eg. one that was generated for this
+/// workstation), as it is now, compared with the version of it that
+/// was last approved.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct ScriptReview {
+    /// The name of the library script, eg. `InstallDependencies`.
+    pub command: String,
+    /// The id of the script as it is now (derived from a hash of its
+    /// source). This is what gets approved.
+    pub id: Ulid,
+    /// With the libraries it includes expanded, but not rendered (if it
+    /// is a template).
+    pub source: String,
+    /// Builtin scripts, and approved versions, may be run as they are.
+    pub trusted: bool,
+    /// The id of the version that was last approved, if any.
+    pub approved_id: Option<Ulid>,
+    /// The changes from the version that was last approved (or from
+    /// nothing, if there isn't one).
+    pub diff: Vec<DiffLine>,
+}
+
+/// Approve a script, by the id from its review.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct ScriptApproval {
+    pub id: Ulid,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// The lines removed from old, and added to new (by the longest
+/// common subsequence of lines).
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // common[i][j] is the length of the LCS of old[i..] and new[j..]:
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = match old[i] == new[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            diff.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        } else {
+            diff.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        }
+    }
+    diff
+}
@@ -1,8 +1,48 @@
+use crate::script::UnmetRequirement;
+use crate::sudo::SudoStatus;
+use crate::workflow::{StepState, Workflow};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use num_enum::IntoPrimitive;
-use serde::ser::SerializeStruct;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
 use std::time::Duration;
+use strum::{AsRefStr, Display, EnumIter};
 use ulid::Ulid;
+use utoipa::ToSchema;
+
+/// The version of ServerMsg and ClientMsg. Bump it whenever either one
+/// changes in a way that an older build could not understand. Hello and
+/// Welcome must never change, so that every build can read them.
+pub const PROTOCOL_VERSION: u32 = 11;
+
+/// Optional features of the command socket.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Capability {
+    /// Many processes may run on one socket at once.
+    Multiplex,
+    /// A client may attach to a process started by another socket.
+    Attach,
+    /// Processes may run in a pseudo terminal.
+    Pty,
+    /// Workflows (several library scripts, run as one) may be run.
+    Workflow,
+}
+
+/// The first message the client sends on the command socket.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub client_version: String,
+    pub capabilities: Vec<Capability>,
+}
+
+/// The first message the server sends on the command socket.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Welcome {
+    pub protocol_version: u32,
+    pub server_version: String,
+    pub capabilities: Vec<Capability>,
+}
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct PingReport {
@@ -14,55 +54,308 @@ pub struct PingReport {
     pub duration: Duration,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+/// Start a command from the library. One socket may run any number of
+/// processes at once, so every message about this one is tagged with
+/// the process_id, which the client chooses.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Command {
     pub id: Ulid,
-}
-impl Serialize for Command {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let mut state = serializer.serialize_struct("root", 1)?;
-        state.serialize_field("Command", &serde_json::json!({ "id": self.id.to_string() }))?;
-        state.end()
-    }
+    pub process_id: Ulid,
+    /// The name of the environment profile to run it with (see EnvProfile).
+    #[serde(default)]
+    pub profile: Option<String>,
 }
 
+/// A process was started.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Process {
     pub id: Ulid,
+    pub command_id: Ulid,
+}
+
+/// Cancel a running process. It is sent SIGINT, then SIGTERM, then
+/// SIGKILL, until it exits.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CancelProcess {
+    pub id: Ulid,
+}
+
+/// Run a workflow. Every message about the run is tagged with the
+/// run_id, which the client chooses, and the cancel and signal
+/// messages for the run_id apply to all of its running steps. Each
+/// step runs as a process of its own, with an id chosen by the server.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RunWorkflow {
+    pub name: String,
+    pub run_id: Ulid,
+    /// Referred to by the steps as `${params.NAME}`.
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+    /// The environment profile that every step runs with.
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+/// A workflow run was started.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct WorkflowRun {
+    pub id: Ulid,
+    pub workflow: Workflow,
+}
+
+/// A step of a workflow run changed state. Once it is Running, the
+/// messages about its process (tagged with the process_id) follow.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct WorkflowStepStatus {
+    pub id: Ulid,
+    pub step: String,
+    pub state: StepState,
+    #[serde(default)]
+    pub process_id: Option<Ulid>,
+}
+
+/// Every step of a workflow run has finished (or was skipped).
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct WorkflowComplete {
+    pub id: Ulid,
+    /// No step failed, and the run was not cancelled.
+    pub succeeded: bool,
+}
+
+/// The signals a client may send to a process (and all of its children).
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Display, AsRefStr, EnumIter,
+)]
+pub enum Signal {
+    #[serde(rename = "SIGINT")]
+    #[strum(serialize = "SIGINT")]
+    Interrupt,
+    #[serde(rename = "SIGTERM")]
+    #[strum(serialize = "SIGTERM")]
+    Terminate,
+    #[serde(rename = "SIGKILL")]
+    #[strum(serialize = "SIGKILL")]
+    Kill,
+}
+
+/// Send a signal to a running process, without cancelling it.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SignalProcess {
+    pub id: Ulid,
+    pub signal: Signal,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct ProcessComplete {
     pub id: Ulid,
+    /// The exit code, or 128 + the signal number if it was killed.
     pub code: i32,
+    /// The signal that killed the process, if any (eg. "SIGTERM").
+    #[serde(default)]
+    pub signal: Option<String>,
+    #[serde(default)]
+    pub reason: CompletionReason,
+    /// The outputs the script set (with `::set-output`, or in
+    /// $DRY_OUTPUT), with their secrets redacted.
+    #[serde(default)]
+    pub outputs: BTreeMap<String, String>,
+}
+
+/// Why a process stopped running.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum CompletionReason {
+    /// It exited by itself (successfully or not).
+    #[default]
+    Exited,
+    /// It was killed by a signal it was not expecting (eg. SIGTERM from
+    /// the client, or SIGKILL for using too much memory).
+    Killed,
+    Cancelled,
+    /// It ran for longer than its timeout, so it was cancelled.
+    TimedOut,
 }
 
+/// A chunk of output, exactly as the process wrote it. Chunks are sent
+/// as soon as they are read, so they may end in the middle of a line,
+/// or even in the middle of a UTF-8 character.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct ProcessOutput {
     pub stream: StreamType,
     pub id: Ulid,
-    pub line: String,
+    /// The time since the process started.
+    #[serde(
+        serialize_with = "serialize_duration_as_milliseconds",
+        deserialize_with = "deserialize_duration_from_milliseconds",
+        rename = "elapsed_ms"
+    )]
+    pub elapsed: Duration,
+    /// The position of the first byte of data in the stream.
+    pub offset: u64,
+    #[serde(
+        serialize_with = "serialize_bytes_as_base64",
+        deserialize_with = "deserialize_bytes_from_base64"
+    )]
+    pub data: Vec<u8>,
+}
+
+/// The process has not started yet: it is waiting in the run queue
+/// (see ScriptEntry::locks). This is sent again whenever its place
+/// changes, until the Process message says it has started.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessQueued {
+    pub id: Ulid,
+    /// 1 for the next run in the queue.
+    pub position: usize,
+    /// What it is waiting for (see QueuedRun::waiting_for).
+    pub waiting_for: Vec<String>,
+}
+
+/// The script set an output, with a line of its own on stdout:
+/// `::set-output name=NAME::value`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessSetOutput {
+    pub id: Ulid,
+    pub name: String,
+    pub value: String,
+}
+
+/// The script reported how far it has got: `::progress 40/100`, with
+/// an optional message (`::progress 40/100::Downloading ...`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessProgress {
+    pub id: Ulid,
+    pub current: u64,
+    pub total: u64,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// The output that follows belongs to a (collapsible) group, started
+/// with `::group::Title`, until the group is ended (with
+/// `::endgroup::`, when the title is None). Groups do not nest: a new
+/// group ends the one before it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessGroup {
+    pub id: Ulid,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Display, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum AnnotationLevel {
+    Warning,
+    Error,
+}
+
+/// The script annotated its output: `::warning::message`, or
+/// `::error title=Title::message`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessAnnotation {
+    pub id: Ulid,
+    pub level: AnnotationLevel,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub message: String,
+}
+
+/// The command was not run, because the workstation does not meet
+/// the requirements declared in the script header.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ProcessRejected {
+    pub id: Ulid,
+    pub command_id: Ulid,
+    pub unmet_requirements: Vec<UnmetRequirement>,
+}
+
+/// The process could not be started (eg. an unknown command, or a
+/// process_id that is already in use).
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ProcessError {
+    pub id: Ulid,
+    pub message: String,
+}
+
+/// Events pushed on the command socket that are not about any one
+/// process.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum ServerEvent {
+    SudoStatus(SudoStatus),
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 /// Enum of message types that the server may send to the client.
 pub enum ServerMsg {
+    Welcome(Welcome),
     Ping,
     Pong,
     PingReport(PingReport),
     Process(Process),
+    ProcessQueued(ProcessQueued),
     ProcessOutput(ProcessOutput),
+    ProcessSetOutput(ProcessSetOutput),
+    ProcessProgress(ProcessProgress),
+    ProcessGroup(ProcessGroup),
+    ProcessAnnotation(ProcessAnnotation),
     ProcessComplete(ProcessComplete),
+    ProcessRejected(ProcessRejected),
+    ProcessError(ProcessError),
+    Workflow(WorkflowRun),
+    WorkflowStep(WorkflowStepStatus),
+    WorkflowComplete(WorkflowComplete),
+    Event(ServerEvent),
+}
+
+impl ServerMsg {
+    /// The process this message is about, if any.
+    pub fn process_id(&self) -> Option<Ulid> {
+        match self {
+            ServerMsg::Process(p) => Some(p.id),
+            ServerMsg::ProcessQueued(q) => Some(q.id),
+            ServerMsg::ProcessOutput(o) => Some(o.id),
+            ServerMsg::ProcessSetOutput(o) => Some(o.id),
+            ServerMsg::ProcessProgress(p) => Some(p.id),
+            ServerMsg::ProcessGroup(g) => Some(g.id),
+            ServerMsg::ProcessAnnotation(a) => Some(a.id),
+            ServerMsg::ProcessComplete(c) => Some(c.id),
+            ServerMsg::ProcessRejected(r) => Some(r.id),
+            ServerMsg::ProcessError(e) => Some(e.id),
+            ServerMsg::Workflow(w) => Some(w.id),
+            ServerMsg::WorkflowStep(s) => Some(s.id),
+            ServerMsg::WorkflowComplete(c) => Some(c.id),
+            _ => None,
+        }
+    }
+
+    /// Is this the last message about its process?
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            ServerMsg::ProcessComplete(_)
+                | ServerMsg::ProcessRejected(_)
+                | ServerMsg::ProcessError(_)
+                | ServerMsg::WorkflowComplete(_)
+        )
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 /// Enum of message types that the client may send to the server.
 pub enum ClientMsg {
+    Hello(Hello),
     Command(Command),
-    Cancel,
+    Workflow(RunWorkflow),
+    Cancel(CancelProcess),
+    Signal(SignalProcess),
+    Ping,
+    Pong,
+    PingReport(PingReport),
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+/// Enum of message types that the client may send on a socket that
+/// only pushes updates from the server (eg. logs, sudo status).
+pub enum SubscriberMsg {
     Ping,
     Pong,
     PingReport(PingReport),
@@ -86,6 +379,21 @@ where
     Ok(Duration::from_millis(millis))
 }
 
+fn serialize_bytes_as_base64<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&BASE64.encode(bytes))
+}
+
+fn deserialize_bytes_from_base64<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let encoded = String::deserialize(deserializer)?;
+    BASE64.decode(encoded).map_err(serde::de::Error::custom)
+}
+
 /// Exit codes for websocket connections
 /// https://www.rfc-editor.org/rfc/rfc6455.html#section-7.4.1
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive)]
@@ -126,7 +434,15 @@ impl WebSocketMessage for ClientMsg {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+impl WebSocketMessage for SubscriberMsg {
+    const PING: Self = SubscriberMsg::Ping;
+    const PONG: Self = SubscriberMsg::Pong;
+    fn ping_report(duration: Duration) -> Self {
+        SubscriberMsg::PingReport(PingReport { duration })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum StreamType {
     Stdout,
     Stderr,
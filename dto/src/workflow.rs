@@ -0,0 +1,330 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use utoipa::ToSchema;
+
+/// A series of library scripts, run as one. Each step waits for the
+/// steps listed in its `after` (or else the step before it), so the
+/// steps form a DAG, and independent steps run at the same time:
+///
+///   {
+///     "title": "Install d.rymcg.tech",
+///     "steps": [
+///       {"id": "dependencies", "script": "InstallDependencies"},
+///       {"id": "install", "script": "InstallDRymcgTech",
+///        "params": {"ROOT_DIR": "${steps.dependencies.ROOT_DIR}"}}
+///     ]
+///   }
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct Workflow {
+    /// Filled in by the server, from the name of the definition file.
+    #[serde(default)]
+    pub name: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<WorkflowStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq)]
+pub struct WorkflowStep {
+    pub id: String,
+    /// The name of the library script, eg. `InstallDependencies`.
+    pub script: String,
+    /// The steps that must finish first. When this is not given, the
+    /// step waits for the step before it (if any).
+    #[serde(default)]
+    pub after: Option<Vec<String>>,
+    #[serde(default)]
+    pub when: StepCondition,
+    /// Environment variables passed to the script. Values may refer to
+    /// the parameters of the run (`${params.NAME}`), or to the outputs
+    /// of an earlier step (`${steps.ID.NAME}`).
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+/// When a step runs, depending on the outcome of the steps it waits for.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StepCondition {
+    /// Only if they all succeeded.
+    #[default]
+    OnSuccess,
+    /// Only if any of them failed.
+    OnFailure,
+    /// Whatever happened to them (unless the run was cancelled).
+    Always,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Eq)]
+pub enum StepState {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    /// Its condition was not met, or the run was cancelled.
+    Skipped,
+}
+
+impl StepState {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, StepState::Pending | StepState::Running)
+    }
+}
+
+impl StepCondition {
+    /// Should the step run, given the (finished) states of the steps
+    /// it waits for?
+    pub fn is_met(&self, dependencies: &[StepState]) -> bool {
+        match self {
+            StepCondition::OnSuccess => dependencies.iter().all(|s| *s == StepState::Succeeded),
+            StepCondition::OnFailure => dependencies.contains(&StepState::Failed),
+            StepCondition::Always => true,
+        }
+    }
+}
+
+impl Workflow {
+    /// The ids of the steps that the step at index waits for.
+    pub fn dependencies(&self, index: usize) -> Vec<&str> {
+        match &self.steps[index].after {
+            Some(after) => after.iter().map(String::as_str).collect(),
+            None if index > 0 => vec![self.steps[index - 1].id.as_str()],
+            None => Vec::new(),
+        }
+    }
+
+    /// The ids of every step that must finish before the step at index
+    /// (the steps it waits for, and the steps they wait for, etc.)
+    pub fn ancestors(&self, index: usize) -> HashSet<&str> {
+        let mut ancestors = HashSet::new();
+        let mut queue = self.dependencies(index);
+        while let Some(id) = queue.pop() {
+            if ancestors.insert(id) {
+                if let Some(i) = self.steps.iter().position(|s| s.id == id) {
+                    queue.extend(self.dependencies(i));
+                }
+            }
+        }
+        ancestors
+    }
+
+    /// Check that the step ids are unique, that every step waits only
+    /// for steps that exist, that there are no cycles, and that every
+    /// `${steps.ID.NAME}` refers to a step that finishes first.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut ids = HashSet::new();
+        for step in &self.steps {
+            if !ids.insert(step.id.as_str()) {
+                return Err(format!("Duplicate step id: {}", step.id));
+            }
+        }
+        for (index, step) in self.steps.iter().enumerate() {
+            if let Some(missing) = self
+                .dependencies(index)
+                .into_iter()
+                .find(|d| !ids.contains(d))
+            {
+                return Err(format!("Step {} waits for unknown step {missing}", step.id));
+            }
+            let ancestors = self.ancestors(index);
+            if ancestors.contains(step.id.as_str()) {
+                return Err(format!("Step {} waits for itself", step.id));
+            }
+            for value in step.params.values() {
+                for reference in references(value) {
+                    if let Some((id, _)) = reference
+                        .strip_prefix("steps.")
+                        .and_then(|r| r.split_once('.'))
+                    {
+                        if !ancestors.contains(id) {
+                            return Err(format!(
+                                "Step {} uses the outputs of step {id}, which does not finish before it",
+                                step.id
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `${...}` references in a parameter value.
+fn references(value: &str) -> Vec<&str> {
+    let mut references = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        match rest[start + 2..].find('}') {
+            Some(end) => {
+                references.push(&rest[start + 2..start + 2 + end]);
+                rest = &rest[start + 2 + end + 1..];
+            }
+            None => break,
+        }
+    }
+    references
+}
+
+/// Replace every `${...}` reference in a parameter value with its value,
+/// from the parameters of the run and the outputs of the earlier steps.
+/// Anything unknown is replaced with nothing, as in the shell.
+pub fn substitute(
+    value: &str,
+    params: &BTreeMap<String, String>,
+    outputs: &BTreeMap<String, BTreeMap<String, String>>,
+) -> String {
+    let mut substituted = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start + 2..].find('}') else {
+            break;
+        };
+        substituted.push_str(&rest[..start]);
+        let reference = &rest[start + 2..start + 2 + end];
+        let resolved = match reference.split_once('.') {
+            Some(("params", name)) => params.get(name),
+            Some(("steps", path)) => path
+                .split_once('.')
+                .and_then(|(id, name)| outputs.get(id)?.get(name)),
+            _ => None,
+        };
+        substituted.push_str(resolved.map(String::as_str).unwrap_or_default());
+        rest = &rest[start + 2 + end + 1..];
+    }
+    substituted.push_str(rest);
+    substituted
+}
+
+/// Parse the outputs a step wrote to `$DRY_OUTPUT`, one `NAME=value`
+/// per line.
+pub fn parse_outputs(source: &str) -> BTreeMap<String, String> {
+    source
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .filter(|(name, _)| !name.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(id: &str, after: Option<&[&str]>, params: &[(&str, &str)]) -> WorkflowStep {
+        WorkflowStep {
+            id: id.to_string(),
+            script: "TestExampleOne".to_string(),
+            after: after.map(|a| a.iter().map(|s| s.to_string()).collect()),
+            when: StepCondition::default(),
+            params: params
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    fn workflow(steps: Vec<WorkflowStep>) -> Workflow {
+        Workflow {
+            name: "test".to_string(),
+            title: "Test".to_string(),
+            description: String::new(),
+            steps,
+        }
+    }
+
+    #[test]
+    fn steps_wait_for_the_one_before_them() {
+        let w = workflow(vec![
+            step("a", None, &[]),
+            step("b", None, &[]),
+            step("c", Some(&[]), &[]),
+        ]);
+        assert!(w.dependencies(0).is_empty());
+        assert_eq!(w.dependencies(1), vec!["a"]);
+        assert!(w.dependencies(2).is_empty());
+        assert!(w.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_duplicate_and_unknown_steps() {
+        let duplicate = workflow(vec![step("a", None, &[]), step("a", None, &[])]);
+        assert!(duplicate.validate().unwrap_err().contains("Duplicate"));
+        let unknown = workflow(vec![step("a", Some(&["nope"]), &[])]);
+        assert!(unknown
+            .validate()
+            .unwrap_err()
+            .contains("unknown step nope"));
+    }
+
+    #[test]
+    fn validate_rejects_cycles() {
+        let w = workflow(vec![
+            step("a", Some(&["c"]), &[]),
+            step("b", Some(&["a"]), &[]),
+            step("c", Some(&["b"]), &[]),
+        ]);
+        assert!(w.validate().unwrap_err().contains("waits for itself"));
+        let itself = workflow(vec![step("a", Some(&["a"]), &[])]);
+        assert!(itself.validate().is_err());
+    }
+
+    #[test]
+    fn validate_checks_that_outputs_are_ready() {
+        let ready = workflow(vec![
+            step("a", None, &[]),
+            step("b", Some(&[]), &[]),
+            step("c", Some(&["a"]), &[("X", "${steps.a.OUT}")]),
+        ]);
+        assert!(ready.validate().is_ok());
+        let unordered = workflow(vec![
+            step("a", None, &[]),
+            step("b", Some(&[]), &[]),
+            step("c", Some(&["a"]), &[("X", "${steps.b.OUT}")]),
+        ]);
+        assert!(unordered.validate().unwrap_err().contains("step b"));
+    }
+
+    #[test]
+    fn substitute_params_and_outputs() {
+        let params = BTreeMap::from([("NAME".to_string(), "world".to_string())]);
+        let outputs = BTreeMap::from([(
+            "a".to_string(),
+            BTreeMap::from([("DIR".to_string(), "/tmp/x".to_string())]),
+        )]);
+        assert_eq!(
+            substitute("hello ${params.NAME} in ${steps.a.DIR}/", &params, &outputs),
+            "hello world in /tmp/x/"
+        );
+        assert_eq!(substitute("plain", &params, &outputs), "plain");
+    }
+
+    #[test]
+    fn substitute_unknown_references_with_nothing() {
+        let params = BTreeMap::new();
+        let outputs = BTreeMap::new();
+        assert_eq!(
+            substitute(
+                "[${params.NOPE}][${steps.a.B}][${other}]",
+                &params,
+                &outputs
+            ),
+            "[][][]"
+        );
+        // An unterminated reference is left as it is:
+        assert_eq!(
+            substitute("x ${params.A", &params, &outputs),
+            "x ${params.A"
+        );
+    }
+
+    #[test]
+    fn outputs_are_name_value_lines() {
+        let outputs = parse_outputs("A=1\nB = two=2\n=nameless\njunk\n");
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs["A"], "1");
+        assert_eq!(outputs["B"], " two=2");
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use strum::Display;
 use utoipa::ToSchema;
 
@@ -100,3 +101,71 @@ impl Default for Platform {
         }
     }
 }
+
+/// The facts about the workstation that script templates are rendered
+/// with (see ScriptEntry::template), eg:
+///
+///   #% if package_manager == "dnf"
+///   $DRY_SUDO dnf install -y {{ missing_packages | quote }}
+///   #% endif
+///
+/// They are not quoted for the shell, unless with the `quote` filter.
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, ToSchema, Clone)]
+pub struct WorkstationFacts {
+    /// linux, wsl2, macos or unknown.
+    pub os_type: String,
+    /// fedora, arch, debian, ubuntu or unsupported.
+    pub distribution: String,
+    /// The version of the distribution (eg. 40).
+    pub release: String,
+    /// The variant of the distribution (eg. silverblue), if any.
+    pub variant: String,
+    /// The version of the kernel.
+    pub kernel: String,
+    /// dnf, apt, pacman or apk, if one was found.
+    pub package_manager: Option<String>,
+    pub hostname: String,
+    pub user: String,
+    pub uid: u32,
+    pub home: String,
+    /// Have root privileges been acquired?
+    pub sudo: bool,
+    /// The program that acquires them (eg. sudo or doas), if any.
+    pub escalator: Option<String>,
+    /// Is the server running in a toolbox (or distrobox) container?
+    pub toolbox: bool,
+    /// The dependencies of dry_console, by name.
+    pub dependencies: BTreeMap<String, DependencyFact>,
+    /// The names of the dependencies that are not installed.
+    pub missing: Vec<String>,
+    /// The packages to install them with, using package_manager.
+    pub missing_packages: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, ToSchema, Clone)]
+pub struct DependencyFact {
+    pub installed: bool,
+    /// Where it is installed (empty, if it is not).
+    pub path: String,
+}
+
+/// A git checkout of d.rymcg.tech.
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, ToSchema, Clone)]
+pub struct DRymcgTechCheckout {
+    pub path: String,
+    /// The URL of the `origin` remote (None, if there isn't one).
+    pub remote: Option<String>,
+    /// None, if HEAD is detached (eg. a tag or a commit was checked out).
+    pub branch: Option<String>,
+    pub commit: String,
+    /// The `d.rymcg.tech` CLI that is on the PATH (None, if there isn't one).
+    pub cli: Option<String>,
+}
+
+/// Where d.rymcg.tech is installed (ROOT_DIR, see the InstallDRymcgTech
+/// script), and the checkout that is there, if it is installed.
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, ToSchema, Clone)]
+pub struct DRymcgTechInstall {
+    pub root_dir: String,
+    pub checkout: Option<DRymcgTechCheckout>,
+}
@@ -0,0 +1,20 @@
+use gloo_utils::document;
+use web_sys::Url;
+
+/// The path prefix the server is mounted under (eg. `/alice`), or an
+/// empty string when it is served from the root. The server writes
+/// this into the `<base>` element of index.html (see `--base-path`).
+pub fn base_path() -> String {
+    document()
+        .base_uri()
+        .ok()
+        .flatten()
+        .and_then(|uri| Url::new(&uri).ok())
+        .map(|url| url.pathname().trim_end_matches('/').to_string())
+        .unwrap_or_default()
+}
+
+/// Prefix an absolute path (eg. `/api/session/`) with the base path.
+pub fn url(path: &str) -> String {
+    format!("{}{path}", base_path())
+}
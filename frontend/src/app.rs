@@ -1,7 +1,12 @@
+use crate::api;
 use crate::components::logout;
+use crate::components::sudo::SudoStatusIndicator;
+use crate::components::version::VersionCheck;
 use crate::components::ButtonLink;
-use crate::pages::{apps, login, routes, workstation};
+use crate::pages::{apps, login, logs, profiles, routes, schedules, secrets, workstation};
+use crate::websocket::{use_event, EventBus, EventSubscription, ProcessSocket};
 use anyhow::{anyhow, Error};
+use dry_console_dto::events::Event;
 pub use dry_console_dto::session::SessionState;
 use gloo_events::EventListener;
 use gloo_net::http::Request;
@@ -19,6 +24,10 @@ enum TopMenuChoices {
     Workstation,
     Apps,
     Routes,
+    Schedules,
+    Profiles,
+    Secrets,
+    Logs,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Target, EnumIter, Display)]
@@ -27,6 +36,10 @@ pub enum AppRoute {
     Workstation,
     Apps,
     Routes,
+    Schedules,
+    Profiles,
+    Secrets,
+    Logs,
     Login,
 }
 
@@ -37,6 +50,10 @@ impl From<AppRoute> for &'static str {
             AppRoute::Workstation => "Workstation",
             AppRoute::Apps => "Apps",
             AppRoute::Routes => "Routes",
+            AppRoute::Schedules => "Schedules",
+            AppRoute::Profiles => "Profiles",
+            AppRoute::Secrets => "Secrets",
+            AppRoute::Logs => "Logs",
         }
     }
 }
@@ -69,7 +86,7 @@ struct RedirectProps {
 }
 
 async fn check_session_state() -> Result<SessionState, Error> {
-    let response = Request::get("/api/session").send().await?;
+    let response = Request::get(&api::url("/api/session/")).send().await?;
 
     match response.status() {
         200 => {
@@ -80,6 +97,30 @@ async fn check_session_state() -> Result<SessionState, Error> {
     }
 }
 
+#[derive(Properties, PartialEq)]
+struct SessionEventsProps {
+    session_state: UseStateHandle<SessionState>,
+}
+
+/// Keeps the session state up to date, as logins are enabled (or
+/// disabled) and the session is revoked, eg. from another tab.
+#[function_component(SessionEvents)]
+fn session_events(props: &SessionEventsProps) -> Html {
+    let session_state = props.session_state.clone();
+    use_event(Callback::from(move |event| match event {
+        Event::SessionRevoked(_) => session_state.set(SessionState {
+            logged_in: false,
+            ..(*session_state).clone()
+        }),
+        Event::LoginAllowed(new_login_allowed) => session_state.set(SessionState {
+            new_login_allowed,
+            ..(*session_state).clone()
+        }),
+        _ => {}
+    }));
+    html! {}
+}
+
 #[derive(Clone, PartialEq)]
 pub struct WindowDimensions {
     pub width: f64,
@@ -132,6 +173,11 @@ pub fn app() -> Html {
         height: *height_handle,
     };
 
+    // Every terminal runs its processes on this one socket:
+    let process_socket = use_memo((), |_| ProcessSocket::default());
+    // Every component that follows the server events shares this one:
+    let event_bus = use_memo((), |_| EventBus::default());
+
     let session_state = use_state(SessionState::default);
     let checking_session = use_state(|| true);
 
@@ -153,8 +199,16 @@ pub fn app() -> Html {
             || ()
         });
     }
+    let logged_in = session_state.logged_in;
+    let events_session_state = session_state.clone();
     html! {
         <ContextProvider<WindowDimensions> context={screen_dimensions}>
+        <ContextProvider<ProcessSocket> context={(*process_socket).clone()}>
+        <ContextProvider<EventBus> context={(*event_bus).clone()}>
+        if logged_in {
+            <EventSubscription />
+            <SessionEvents session_state={events_session_state} />
+        }
         <BackdropViewer>
             <ToastViewer>
                 <Router<AppRoute> default={AppRoute::Workstation}>
@@ -171,6 +225,8 @@ pub fn app() -> Html {
                 </Router<AppRoute>>
             </ToastViewer>
         </BackdropViewer>
+        </ContextProvider<EventBus>>
+        </ContextProvider<ProcessSocket>>
         </ContextProvider<WindowDimensions>>
     }
 }
@@ -189,6 +245,18 @@ fn switch_app_route(target: AppRoute, session_state: UseStateHandle<SessionState
         AppRoute::Routes => {
             html! {<AppPage {session_state}><routes::Routes/></AppPage>}
         }
+        AppRoute::Schedules => {
+            html! {<AppPage {session_state}><schedules::Schedules/></AppPage>}
+        }
+        AppRoute::Profiles => {
+            html! {<AppPage {session_state}><profiles::Profiles/></AppPage>}
+        }
+        AppRoute::Secrets => {
+            html! {<AppPage {session_state}><secrets::Secrets/></AppPage>}
+        }
+        AppRoute::Logs => {
+            html! {<AppPage {session_state}><logs::Logs/></AppPage>}
+        }
     }
 }
 
@@ -209,6 +277,10 @@ fn top_bar_menu() -> Html {
             AppRoute::Workstation => Some(TopMenuChoices::Workstation),
             AppRoute::Apps => Some(TopMenuChoices::Apps),
             AppRoute::Routes => Some(TopMenuChoices::Routes),
+            AppRoute::Schedules => Some(TopMenuChoices::Schedules),
+            AppRoute::Profiles => Some(TopMenuChoices::Profiles),
+            AppRoute::Secrets => Some(TopMenuChoices::Secrets),
+            AppRoute::Logs => Some(TopMenuChoices::Logs),
             #[allow(unreachable_patterns)]
             _ => None,
         },
@@ -222,6 +294,10 @@ fn top_bar_menu() -> Html {
                 TopMenuChoices::Workstation => AppRoute::Workstation,
                 TopMenuChoices::Apps => AppRoute::Apps,
                 TopMenuChoices::Routes => AppRoute::Routes,
+                TopMenuChoices::Schedules => AppRoute::Schedules,
+                TopMenuChoices::Profiles => AppRoute::Profiles,
+                TopMenuChoices::Secrets => AppRoute::Secrets,
+                TopMenuChoices::Logs => AppRoute::Logs,
             };
             navigator.push(route); // This will navigate and trigger a re-render
         })
@@ -247,6 +323,30 @@ fn top_bar_menu() -> Html {
                 onchange={let cb = callback.clone(); move |_| { cb.emit(TopMenuChoices::Routes);  }}
                 selected={*selected == Some(TopMenuChoices::Routes)}
             />
+            <ToggleGroupItem
+                text="Schedules"
+                key=3
+                onchange={let cb = callback.clone(); move |_| { cb.emit(TopMenuChoices::Schedules);  }}
+                selected={*selected == Some(TopMenuChoices::Schedules)}
+            />
+            <ToggleGroupItem
+                text="Profiles"
+                key=4
+                onchange={let cb = callback.clone(); move |_| { cb.emit(TopMenuChoices::Profiles);  }}
+                selected={*selected == Some(TopMenuChoices::Profiles)}
+            />
+            <ToggleGroupItem
+                text="Secrets"
+                key=5
+                onchange={let cb = callback.clone(); move |_| { cb.emit(TopMenuChoices::Secrets);  }}
+                selected={*selected == Some(TopMenuChoices::Secrets)}
+            />
+            <ToggleGroupItem
+                text="Logs"
+                key=6
+                onchange={let cb = callback.clone(); move |_| { cb.emit(TopMenuChoices::Logs);  }}
+                selected={*selected == Some(TopMenuChoices::Logs)}
+            />
         </ToggleGroup>
     }
 }
@@ -290,7 +390,7 @@ fn sidebar(
 #[function_component(AppPage)]
 fn page(props: &AppPageProps) -> Html {
     //log::debug!("rendering page");
-    let brand = html! { <a href="/">{"dry_console"}</a> };
+    let brand = html! { <a href={api::url("/")}>{"dry_console"}</a> };
 
     let darkmode = use_state_eq(|| {
         if let Ok(storage) = gloo_storage::LocalStorage::get("dark_mode") {
@@ -361,7 +461,7 @@ fn page(props: &AppPageProps) -> Html {
                     variant={GroupVariant::IconButton}
              >
              { if props.session_state.logged_in {
-                 html! { <TopBarMenu /> }
+                 html! { <><VersionCheck /><SudoStatusIndicator /><TopBarMenu /></> }
              } else {
                  html! { }
              }}
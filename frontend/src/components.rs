@@ -6,3 +6,8 @@ pub mod color_picker;
 pub mod loading_state;
 pub mod manual_intervention;
 pub mod markdown;
+pub mod profile_select;
+pub mod script_review;
+pub mod sudo;
+pub mod version;
+pub mod workflow;
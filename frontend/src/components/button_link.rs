@@ -1,3 +1,4 @@
+use crate::api;
 use gloo_utils::window;
 use patternfly_yew::prelude::*;
 use yew::prelude::*;
@@ -15,16 +16,21 @@ pub struct ButtonLinkProps {
 
 #[function_component(ButtonLink)]
 pub fn button_link(props: &ButtonLinkProps) -> Html {
-    let href = props.href.clone();
+    // Internal links are relative to the base path:
+    let href = if props.href.starts_with('/') {
+        api::url(&props.href)
+    } else {
+        props.href.clone()
+    };
     let target = props.target.clone().unwrap_or_else(|| "_self".into());
 
     let onclick = Callback::from(move |e: MouseEvent| {
         e.prevent_default();
         let _ = window()
             .open_with_url_and_target(&href, &target)
-            .expect(&format!(
-                "failed to open url with target: href:{href} target:{target}"
-            ));
+            .unwrap_or_else(|_| {
+                panic!("failed to open url with target: href:{href} target:{target}")
+            });
     });
 
     html! {
@@ -1,3 +1,4 @@
+use crate::api;
 use crate::app::{AppRoute, SessionState};
 use gloo_net::http::Request;
 use patternfly_yew::prelude::*;
@@ -48,7 +49,9 @@ pub fn logout(props: &LogoutProps) -> Html {
             let router = Rc::clone(&router);
 
             wasm_bindgen_futures::spawn_local(async move {
-                let response = Request::post("/api/session/logout/").send().await;
+                let response = Request::post(&api::url("/api/session/logout/"))
+                    .send()
+                    .await;
 
                 match response {
                     Ok(res) if res.ok() => {
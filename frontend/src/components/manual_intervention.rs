@@ -1,3 +1,4 @@
+use crate::api;
 use crate::components::loading_state::LoadingState;
 use crate::pages::workstation::WorkstationTab;
 use dry_console_dto::script::ScriptEntry;
@@ -35,7 +36,7 @@ pub fn manual_intervention(props: &ManualInterventionProps) -> Html {
 
         use_effect_with(reload_trigger, move |_| {
             wasm_bindgen_futures::spawn_local(async move {
-                let url = format!("/api/workstation/command/{}", script);
+                let url = api::url(&format!("/api/workstation/command/{}", script));
                 let response = match gloo_net::http::Request::get(&url).send().await {
                     Ok(response) => response,
                     Err(_) => {
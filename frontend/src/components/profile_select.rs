@@ -0,0 +1,81 @@
+use crate::api;
+use dry_console_dto::profile::EnvProfile;
+use gloo::net::http::Request;
+use gloo_storage::{LocalStorage, Storage};
+use patternfly_yew::prelude::*;
+use yew::prelude::*;
+
+/// The profile that terminals and workflows run with, as last picked:
+const PROFILE_LOCALSTORAGE_KEY: &str = "run:profile";
+
+pub async fn fetch_profiles() -> Result<Vec<EnvProfile>, String> {
+    let response = Request::get(&api::url("/api/profiles/"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.ok() {
+        true => response.json().await.map_err(|e| e.to_string()),
+        false => Err(response.status_text()),
+    }
+}
+
+/// The profile that was last picked.
+pub fn stored_profile() -> Option<String> {
+    LocalStorage::get::<Option<String>>(PROFILE_LOCALSTORAGE_KEY)
+        .ok()
+        .flatten()
+}
+
+/// The environment profile to run with (None for the server's own
+/// environment), remembered across page loads.
+#[hook]
+pub fn use_profile() -> UseStateHandle<Option<String>> {
+    let profile = use_state(stored_profile);
+    use_effect_with((*profile).clone(), |profile| {
+        LocalStorage::set(PROFILE_LOCALSTORAGE_KEY, profile)
+            .expect("Failed to store setting in local storage");
+    });
+    profile
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ProfileSelectProps {
+    pub selected: Option<String>,
+    pub onchange: Callback<Option<String>>,
+}
+
+/// Picks the environment profile that a script runs with.
+#[function_component(ProfileSelect)]
+pub fn profile_select(props: &ProfileSelectProps) -> Html {
+    let profiles = use_state(Vec::<EnvProfile>::new);
+    {
+        let profiles = profiles.clone();
+        use_effect_with((), move |_| {
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Ok(fetched) = fetch_profiles().await {
+                    profiles.set(fetched);
+                }
+            });
+        });
+    }
+    if profiles.is_empty() && props.selected.is_none() {
+        return html! {};
+    }
+    let text = match &props.selected {
+        Some(name) => format!("Profile: {name}"),
+        None => "Profile: (none)".to_string(),
+    };
+    html! {
+        <Dropdown {text}>
+            <MenuAction onclick={props.onchange.reform(|_| None)}>{"(none)"}</MenuAction>
+            { for profiles.iter().map(|profile| {
+                let name = profile.name.clone();
+                html_nested! {
+                    <MenuAction onclick={props.onchange.reform(move |_| Some(name.clone()))}>
+                        { &profile.name }
+                    </MenuAction>
+                }
+            }) }
+        </Dropdown>
+    }
+}
@@ -0,0 +1,115 @@
+use crate::api;
+use crate::components::loading_state::LoadingState;
+use dry_console_dto::trust::{DiffLine, ScriptApproval, ScriptReview};
+use gloo::net::http::Request;
+use patternfly_yew::prelude::*;
+use yew::prelude::*;
+
+async fn fetch_review(command: &str) -> Result<ScriptReview, String> {
+    let url = api::url(&format!("/api/workstation/command/{command}/review/"));
+    let response = Request::get(&url).send().await.map_err(|e| e.to_string())?;
+    match response.ok() {
+        true => response.json().await.map_err(|e| e.to_string()),
+        false => Err(response.status_text()),
+    }
+}
+
+async fn post_approval(command: &str, approval: ScriptApproval) -> Result<(), String> {
+    let url = api::url(&format!("/api/workstation/command/{command}/approve/"));
+    let response = Request::post(&url)
+        .json(&approval)
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.ok() {
+        true => Ok(()),
+        false => Err(response
+            .text()
+            .await
+            .unwrap_or_else(|_| response.status_text())),
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ScriptReviewProps {
+    /// The name of the library script, eg. `InstallDependencies`.
+    pub command: AttrValue,
+    pub on_approved: Callback<()>,
+}
+
+/// Shows a script that is not builtin, as changed since it was last
+/// approved, and asks the user to approve it before it can be run.
+#[function_component(ScriptReviewPanel)]
+pub fn script_review_panel(props: &ScriptReviewProps) -> Html {
+    let review = use_state(|| None::<Result<ScriptReview, String>>);
+    let error = use_state(|| None::<String>);
+
+    {
+        let review = review.clone();
+        use_effect_with(props.command.clone(), move |command| {
+            let command = command.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                review.set(Some(fetch_review(&command).await));
+            });
+        });
+    }
+
+    let review = match &*review {
+        None => return html! { <LoadingState /> },
+        Some(Err(e)) => {
+            return html! {
+                <Alert inline=true r#type={AlertType::Danger} title={format!("Could not review the script: {e}")} />
+            }
+        }
+        Some(Ok(review)) => review.clone(),
+    };
+
+    let onapprove = {
+        let command = props.command.clone();
+        let on_approved = props.on_approved.clone();
+        let error = error.clone();
+        let approval = ScriptApproval { id: review.id };
+        Callback::from(move |_: MouseEvent| {
+            let command = command.clone();
+            let on_approved = on_approved.clone();
+            let error = error.clone();
+            let approval = approval.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match post_approval(&command, approval).await {
+                    Ok(()) => on_approved.emit(()),
+                    Err(e) => error.set(Some(e)),
+                }
+            });
+        })
+    };
+
+    let title = match review.approved_id {
+        Some(_) => "This script has changed since it was approved",
+        None => "This script has not been approved",
+    };
+    html! {
+        <Alert inline=true r#type={AlertType::Warning} {title}>
+            <p>
+                {"It is not one of the builtin scripts, so it will not be run until you approve this version of it ("}
+                <code>{review.id.to_string()}</code>{"). "}
+                if review.approved_id.is_some() {
+                    {"The changes from the version you approved before are:"}
+                } else {
+                    {"Its source is:"}
+                }
+            </p>
+            <pre class="script-diff">
+                { for review.diff.iter().map(|line| match line {
+                    DiffLine::Same(text) => html! { <div>{format!("  {text}")}</div> },
+                    DiffLine::Added(text) => html! { <div class="diff-added">{format!("+ {text}")}</div> },
+                    DiffLine::Removed(text) => html! { <div class="diff-removed">{format!("- {text}")}</div> },
+                }) }
+            </pre>
+            if let Some(e) = &*error {
+                <Alert inline=true r#type={AlertType::Danger} title={format!("Could not approve the script: {e}")} />
+            }
+            <Button label="Approve this version" variant={ButtonVariant::Warning} onclick={onapprove} />
+        </Alert>
+    }
+}
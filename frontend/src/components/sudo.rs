@@ -0,0 +1,228 @@
+use crate::api;
+use crate::websocket::use_subscription;
+use dry_console_dto::sudo::{SudoPassword, SudoServerMsg, SudoState, SudoStatus};
+use gloo_net::http::Request;
+use patternfly_yew::prelude::*;
+use std::time::Duration;
+use web_sys::SubmitEvent;
+use yew::prelude::*;
+
+/// POST to one of the /api/sudo/ endpoints, returning the server's
+/// error message on failure.
+async fn post(path: &str, password: Option<SudoPassword>) -> Result<(), String> {
+    let request = Request::post(&api::url(path));
+    let response = match password {
+        Some(password) => {
+            request
+                .json(&password)
+                .map_err(|e| e.to_string())?
+                .send()
+                .await
+        }
+        None => request.send().await,
+    }
+    .map_err(|e| e.to_string())?;
+    match response.ok() {
+        true => Ok(()),
+        false => Err(response
+            .text()
+            .await
+            .unwrap_or_else(|_| response.status_text())),
+    }
+}
+
+fn state_label(status: &SudoStatus) -> (Color, String) {
+    let name = status
+        .escalator
+        .map(|e| e.to_string())
+        .unwrap_or_else(|| "sudo".to_string());
+    match status.state {
+        SudoState::Disabled => (Color::Grey, format!("{name} off")),
+        SudoState::Active => (Color::Green, name),
+        SudoState::Expired => (Color::Orange, format!("{name} expired")),
+        SudoState::Authenticating => (Color::Blue, format!("{name} ...")),
+        SudoState::AwaitingPassword => (Color::Blue, format!("{name} password")),
+    }
+}
+
+/// Shows the sudo status in the top bar, and asks for the password
+/// whenever the server is waiting for it.
+#[function_component(SudoStatusIndicator)]
+pub fn sudo_status_indicator() -> Html {
+    let toaster = use_toaster().expect("Must be nested inside a ToastViewer");
+    let backdrop = use_backdrop();
+    let status = use_state_eq(|| None::<SudoStatus>);
+    // Did this component open the password modal?
+    let prompting = use_mut_ref(|| false);
+
+    use_subscription(
+        "/api/sudo/status/",
+        {
+            let status = status.clone();
+            Callback::from(move |connected: bool| {
+                if !connected {
+                    status.set(None);
+                }
+            })
+        },
+        {
+            let status = status.clone();
+            Callback::from(move |msg| {
+                if let SudoServerMsg::Status(s) = msg {
+                    status.set(Some(s));
+                }
+            })
+        },
+    );
+
+    // Open the password modal when sudo asks, and close it once
+    // sudo has stopped waiting (eg. the password was entered in another tab):
+    {
+        let backdrop = backdrop.clone();
+        use_effect_with((*status).clone(), move |status| {
+            if let Some(backdrop) = backdrop {
+                match status {
+                    Some(s) if s.state == SudoState::AwaitingPassword => {
+                        *prompting.borrow_mut() = true;
+                        backdrop.open(Backdrop::new(html! {
+                            <SudoPasswordModal message={s.message.clone()} />
+                        }));
+                    }
+                    _ if *prompting.borrow() => {
+                        *prompting.borrow_mut() = false;
+                        backdrop.close();
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    let onacquire = Callback::from(move |_| {
+        let toaster = toaster.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = post("/api/sudo/acquire/", None).await {
+                toaster.toast(Toast {
+                    title: format!("Could not authenticate sudo: {e}"),
+                    timeout: Some(Duration::from_secs(5)),
+                    r#type: AlertType::Danger,
+                    ..Default::default()
+                });
+            }
+        });
+    });
+
+    match &*status {
+        None => html! {},
+        Some(s) => {
+            let (color, label) = state_label(s);
+            let can_acquire =
+                s.can_acquire && matches!(s.state, SudoState::Disabled | SudoState::Expired);
+            html! {
+                <>
+                    <Tooltip text={s.message.clone().unwrap_or_else(|| format!("{:?}", s.state))}>
+                        <Label {color} {label} />
+                    </Tooltip>
+                    if can_acquire {
+                        <Button
+                            variant={ButtonVariant::Link}
+                            label="Authenticate sudo"
+                            onclick={onacquire}
+                        />
+                    }
+                </>
+            }
+        }
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SudoPasswordModalProps {
+    /// Shown above the password field, eg. "Sorry, try again."
+    pub message: Option<String>,
+}
+
+#[function_component(SudoPasswordModal)]
+pub fn sudo_password_modal(props: &SudoPasswordModalProps) -> Html {
+    let password = use_state(String::new);
+    let error = use_state_eq(|| None::<String>);
+
+    let onchange = {
+        let password = password.clone();
+        Callback::from(move |value: String| password.set(value))
+    };
+
+    let onsubmit = {
+        let password = password.clone();
+        let error = error.clone();
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            let body = SudoPassword {
+                password: (*password).clone(),
+            };
+            let error = error.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                // The modal is closed once the status changes:
+                if let Err(e) = post("/api/sudo/password/", Some(body)).await {
+                    error.set(Some(e));
+                }
+            });
+        })
+    };
+
+    let oncancel = {
+        let error = error.clone();
+        Callback::from(move |_| {
+            let error = error.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Err(e) = post("/api/sudo/cancel/", None).await {
+                    error.set(Some(e));
+                }
+            });
+        })
+    };
+
+    let footer = html! {
+        <>
+            <Button
+                variant={ButtonVariant::Primary}
+                label="Authenticate"
+                r#type={ButtonType::Submit}
+                form="sudo-password-form"
+            />
+            <Button
+                variant={ButtonVariant::Link}
+                label="Cancel"
+                onclick={oncancel.reform(|_| ())}
+            />
+        </>
+    };
+
+    html! {
+        <Modal
+            title="Authenticate sudo"
+            description="The server needs your password to run commands as root."
+            variant={ModalVariant::Small}
+            {footer}
+            onclose={oncancel}
+            disable_close_click_outside=true
+        >
+            <Form id="sudo-password-form" {onsubmit}>
+                if let Some(message) = &props.message {
+                    <Alert inline=true r#type={AlertType::Warning} title={message.clone()} />
+                }
+                if let Some(e) = &*error {
+                    <Alert inline=true r#type={AlertType::Danger} title={e.clone()} />
+                }
+                <FormGroup label="Password">
+                    <TextInput
+                        r#type={TextInputType::Password}
+                        autofocus=true
+                        value={(*password).clone()}
+                        {onchange}
+                    />
+                </FormGroup>
+            </Form>
+        </Modal>
+    }
+}
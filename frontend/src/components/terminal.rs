@@ -1,35 +1,40 @@
+use crate::api;
 use crate::components::color_picker::ColorPicker;
 use crate::components::loading_state::LoadingState;
 use crate::components::markdown::MarkdownContent;
+use crate::components::profile_select::{use_profile, ProfileSelect};
+use crate::components::script_review::ScriptReviewPanel;
+use crate::websocket::ProcessSocket;
 use crate::{app::WindowDimensions, pages::workstation::WorkstationTab};
-use dry_console_dto::script::ScriptEntry;
-use dry_console_dto::websocket::Command;
-use dry_console_dto::websocket::PingReport;
+use dry_console_dto::script::{ScriptEntry, UnmetRequirement};
+use dry_console_dto::websocket::AnnotationLevel;
+use dry_console_dto::websocket::ProcessOutput;
+use dry_console_dto::websocket::ProcessProgress;
+use dry_console_dto::websocket::ProcessQueued;
 use dry_console_dto::websocket::ServerMsg;
+use dry_console_dto::websocket::Signal;
 use dry_console_dto::websocket::StreamType;
-use gloo::console::debug;
 use gloo::console::error;
 use gloo::net::http::Request;
 use gloo_storage::LocalStorage;
 use gloo_storage::Storage;
+use itertools::Itertools;
 use patternfly_yew::prelude::*;
-use serde_json::from_str;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::rc::Rc;
+use std::time::Duration;
+use strum::IntoEnumIterator;
 use ulid::Ulid;
-use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::spawn_local;
 use wasm_bindgen_futures::JsFuture;
-use web_sys::js_sys;
 use web_sys::js_sys::JsString;
 use web_sys::js_sys::Promise;
 use web_sys::js_sys::Reflect;
 use web_sys::window;
-use web_sys::Blob;
-use web_sys::FileReader;
+use web_sys::HtmlElement;
 use web_sys::HtmlInputElement;
-use web_sys::MessageEvent;
-use web_sys::{HtmlElement, WebSocket};
 use yew::prelude::*;
 
 const SHOW_LINE_NUMBERS_LOCALSTORAGE_KEY: &str = "terminal:show_line_numbers";
@@ -41,44 +46,310 @@ const TEXT_COLOR_STDOUT_LOCALSTORAGE_KEY: &str = "terminal:text_color_stdout";
 const TEXT_COLOR_STDERR_LOCALSTORAGE_KEY: &str = "terminal:text_color_stderr";
 const SHOW_META_STREAM_LOCALSTORAGE_KEY: &str = "terminal:show_meta_stream";
 
+/// The height of every line of output, in pixels (see style.css):
+const LINE_HEIGHT: i32 = 20;
+
+/// How many lines to render above and below the visible ones, so that
+/// scrolling a little does not show blank space:
+const OVERSCAN: i32 = 50;
+
 pub fn scroll_to_line(node_ref: &NodeRef, line_number: i32) {
     if let Some(element) = node_ref.cast::<web_sys::HtmlElement>() {
         //debug!(element.clone());
         // Calculate the scroll position based on line height and line number
-        let line_height = 20; // Adjust this according to your CSS
         let scroll_position = if line_number <= 0 {
             0
         } else if line_number == i32::MAX {
             element.scroll_height()
         } else {
-            line_number * line_height
+            line_number * LINE_HEIGHT
         };
         //debug!(format!("scroll! {}", scroll_position));
         element.set_scroll_top(scroll_position);
     }
 }
 
+/// Lines that the script did not write itself, but asked for with a
+/// workflow command (eg. `::group::Title`, or `::warning::message`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LineKind {
+    Output,
+    /// The title of a group, by its index:
+    Group(usize),
+    Annotation(AnnotationLevel),
+}
+
+/// One line of output, as it currently appears.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TerminalLine {
+    pub stream: StreamType,
+    pub text: String,
+    /// When the line was started, since the process started:
+    elapsed: Option<Duration>,
+    pub kind: LineKind,
+    /// The group the line belongs to, if any:
+    group: Option<usize>,
+}
+
+/// Where the next output of a stream goes.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct StreamCursor {
+    /// The line being written, until a newline ends it:
+    line: Option<usize>,
+    column: usize,
+    /// How many bytes of the stream have been received:
+    received: u64,
+    /// The start of a UTF-8 character, split between two chunks:
+    partial: Vec<u8>,
+}
+
+/// Renders the raw output of a process into lines, the way a terminal
+/// would. A carriage return moves back to the start of the line, so the
+/// text after it overwrites the line (eg. a progress bar).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TerminalBuffer {
+    lines: Vec<TerminalLine>,
+    streams: HashMap<StreamType, StreamCursor>,
+    /// Whether each group is collapsed:
+    collapsed: Vec<bool>,
+    /// The group that new lines belong to:
+    group: Option<usize>,
+    /// The last progress the script reported:
+    pub progress: Option<ProcessProgress>,
+    /// The outputs the script set (once it is complete, all of them):
+    pub outputs: BTreeMap<String, String>,
+    /// Where the run is in the run queue, until it starts:
+    pub queued: Option<ProcessQueued>,
+}
+
+impl TerminalBuffer {
+    /// Apply a message about the process: its output, or one of the
+    /// workflow commands of its script.
+    pub fn receive(&mut self, msg: &ServerMsg) {
+        match msg {
+            ServerMsg::ProcessQueued(queued) => {
+                if self.queued.is_none() {
+                    self.push_line(
+                        StreamType::Meta,
+                        format!("## Queued, waiting for: {}", queued.waiting_for.join(", ")),
+                    );
+                }
+                self.queued = Some(queued.clone());
+            }
+            ServerMsg::Process(_) => self.queued = None,
+            ServerMsg::ProcessOutput(output) => self.write(output),
+            ServerMsg::ProcessSetOutput(output) => {
+                self.outputs
+                    .insert(output.name.clone(), output.value.clone());
+            }
+            ServerMsg::ProcessProgress(progress) => self.progress = Some(progress.clone()),
+            ServerMsg::ProcessGroup(group) => match &group.title {
+                Some(title) => self.start_group(title.clone()),
+                None => self.group = None,
+            },
+            ServerMsg::ProcessAnnotation(annotation) => {
+                let title = match &annotation.title {
+                    Some(title) => format!("{}: {title}", annotation.level),
+                    None => annotation.level.to_string(),
+                };
+                let symbol = match annotation.level {
+                    AnnotationLevel::Warning => "⚠️",
+                    AnnotationLevel::Error => "❌",
+                };
+                for (i, text) in annotation.message.lines().enumerate() {
+                    let text = match i {
+                        0 => format!("{symbol} {title}: {text}"),
+                        _ => format!("   {text}"),
+                    };
+                    self.push(
+                        StreamType::Stdout,
+                        text,
+                        LineKind::Annotation(annotation.level),
+                    );
+                }
+            }
+            ServerMsg::ProcessComplete(complete) => self.outputs = complete.outputs.clone(),
+            _ => {}
+        }
+    }
+
+    pub fn write(&mut self, output: &ProcessOutput) {
+        // (The lines of the server are never part of a group.)
+        let group = self.group.filter(|_| output.stream != StreamType::Meta);
+        let Self { lines, streams, .. } = self;
+        let cursor = streams.entry(output.stream).or_default();
+        let end = output.offset + output.data.len() as u64;
+        if end <= cursor.received {
+            return;
+        }
+        let data = match output.offset.checked_sub(cursor.received) {
+            // Skip anything that was already received:
+            None => &output.data[(cursor.received - output.offset) as usize..],
+            Some(0) => &output.data[..],
+            // The server marks any output it dropped with a meta line:
+            Some(_) => {
+                cursor.line = None;
+                cursor.partial.clear();
+                &output.data[..]
+            }
+        };
+        cursor.received = end;
+        let mut bytes = std::mem::take(&mut cursor.partial);
+        bytes.extend_from_slice(data);
+        for c in decode_utf8(&bytes, &mut cursor.partial).chars() {
+            match c {
+                '\n' => {
+                    cursor.line = None;
+                    cursor.column = 0;
+                }
+                '\r' => cursor.column = 0,
+                '\x08' => cursor.column = cursor.column.saturating_sub(1),
+                c => {
+                    let index = *cursor.line.get_or_insert_with(|| {
+                        lines.push(TerminalLine {
+                            stream: output.stream,
+                            text: String::new(),
+                            elapsed: Some(output.elapsed),
+                            kind: LineKind::Output,
+                            group,
+                        });
+                        lines.len() - 1
+                    });
+                    let text = &mut lines[index].text;
+                    match text.char_indices().nth(cursor.column) {
+                        Some((i, old)) => {
+                            text.replace_range(i..i + old.len_utf8(), c.encode_utf8(&mut [0; 4]))
+                        }
+                        None => text.push(c),
+                    }
+                    cursor.column += 1;
+                }
+            }
+        }
+    }
+
+    /// The lines that are shown (the lines of collapsed groups are not),
+    /// each with its line number if it is output on stdout.
+    pub fn shown_lines(&self, show_meta_stream: bool) -> Vec<(Option<usize>, &TerminalLine)> {
+        let mut line_number = 0;
+        self.lines
+            .iter()
+            .map(|line| match (line.stream, line.kind) {
+                (StreamType::Stdout, LineKind::Output) => {
+                    line_number += 1;
+                    (Some(line_number), line)
+                }
+                _ => (None, line),
+            })
+            .filter(|(_, line)| show_meta_stream || line.stream != StreamType::Meta)
+            .filter(|(_, line)| !line.group.is_some_and(|group| self.is_collapsed(group)))
+            .collect()
+    }
+
+    /// Add a whole line that did not come from the process.
+    pub fn push_line(&mut self, stream: StreamType, text: String) {
+        self.push(stream, text, LineKind::Output);
+    }
+
+    fn push(&mut self, stream: StreamType, text: String, kind: LineKind) {
+        if let Some(cursor) = self.streams.get_mut(&stream) {
+            cursor.line = None;
+            cursor.column = 0;
+        }
+        self.lines.push(TerminalLine {
+            stream,
+            text,
+            elapsed: None,
+            kind,
+            group: self.group.filter(|_| stream != StreamType::Meta),
+        });
+    }
+
+    /// Start a new group (ending the one before it, if any). The output
+    /// that follows is written on new lines.
+    fn start_group(&mut self, title: String) {
+        self.group = None;
+        let index = self.collapsed.len();
+        self.collapsed.push(false);
+        for cursor in self.streams.values_mut() {
+            cursor.line = None;
+            cursor.column = 0;
+        }
+        self.push(StreamType::Stdout, title, LineKind::Group(index));
+        self.group = Some(index);
+    }
+
+    pub fn is_collapsed(&self, group: usize) -> bool {
+        self.collapsed.get(group).copied().unwrap_or_default()
+    }
+
+    /// Collapse the group, or expand it again.
+    pub fn toggle_group(&mut self, group: usize) {
+        if let Some(collapsed) = self.collapsed.get_mut(group) {
+            *collapsed = !*collapsed;
+        }
+    }
+}
+
+/// Decode as much of bytes as possible, leaving an incomplete character
+/// at the end in partial, and replacing invalid bytes with U+FFFD.
+fn decode_utf8(mut bytes: &[u8], partial: &mut Vec<u8>) -> String {
+    let mut text = String::new();
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                text.push_str(valid);
+                return text;
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                match e.error_len() {
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        bytes = &rest[len..];
+                    }
+                    None => {
+                        *partial = rest.to_vec();
+                        return text;
+                    }
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct WebSocketState {
-    websocket: Option<WebSocket>,
+    /// The process running on the shared ProcessSocket:
+    process_id: Option<Ulid>,
     script_entry: Option<ScriptEntry>,
     status: TerminalStatus,
-    messages: Vec<(StreamType, String)>,
+    /// The output may grow to many thousands of lines, so it is shared
+    /// by every state, rather than copied. revision changes with it.
+    output: Rc<RefCell<TerminalBuffer>>,
+    revision: usize,
     error: String,
 }
 // Reducer actions to manage WebSocketState
 #[derive(Debug)]
 enum WebSocketAction {
-    Initialize(ScriptEntry),
-    Connect(WebSocket),
-    ReceivePingReport(PingReport),
-    ReceiveProcessOutput(StreamType, String),
+    Initialize(Box<ScriptEntry>),
+    Start(Ulid),
+    /// Output, or a workflow command of the script (see
+    /// TerminalBuffer::receive).
+    ReceiveProcessMessage(ServerMsg),
     ReceiveProcessComplete(String, usize),
+    /// Collapse or expand a group of lines.
+    ToggleGroup(usize),
     ReceiveProcess(Ulid),
+    /// The process is waiting in the run queue.
+    ReceiveProcessQueued(ProcessQueued),
     Failed(String),
     CriticalError(String),
     Reset,
-    SendPong,
+    /// Fetch the script entry again (eg. once it has been approved).
+    Reload,
 }
 impl Reducible for WebSocketState {
     type Action = WebSocketAction;
@@ -91,71 +362,78 @@ impl Reducible for WebSocketState {
             WebSocketAction::Initialize(script_entry) => {
                 //debug!("Action: Initialize");
                 WebSocketState {
-                    script_entry: Some(script_entry),
-                    websocket: None,
+                    script_entry: Some(*script_entry),
+                    process_id: None,
                     status: TerminalStatus::Initialized,
-                    messages: self.messages.clone(),
+                    output: self.output.clone(),
+                    revision: self.revision,
                     error: self.error.clone(),
                 }
                 .into()
             }
-            WebSocketAction::Connect(ws) => {
-                //debug!("Action: Connect");
+            WebSocketAction::Start(process_id) => {
+                //debug!("Action: Start");
                 WebSocketState {
                     script_entry: self.script_entry.clone(),
-                    websocket: Some(ws),
+                    process_id: Some(process_id),
                     status: TerminalStatus::Connecting,
-                    messages: self.messages.clone(),
+                    output: self.output.clone(),
+                    revision: self.revision,
                     error: self.error.clone(),
                 }
                 .into()
             }
-            WebSocketAction::ReceivePingReport(_r) => {
-                //debug!(format!("Action: ReceivePingReport {:?}", r));
+            WebSocketAction::ReceiveProcessQueued(queued) => {
+                self.output
+                    .borrow_mut()
+                    .receive(&ServerMsg::ProcessQueued(queued));
                 WebSocketState {
                     script_entry: self.script_entry.clone(),
-                    websocket: self.websocket.clone(),
-                    status: if self.status == TerminalStatus::Connecting {
-                        if let Some(ws) = &self.websocket {
-                            if let Ok(serialized_msg) = serde_json::to_string(&Command {
-                                id: self.script_entry.clone().unwrap().id,
-                            }) {
-                                //debug!(format!("sending command serialized: {}", serialized_msg));
-                                ws.send_with_str(&serialized_msg).ok();
-                            }
-                        }
-                        TerminalStatus::Ready
-                    } else {
-                        self.status.clone()
-                    },
-                    messages: self.messages.clone(),
+                    process_id: self.process_id,
+                    status: TerminalStatus::Queued,
+                    output: self.output.clone(),
+                    revision: self.revision + 1,
                     error: self.error.clone(),
                 }
                 .into()
             }
             WebSocketAction::ReceiveProcess(_id) => {
                 //debug!(format!("Action: ReceiveProcess, id: {:?}", id));
+                self.output.borrow_mut().queued = None;
                 WebSocketState {
                     script_entry: self.script_entry.clone(),
-                    websocket: self.websocket.clone(),
+                    process_id: self.process_id,
                     status: TerminalStatus::Processing,
-                    messages: self.messages.clone(),
+                    output: self.output.clone(),
+                    revision: self.revision,
                     error: self.error.clone(),
                 }
                 .into()
             }
-            WebSocketAction::ReceiveProcessOutput(stream, message) => {
+            WebSocketAction::ReceiveProcessMessage(msg) => {
                 //debug!(format!(
-                //    "Action: ReceiveProcessOutput, stream: {:?}, message: {}",
-                //    stream, message
+                //    "Action: ReceiveProcessMessage: {:?}",
+                //    msg
                 //));
-                let mut messages = self.messages.clone();
-                messages.push((stream, message));
+                self.output.borrow_mut().receive(&msg);
+                WebSocketState {
+                    script_entry: self.script_entry.clone(),
+                    process_id: self.process_id,
+                    status: self.status.clone(),
+                    output: self.output.clone(),
+                    revision: self.revision + 1,
+                    error: self.error.clone(),
+                }
+                .into()
+            }
+            WebSocketAction::ToggleGroup(group) => {
+                self.output.borrow_mut().toggle_group(group);
                 WebSocketState {
                     script_entry: self.script_entry.clone(),
-                    websocket: self.websocket.clone(),
+                    process_id: self.process_id,
                     status: self.status.clone(),
-                    messages,
+                    output: self.output.clone(),
+                    revision: self.revision + 1,
                     error: self.error.clone(),
                 }
                 .into()
@@ -167,67 +445,63 @@ impl Reducible for WebSocketState {
                 //));
                 WebSocketState {
                     script_entry: self.script_entry.clone(),
-                    websocket: self.websocket.clone(),
+                    process_id: self.process_id,
                     status: if code == 0 {
                         TerminalStatus::Complete
                     } else {
                         TerminalStatus::Failed
                     },
-                    messages: self.messages.clone(),
+                    output: self.output.clone(),
+                    revision: self.revision,
                     error: self.error.clone(),
                 }
                 .into()
             }
             WebSocketAction::Failed(error_message) => {
                 //debug!("Action: Failed, error_message: {}", error_message.clone());
-                let mut messages = self.messages.clone();
-                messages.push((
+                self.output.borrow_mut().push_line(
                     StreamType::Meta,
                     format!("# [Process failed]: {}", error_message),
-                ));
+                );
                 WebSocketState {
                     script_entry: self.script_entry.clone(),
-                    websocket: None,
+                    process_id: None,
                     status: TerminalStatus::Failed,
-                    messages,
+                    output: self.output.clone(),
+                    revision: self.revision + 1,
                     error: self.error.clone(),
                 }
                 .into()
             }
             WebSocketAction::Reset => {
                 //debug!("Action: Reset");
-                if let Some(ws) = &self.websocket {
-                    debug!("Closing socket");
-                    ws.close().ok();
-                }
                 WebSocketState {
                     script_entry: self.script_entry.clone(),
-                    websocket: None,
+                    process_id: None,
                     status: TerminalStatus::Initialized,
-                    messages: Vec::new(),
+                    output: Default::default(),
+                    revision: 0,
                     error: self.error.clone(),
                 }
                 .into()
             }
-            WebSocketAction::SendPong => {
-                //debug!("Action: SendPong");
-                if let Some(ws) = &self.websocket {
-                    let msg = ServerMsg::Pong;
-                    if let Ok(serialized_msg) = serde_json::to_string(&msg) {
-                        ws.send_with_str(&serialized_msg).ok();
-                    } else {
-                        error!("Failed to serialize ServerMsg::Pong");
-                    }
-                }
-                self.clone()
+            WebSocketAction::Reload => WebSocketState {
+                script_entry: None,
+                process_id: None,
+                status: TerminalStatus::Uninitialized,
+                output: Default::default(),
+                revision: 0,
+                error: String::new(),
             }
+            .into(),
             WebSocketAction::CriticalError(e) => {
                 //debug!("Action: CriticalError");
                 WebSocketState {
                     script_entry: None,
-                    websocket: None,
+                    process_id: None,
                     status: TerminalStatus::Critical,
-                    messages: Vec::new(),
+                    output: Default::default(),
+                    revision: 0,
                     error: e,
                 }
                 .into()
@@ -244,7 +518,8 @@ enum TerminalStatus {
     Uninitialized,
     Initialized,
     Connecting,
-    Ready,
+    /// Waiting in the run queue, for the locks of the script.
+    Queued,
     Processing,
     Failed,
     Critical,
@@ -258,9 +533,17 @@ pub struct TerminalOutputProps {
     pub selected_tab: WorkstationTab,
     pub on_done: Option<Callback<MouseEvent>>,
 }
-impl TerminalOutputProps {
-    pub fn default_on_done() -> Callback<MouseEvent> {
-        Callback::from(|_| {})
+/// eg. "bash found 1 error", "shellcheck found 3 problems".
+fn diagnostics_title(script_entry: &ScriptEntry) -> String {
+    let found_by = match script_entry.diagnostics.iter().all(|d| d.source == "bash") {
+        true => "bash",
+        false => "shellcheck",
+    };
+    match (script_entry.error_count(), script_entry.diagnostics.len()) {
+        (1, _) => format!("{found_by} found an error in this script"),
+        (0, 1) => format!("{found_by} found a problem in this script"),
+        (0, count) => format!("{found_by} found {count} problems in this script"),
+        (errors, _) => format!("{found_by} found {errors} errors in this script"),
     }
 }
 
@@ -273,6 +556,7 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
     });
     let show_meta_stream =
         use_state(|| LocalStorage::get::<bool>(SHOW_META_STREAM_LOCALSTORAGE_KEY).unwrap_or(true));
+    let profile = use_profile();
     let background_color_change = use_state(|| {
         LocalStorage::get::<bool>(BACKGROUND_COLOR_CHANGE_LOCALSTORAGE_KEY).unwrap_or(true)
     });
@@ -302,15 +586,20 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
     });
     let text_color_stderr_clone = text_color_stderr.clone();
     let user_attempted_scroll = use_state(|| false);
+    // Only the lines in (or near) the viewport are rendered:
+    let scroll_top = use_state_eq(|| 0);
+    let viewport_height = use_state_eq(|| 1000);
     let terminal_ref = use_node_ref();
     let terminal_content_ref = use_node_ref();
     let gutter_ref = use_node_ref();
 
+    let process_socket = use_context::<ProcessSocket>().expect("no ProcessSocket ctx found");
     let ws_state = use_reducer(|| WebSocketState {
         script_entry: None,
-        websocket: None,
+        process_id: None,
         status: TerminalStatus::Uninitialized,
-        messages: Vec::new(),
+        output: Default::default(),
+        revision: 0,
         error: "".to_string(),
     });
 
@@ -359,14 +648,21 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
         let terminal_content_ref = terminal_content_ref.clone();
         let gutter_ref = gutter_ref.clone();
         let user_attempted_scroll = user_attempted_scroll.clone();
+        let scroll_top = scroll_top.clone();
+        let viewport_height = viewport_height.clone();
         Callback::from(move |_| {
-            user_attempted_scroll.set(true);
-            if let (Some(terminal), Some(gutter)) = (
-                terminal_content_ref.cast::<HtmlElement>(),
-                gutter_ref.cast::<HtmlElement>(),
-            ) {
-                let scroll_top = terminal.scroll_top();
-                gutter.set_scroll_top(scroll_top);
+            let Some(terminal) = terminal_content_ref.cast::<HtmlElement>() else {
+                return;
+            };
+            // Keep following the output, for as long as it is scrolled to the bottom:
+            user_attempted_scroll.set(
+                terminal.scroll_top() + terminal.client_height()
+                    < terminal.scroll_height() - LINE_HEIGHT,
+            );
+            scroll_top.set(terminal.scroll_top());
+            viewport_height.set(terminal.client_height());
+            if let Some(gutter) = gutter_ref.cast::<HtmlElement>() {
+                gutter.set_scroll_top(terminal.scroll_top());
             }
         })
     };
@@ -387,129 +683,107 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
         })
     };
 
-    // Reset reinitializes websocket and terminal
-    fn cancel_websocket(ws_state: &WebSocketState) {
-        if let Some(ws) = &ws_state.websocket {
-            ws.send_with_str("\"Cancel\"").ok();
-            //ws.close().ok();
+    // Reset reinitializes the process and terminal
+    fn cancel_process(process_socket: &ProcessSocket, ws_state: &WebSocketState) {
+        if let Some(process_id) = ws_state.process_id {
+            process_socket.cancel(process_id);
         }
     }
-    let cancel_process = {
+    let cancel = {
         let ws_state = ws_state.clone();
+        let process_socket = process_socket.clone();
         Callback::from(move |_: MouseEvent| {
-            cancel_websocket(&ws_state);
+            cancel_process(&process_socket, &ws_state);
+        })
+    };
+    let send_signal = {
+        let ws_state = ws_state.clone();
+        let process_socket = process_socket.clone();
+        Callback::from(move |signal: Signal| {
+            if let Some(process_id) = ws_state.process_id {
+                process_socket.signal(process_id, signal);
+            }
         })
     };
     let reset_terminal = {
         let ws_state = ws_state.clone();
+        let process_socket = process_socket.clone();
         let user_attempted_scroll = user_attempted_scroll.clone();
         Callback::from(move |_: MouseEvent| {
-            cancel_websocket(&ws_state);
+            cancel_process(&process_socket, &ws_state);
+            if let Some(process_id) = ws_state.process_id {
+                process_socket.forget(process_id);
+            }
             user_attempted_scroll.set(false);
             ws_state.dispatch(WebSocketAction::Reset);
         })
     };
 
-    // "Run command" button callback to set up WebSocket and change status
+    // "Run command" button callback to start the process on the shared socket
     let run_command = {
         let ws_state = ws_state.clone();
+        let profile = profile.clone();
+        let process_socket = process_socket.clone();
         let user_attempted_scroll = user_attempted_scroll.clone();
         Callback::from(move |_: MouseEvent| {
             //debug!("Run command button clicked");
             user_attempted_scroll.set(false);
-            // Close any existing WebSocket before starting a new one
-            if let Some(ws) = &ws_state.websocket {
-                //debug!("Closing existing WebSocket");
-                ws.close().ok();
+            // Stop any previous run of this terminal before starting a new one
+            if let Some(process_id) = ws_state.process_id {
+                process_socket.cancel(process_id);
+                process_socket.forget(process_id);
             }
             ws_state.dispatch(WebSocketAction::Reset);
 
-            // Attempt to connect the WebSocket
-            //debug!("Attempting to connect WebSocket");
-
-            let ws = WebSocket::new("/api/workstation/command_execute/").unwrap();
-            let ws_clone = ws.clone();
-            ws_state.dispatch(WebSocketAction::Connect(ws));
-
-            // Set up the onerror callback to catch connection errors
-            let onerror_callback = {
-                let ws_state = ws_state.clone();
-                Closure::wrap(Box::new(move |error: ErrorEvent| {
-                    debug!(format!("WebSocket error: {}", error.message()));
-                    ws_state.dispatch(WebSocketAction::Failed(format!("{:?}", error.message())));
-                }) as Box<dyn FnMut(ErrorEvent)>)
+            let Some(script_entry) = ws_state.script_entry.clone() else {
+                return;
             };
-            ws_clone.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
-            onerror_callback.forget();
-
-            // Set up the onmessage callback
-            let onmessage_callback = {
+            let on_message = {
                 let ws_state = ws_state.clone();
-                Closure::wrap(Box::new(move |event: MessageEvent| {
-                    if let Ok(blob) = event.data().dyn_into::<Blob>() {
-                        let ws_state = ws_state.clone();
-
-                        let reader = FileReader::new().unwrap();
-                        let reader_clone = reader.clone();
-                        let onloadend_callback =
-                            Closure::wrap(Box::new(move |_: web_sys::ProgressEvent| {
-                                let result = reader_clone.result().unwrap();
-
-                                if let Ok(text) = result.dyn_into::<js_sys::JsString>() {
-                                    handle_message(ws_state.clone(), text.into());
-                                } else {
-                                    error!("Failed to convert result to text");
-                                }
-                            }) as Box<dyn FnMut(_)>);
-
-                        reader.set_onloadend(Some(onloadend_callback.as_ref().unchecked_ref()));
-                        reader.read_as_text(&blob).unwrap();
-                        onloadend_callback.forget();
-                    } else {
-                        error!("Received unsupported WebSocket message type");
-                    }
-                }) as Box<dyn FnMut(MessageEvent)>)
+                Callback::from(move |msg: ServerMsg| handle_message(&ws_state, msg))
             };
+            let process_id = process_socket.start(script_entry.id, (*profile).clone(), on_message);
+            ws_state.dispatch(WebSocketAction::Start(process_id));
 
-            fn handle_message(ws_state: UseReducerHandle<WebSocketState>, msg: String) {
-                match from_str::<ServerMsg>(&msg) {
-                    Ok(server_msg) => match server_msg {
-                        ServerMsg::Ping => {
-                            ws_state.dispatch(WebSocketAction::SendPong);
-                        }
-                        ServerMsg::PingReport(r) => {
-                            ws_state.dispatch(WebSocketAction::ReceivePingReport(r));
-                        }
-                        ServerMsg::Process(p) => {
-                            ws_state.dispatch(WebSocketAction::ReceiveProcess(p.id));
-                        }
-                        ServerMsg::ProcessOutput(o) => {
-                            ws_state
-                                .dispatch(WebSocketAction::ReceiveProcessOutput(o.stream, o.line));
-                        }
-                        ServerMsg::ProcessComplete(c) => {
-                            ws_state.dispatch(WebSocketAction::ReceiveProcessComplete(
-                                c.id.to_string(),
-                                c.code.try_into().unwrap_or(128),
-                            ));
-                        }
-                        _ => {}
-                    },
-                    Err(e) => {
-                        error!(format!("Failed to parse message: {}, error: {}", msg, e));
-                        ws_state.dispatch(WebSocketAction::Failed(msg));
+            fn handle_message(ws_state: &UseReducerHandle<WebSocketState>, msg: ServerMsg) {
+                match msg {
+                    ServerMsg::Process(p) => {
+                        ws_state.dispatch(WebSocketAction::ReceiveProcess(p.id));
+                    }
+                    ServerMsg::ProcessQueued(q) => {
+                        ws_state.dispatch(WebSocketAction::ReceiveProcessQueued(q));
+                    }
+                    msg @ (ServerMsg::ProcessOutput(_)
+                    | ServerMsg::ProcessSetOutput(_)
+                    | ServerMsg::ProcessProgress(_)
+                    | ServerMsg::ProcessGroup(_)
+                    | ServerMsg::ProcessAnnotation(_)) => {
+                        ws_state.dispatch(WebSocketAction::ReceiveProcessMessage(msg));
+                    }
+                    ServerMsg::ProcessComplete(c) => {
+                        let id = c.id.to_string();
+                        let code = c.code.try_into().unwrap_or(128);
+                        // (For its outputs.)
+                        ws_state.dispatch(WebSocketAction::ReceiveProcessMessage(
+                            ServerMsg::ProcessComplete(c),
+                        ));
+                        ws_state.dispatch(WebSocketAction::ReceiveProcessComplete(id, code));
                     }
+                    ServerMsg::ProcessRejected(r) => {
+                        ws_state.dispatch(WebSocketAction::Failed(format!(
+                            "Unmet requirements: {}",
+                            r.unmet_requirements.iter().join("; ")
+                        )));
+                    }
+                    ServerMsg::ProcessError(e) => {
+                        ws_state.dispatch(WebSocketAction::Failed(e.message));
+                    }
+                    _ => {}
                 }
             }
-
-            ws_clone.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
-            onmessage_callback.forget();
         })
     };
 
-    let mut line_number_gutter = 1;
-    let mut line_number_output = 1;
-
     let settings_link = html! {
         <Button>{"📻️ Settings"}</Button>
     };
@@ -653,6 +927,34 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
             </List>
         </PopoverBody>
     );
+    fn copy_text(text: String, set_button_text: yew::UseStateHandle<String>) {
+        if let Some(window) = window() {
+            let navigator = window.navigator();
+            let clipboard = Reflect::get(&navigator, &JsString::from("clipboard")).unwrap();
+
+            if clipboard.is_undefined() {
+                error!("Clipboard API is not supported in this browser");
+            } else {
+                let clipboard: web_sys::Clipboard = clipboard.dyn_into().unwrap();
+                let promise: Promise = clipboard.write_text(&text);
+
+                let future = JsFuture::from(promise);
+                wasm_bindgen_futures::spawn_local(async move {
+                    if future.await.is_ok() {
+                        set_button_text.set("✅".to_string());
+                        wasm_bindgen_futures::spawn_local(async move {
+                            gloo::timers::future::TimeoutFuture::new(2000).await;
+                            set_button_text.set("📋".to_string());
+                        });
+                    } else {
+                        error!("Failed to copy text");
+                    }
+                });
+            }
+        } else {
+            error!("window not found.");
+        }
+    }
     fn copy_code(
         code_block_ref: NodeRef,
         set_button_text: yew::UseStateHandle<String>,
@@ -663,35 +965,7 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
                 if let Some(content_element) = element.query_selector(".content").unwrap() {
                     // Cast `Element` to `HtmlElement` to use `inner_text()`
                     if let Ok(content) = content_element.dyn_into::<HtmlElement>() {
-                        let text = content.inner_text();
-                        if let Some(window) = window() {
-                            let navigator = window.navigator();
-                            let clipboard =
-                                Reflect::get(&navigator, &JsString::from("clipboard")).unwrap();
-
-                            if clipboard.is_undefined() {
-                                error!("Clipboard API is not supported in this browser");
-                            } else {
-                                let clipboard: web_sys::Clipboard = clipboard.dyn_into().unwrap();
-                                let promise: Promise = clipboard.write_text(&text);
-
-                                let set_button_text = set_button_text.clone();
-                                let future = JsFuture::from(promise);
-                                wasm_bindgen_futures::spawn_local(async move {
-                                    if future.await.is_ok() {
-                                        set_button_text.set("✅".to_string());
-                                        wasm_bindgen_futures::spawn_local(async move {
-                                            gloo::timers::future::TimeoutFuture::new(2000).await;
-                                            set_button_text.set("📋".to_string());
-                                        });
-                                    } else {
-                                        error!("Failed to copy text");
-                                    }
-                                });
-                            }
-                        } else {
-                            error!("window not found.");
-                        }
+                        copy_text(content.inner_text(), set_button_text.clone());
                     } else {
                         error!("Failed to cast content_element to HtmlElement.");
                     }
@@ -707,6 +981,8 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
     #[derive(Properties, PartialEq, Clone)]
     pub struct CommandAreaProps {
         pub script: String,
+        /// The script as it runs, if it has includes or is a template.
+        pub rendered: Option<String>,
         pub description: String,
         pub background_color: String,
         pub foreground_color: String,
@@ -715,6 +991,7 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
     fn command_area(props: &CommandAreaProps) -> Html {
         let CommandAreaProps {
             script,
+            rendered,
             description,
             background_color,
             foreground_color,
@@ -725,6 +1002,14 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
         let ontoggle = use_callback(expanded.clone(), |(), expanded| {
             expanded.set(!**expanded);
         });
+        let show_rendered = use_state_eq(|| false);
+        let ontoggle_rendered = use_callback(show_rendered.clone(), |_, show_rendered| {
+            show_rendered.set(!**show_rendered);
+        });
+        let shown_script = match (*show_rendered, rendered) {
+            (true, Some(rendered)) => rendered,
+            _ => script,
+        };
         html! {
             <div class="command_area" style="position: relative;">
                 <div class="header">
@@ -740,11 +1025,16 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
                 <div class="code_container" ref={code_block_ref.clone()}>
                 <div class="content" style={format!("background-color: {}; color: {}", background_color, foreground_color)}>
                 <CodeBlock>
-                <CodeBlockCode>{script}</CodeBlockCode>
+                <CodeBlockCode>{shown_script}</CodeBlockCode>
                 </CodeBlock>
             </div>
             <button title="Copy script" class="copy-button" onclick={copy_code(code_block_ref.clone(), button_text.clone())}><div class="copy-button-text">{ (*button_text).clone() }</div></button>
                 </div>
+                if rendered.is_some() {
+                    <Button variant={ButtonVariant::Link} onclick={ontoggle_rendered}>
+                        { if *show_rendered { "Show the script as written" } else { "Show the script as it runs on this workstation" } }
+                    </Button>
+                }
                 </ExpandableSection>
                 </StackItem>
                 </Stack>
@@ -755,8 +1045,7 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
     {
         let user_attempted_scroll = user_attempted_scroll.clone();
         let content_ref = terminal_content_ref.clone();
-        let messages_len = ws_state.messages.len();
-        use_effect_with(messages_len, move |_| {
+        use_effect_with(ws_state.revision, move |_| {
             if !*user_attempted_scroll {
                 scroll_to_line(&content_ref, i32::MAX);
             }
@@ -784,14 +1073,15 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
             if *status == TerminalStatus::Uninitialized {
                 let ws_state = ws_state.clone();
                 spawn_local(async move {
-                    let response = Request::get(&format!("/api/workstation/command/{}/", script))
-                        .send()
-                        .await;
+                    let response =
+                        Request::get(&api::url(&format!("/api/workstation/command/{}/", script)))
+                            .send()
+                            .await;
 
                     match response {
                         Ok(resp) => {
                             if let Ok(data) = resp.json::<ScriptEntry>().await {
-                                ws_state.dispatch(WebSocketAction::Initialize(data));
+                                ws_state.dispatch(WebSocketAction::Initialize(Box::new(data)));
                             } else {
                                 match resp.status() {
                                     404 => ws_state.dispatch(WebSocketAction::CriticalError(
@@ -820,6 +1110,12 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
         .clone()
         .unwrap_or(ScriptEntry::default());
 
+    // Fetch the script entry again, now that it has been approved:
+    let on_approved = {
+        let ws_state = ws_state.clone();
+        Callback::from(move |_| ws_state.dispatch(WebSocketAction::Reload))
+    };
+
     let done = {
         let reset_terminal = reset_terminal.clone();
         let on_done = props.on_done.clone();
@@ -831,6 +1127,34 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
         })
     };
 
+    // Render only the lines near the viewport, with empty space in
+    // place of the others:
+    let output = ws_state.output.borrow();
+    let shown = output.shown_lines(*show_meta_stream);
+    let first = (*scroll_top / LINE_HEIGHT - OVERSCAN).clamp(0, shown.len() as i32) as usize;
+    let last = (first + (*viewport_height / LINE_HEIGHT + 2 * OVERSCAN) as usize).min(shown.len());
+    let window = &shown[first..last];
+    let spacer = |lines: usize| {
+        html! {
+            <div class="spacer" style={format!("height: {}px", lines as i32 * LINE_HEIGHT)} />
+        }
+    };
+    // Copy all of the output, not just the rendered lines:
+    let copy_output = {
+        let output = ws_state.output.clone();
+        let show_meta_stream = *show_meta_stream;
+        let output_copy_button_text = output_copy_button_text.clone();
+        Callback::from(move |_: MouseEvent| {
+            let text = output
+                .borrow()
+                .shown_lines(show_meta_stream)
+                .iter()
+                .map(|(_, line)| &line.text)
+                .join("\n");
+            copy_text(text, output_copy_button_text.clone())
+        })
+    };
+
     html! {
         <div class="terminal">
         if ws_state.status == TerminalStatus::Critical {
@@ -838,13 +1162,44 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
         } else if ws_state.status == TerminalStatus::Uninitialized {
             <LoadingState/>
         } else {
-            <CommandArea description={script_entry.description.clone()} script={script_entry.script} background_color={(*background_color_normal).clone()} foreground_color={(*text_color_stdout).clone()}/>
+            <CommandArea description={script_entry.description.clone()} script={script_entry.script.clone()} rendered={script_entry.rendered.clone()} background_color={(*background_color_normal).clone()} foreground_color={(*text_color_stdout).clone()}/>
+            if !script_entry.diagnostics.is_empty() {
+                <Alert inline=true title={diagnostics_title(&script_entry)} r#type={if script_entry.has_errors() { AlertType::Danger } else { AlertType::Info }}>
+                    <ul class="script-diagnostics">
+                    { for script_entry.diagnostics.iter().map(|d| html! { <li>{d.to_string()}</li> }) }
+                    </ul>
+                </Alert>
+            }
+            if script_entry.unmet_requirements.contains(&UnmetRequirement::Unapproved) {
+                <ScriptReviewPanel command={props.script.clone()} on_approved={on_approved} />
+            }
+            if !script_entry.unmet_requirements.is_empty() {
+                <Alert inline=true title="This script can not be run yet" r#type={AlertType::Warning}>
+                    <ul>
+                    { for script_entry.unmet_requirements.iter().map(|r| html! { <li>{r.to_string()}</li> }) }
+                    </ul>
+                </Alert>
+            }
             <div class="toolbar pf-u-display-flex pf-u-justify-content-space-between">
             <div class="pf-u-display-flex">
                         if ws_state.status == TerminalStatus::Initialized {
                           <Button onclick={run_command.clone()}>{"🚀 Run script"}</Button>
+                          <ProfileSelect selected={(*profile).clone()} onchange={let profile = profile.clone(); move |p| profile.set(p)} />
                         } else if ws_state.status == TerminalStatus::Processing {
-                          <Button onclick={cancel_process.clone()}>{"🛑 Stop"}</Button>
+                          <Button onclick={cancel.clone()}>{"🛑 Stop"}</Button>
+                          <Dropdown text="Signal">
+                            { for Signal::iter().map(|signal| {
+                                let send_signal = send_signal.clone();
+                                html_nested! {
+                                    <MenuAction onclick={move |_| send_signal.emit(signal)}>{signal.to_string()}</MenuAction>
+                                }
+                            }) }
+                          </Dropdown>
+                        } else if ws_state.status == TerminalStatus::Queued {
+                          <Button onclick={cancel.clone()}>{"🛑 Stop"}</Button>
+                          if let Some(queued) = &output.queued {
+                            <span class="queued-status">{format!("⏳️ Queued (#{}), waiting for: {}", queued.position, queued.waiting_for.join(", "))}</span>
+                          }
                         } else if ws_state.status == TerminalStatus::Complete {
                             <Button onclick={done.clone()}>{"👍️ Done"}</Button>
                         } else if ws_state.status == TerminalStatus::Connecting {
@@ -861,57 +1216,81 @@ pub fn terminal_output(props: &TerminalOutputProps) -> Html {
                     }
                 </div>
             </div>
+            if let Some(progress) = &output.progress {
+                <Progress class="terminal-progress" value={progress.current as f64 / progress.total as f64} description={progress.message.clone().map(Html::from)} />
+            }
             <div class="terminal_display" ref={terminal_ref.clone()}>
                 if *show_line_numbers && ws_state.status != TerminalStatus::Initialized {
                     <div class="gutter" ref={gutter_ref} style={format!("max-height: {}em", *num_lines)}>
+                    {spacer(first)}
                     {
-                        for ws_state.messages.iter().filter_map(|(stream, _message)| {
-                            if *stream == StreamType::Meta && !*show_meta_stream {
-                                None
-                            } else {
-                                let gutter_content = match stream {
-                                    StreamType::Stdout => {
-                                        let content = line_number_gutter.to_string();
-                                        line_number_gutter += 1;
-                                        content
-                                    }
-                                    StreamType::Stderr => "E".to_string(),         // "E" for StdErr
-                                    StreamType::Meta => "#".to_string(),           // "#" for Meta (assuming this is the correct symbol)
-                                };
-                                Some(html!{
-                                    <div class="gutter-line">{gutter_content}</div>
-                                })
+                        for window.iter().map(|(line_number, line)| {
+                            let gutter_content = match (line.kind, line.stream) {
+                                (LineKind::Group(_), _) => "".to_string(),
+                                (LineKind::Annotation(_), _) => "!".to_string(),
+                                (_, StreamType::Stdout) => line_number.unwrap_or_default().to_string(),
+                                (_, StreamType::Stderr) => "E".to_string(),         // "E" for StdErr
+                                (_, StreamType::Meta) => "#".to_string(),           // "#" for Meta (assuming this is the correct symbol)
+                            };
+                            html!{
+                                <div class="gutter-line">{gutter_content}</div>
                             }
                         })
                     }
+                    {spacer(shown.len() - last)}
                     </div>
             }
         if ws_state.status == TerminalStatus::Complete || ws_state.status == TerminalStatus::Failed {
-            <button title="Copy output" class="copy-button" onclick={copy_code(terminal_ref.clone(), output_copy_button_text.clone())}><div class="copy-button-text">{ (*output_copy_button_text).clone() }</div></button>
+            <button title="Copy output" class="copy-button" onclick={copy_output}><div class="copy-button-text">{ (*output_copy_button_text).clone() }</div></button>
         }
         <div class="content" ref={terminal_content_ref.clone()} {onscroll} style={format!("max-height: {}em; background-color: {}; color: {}", *num_lines, **output_background_color, **output_stdout_color)}>
+        {spacer(first)}
         {
-            for ws_state.messages.iter().filter_map(|(stream, message)| {
-                if *stream == StreamType::Meta && !*show_meta_stream {
-                    None
-                } else {
-                    let (class_name, id, style) = match stream {
-                        StreamType::Stdout => {
-                            let id = format!("line-{}", line_number_output);
-                            line_number_output += 1;
-                            ("stream-stdout", id, "".to_string())
-                        },
-                        StreamType::Stderr => ("stream-stderr", "".to_string(), format!("color: {}", *text_color_stderr)),
-                        StreamType::Meta => ("stream-meta", "".to_string(), "".to_string()),
-                    };
-                    Some(html!{
-                        <span id={id} class={class_name} style={style}>{message}</span>
-                    })
+            for window.iter().map(|(line_number, line)| {
+                match line.kind {
+                    LineKind::Group(group) => {
+                        let ws_state = ws_state.clone();
+                        let onclick = Callback::from(move |_: MouseEvent| {
+                            ws_state.dispatch(WebSocketAction::ToggleGroup(group))
+                        });
+                        let marker = if output.is_collapsed(group) { "▸" } else { "▾" };
+                        return html! {
+                            <span class="group-title" {onclick}>{format!("{marker} {}", line.text)}</span>
+                        };
+                    }
+                    LineKind::Annotation(level) => {
+                        return html! {
+                            <span class={format!("annotation-{level}")}>{&line.text}</span>
+                        };
+                    }
+                    LineKind::Output => {}
+                }
+                let (class_name, id, style) = match line.stream {
+                    StreamType::Stdout => {
+                        let id = format!("line-{}", line_number.unwrap_or_default());
+                        ("stream-stdout", id, "".to_string())
+                    },
+                    StreamType::Stderr => ("stream-stderr", "".to_string(), format!("color: {}", *text_color_stderr)),
+                    StreamType::Meta => ("stream-meta", "".to_string(), "".to_string()),
+                };
+                html!{
+                    <span id={id} class={class_name} style={style} title={line.elapsed.map(|e| format!("+{:.3}s", e.as_secs_f64()))}>{&line.text}</span>
                 }
             })
         }
+        {spacer(shown.len() - last)}
         </div>
         </div>
+            if !output.outputs.is_empty() {
+                <div class="process-outputs">
+                    <Title level={Level::H4}>{"Outputs"}</Title>
+                    <DescriptionList compact=true>
+                        { for output.outputs.iter().map(|(name, value)| html! {
+                            <DescriptionGroup term={name.clone()}><code>{ value }</code></DescriptionGroup>
+                        }) }
+                    </DescriptionList>
+                </div>
+            }
         }
         </div>
     }
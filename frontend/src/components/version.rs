@@ -0,0 +1,66 @@
+use crate::websocket::{compatible, ProcessSocket};
+use dry_console_dto::websocket::Welcome;
+use patternfly_yew::prelude::*;
+use yew::prelude::*;
+
+/// Compares the server's version with this page's, as soon as the
+/// command socket connects, and asks to reload the page when they
+/// differ.
+#[function_component(VersionCheck)]
+pub fn version_check() -> Html {
+    let process_socket = use_context::<ProcessSocket>().expect("no ProcessSocket ctx found");
+    let backdrop = use_backdrop();
+
+    use_effect_with(process_socket, move |process_socket| {
+        process_socket.on_welcome(Callback::from(move |welcome: Welcome| {
+            if let (false, Some(backdrop)) = (compatible(&welcome), &backdrop) {
+                backdrop.open(Backdrop::new(html! {
+                    <ReloadModal server_version={welcome.server_version} />
+                }));
+            }
+        }));
+        process_socket.open();
+    });
+
+    html! {}
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ReloadModalProps {
+    pub server_version: String,
+}
+
+#[function_component(ReloadModal)]
+pub fn reload_modal(props: &ReloadModalProps) -> Html {
+    let backdrop = use_backdrop();
+
+    let onreload = Callback::from(|_| {
+        gloo_utils::window().location().reload().ok();
+    });
+    let onclose = Callback::from(move |_| {
+        if let Some(backdrop) = &backdrop {
+            backdrop.close();
+        }
+    });
+
+    let footer = html! {
+        <Button variant={ButtonVariant::Primary} label="Reload" onclick={onreload} />
+    };
+
+    html! {
+        <Modal
+            title="dry_console was upgraded"
+            variant={ModalVariant::Small}
+            {footer}
+            {onclose}
+        >
+            <p>
+                {format!(
+                    "The server is running version {}, but this page is version {}. Reload the page to keep running commands.",
+                    props.server_version,
+                    env!("CARGO_PKG_VERSION")
+                )}
+            </p>
+        </Modal>
+    }
+}
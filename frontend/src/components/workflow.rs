@@ -0,0 +1,309 @@
+use crate::api;
+use crate::components::loading_state::LoadingState;
+use crate::components::markdown::MarkdownContent;
+use crate::components::profile_select::{use_profile, ProfileSelect};
+use crate::components::terminal::{LineKind, TerminalBuffer, TerminalLine};
+use crate::websocket::ProcessSocket;
+use dry_console_dto::websocket::{ServerMsg, StreamType};
+use dry_console_dto::workflow::{StepCondition, StepState, Workflow};
+use gloo::net::http::Request;
+use itertools::Itertools;
+use patternfly_yew::prelude::*;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use ulid::Ulid;
+use yew::prelude::*;
+
+/// The progress of one run of a workflow.
+#[derive(Debug, Default, PartialEq)]
+struct WorkflowRunState {
+    run_id: Option<Ulid>,
+    steps: HashMap<String, StepState>,
+    /// The step each process belongs to:
+    processes: HashMap<Ulid, String>,
+    /// The transcript of each step. These are shared by every state,
+    /// rather than copied, so revision changes with them.
+    transcripts: HashMap<String, Rc<RefCell<TerminalBuffer>>>,
+    revision: usize,
+    /// None until the run has finished, then whether it succeeded:
+    succeeded: Option<bool>,
+    error: Option<String>,
+}
+
+enum WorkflowAction {
+    Start(Ulid),
+    Message(ServerMsg),
+}
+
+impl WorkflowRunState {
+    fn transcript(&self, step: &str) -> Rc<RefCell<TerminalBuffer>> {
+        self.transcripts.get(step).cloned().unwrap_or_default()
+    }
+
+    /// The transcript of the step that the process belongs to.
+    fn process_transcript(&self, process_id: &Ulid) -> Option<Rc<RefCell<TerminalBuffer>>> {
+        self.processes.get(process_id).map(|s| self.transcript(s))
+    }
+}
+
+impl Reducible for WorkflowRunState {
+    type Action = WorkflowAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        let mut state = WorkflowRunState {
+            run_id: self.run_id,
+            steps: self.steps.clone(),
+            processes: self.processes.clone(),
+            transcripts: self.transcripts.clone(),
+            revision: self.revision + 1,
+            succeeded: self.succeeded,
+            error: self.error.clone(),
+        };
+        match action {
+            WorkflowAction::Start(run_id) => {
+                state = WorkflowRunState {
+                    run_id: Some(run_id),
+                    ..Default::default()
+                };
+            }
+            WorkflowAction::Message(msg) => match msg {
+                ServerMsg::Workflow(run) => {
+                    for step in run.workflow.steps {
+                        state.steps.insert(step.id.clone(), StepState::Pending);
+                        state.transcripts.insert(step.id, Default::default());
+                    }
+                }
+                ServerMsg::WorkflowStep(status) => {
+                    if let Some(process_id) = status.process_id {
+                        state.processes.insert(process_id, status.step.clone());
+                    }
+                    state.steps.insert(status.step, status.state);
+                }
+                msg @ (ServerMsg::ProcessQueued(_)
+                | ServerMsg::Process(_)
+                | ServerMsg::ProcessOutput(_)
+                | ServerMsg::ProcessSetOutput(_)
+                | ServerMsg::ProcessProgress(_)
+                | ServerMsg::ProcessGroup(_)
+                | ServerMsg::ProcessAnnotation(_)
+                | ServerMsg::ProcessComplete(_)) => {
+                    let transcript = msg
+                        .process_id()
+                        .and_then(|id| state.process_transcript(&id));
+                    if let Some(transcript) = transcript {
+                        transcript.borrow_mut().receive(&msg);
+                    }
+                }
+                ServerMsg::ProcessRejected(rejected) => {
+                    if let Some(transcript) = state.process_transcript(&rejected.id) {
+                        transcript.borrow_mut().push_line(
+                            StreamType::Meta,
+                            format!(
+                                "## Not run, unmet requirements: {}",
+                                rejected.unmet_requirements.iter().join("; ")
+                            ),
+                        );
+                    }
+                }
+                ServerMsg::ProcessError(e) => match state.process_transcript(&e.id) {
+                    Some(transcript) => transcript
+                        .borrow_mut()
+                        .push_line(StreamType::Meta, format!("## {}", e.message)),
+                    // The run itself failed (eg. the connection was closed):
+                    None => {
+                        state.error = Some(e.message);
+                        state.succeeded = Some(false);
+                    }
+                },
+                ServerMsg::WorkflowComplete(complete) => {
+                    state.succeeded = Some(complete.succeeded);
+                }
+                _ => return self,
+            },
+        }
+        state.into()
+    }
+}
+
+fn step_icon(state: StepState) -> &'static str {
+    match state {
+        StepState::Pending => "⏳️",
+        StepState::Running => "▶️",
+        StepState::Succeeded => "✅",
+        StepState::Failed => "❌",
+        StepState::Cancelled => "⏹️",
+        StepState::Skipped => "⏭️",
+    }
+}
+
+fn line_class(line: &TerminalLine) -> String {
+    match (line.kind, line.stream) {
+        (LineKind::Group(_), _) => "group-title".to_string(),
+        (LineKind::Annotation(level), _) => format!("annotation-{level}"),
+        (_, StreamType::Stdout) => "stream-stdout".to_string(),
+        (_, StreamType::Stderr) => "stream-stderr".to_string(),
+        (_, StreamType::Meta) => "stream-meta".to_string(),
+    }
+}
+
+fn condition_label(condition: StepCondition) -> &'static str {
+    match condition {
+        StepCondition::OnSuccess => "",
+        StepCondition::OnFailure => " (on failure)",
+        StepCondition::Always => " (always)",
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct WorkflowViewProps {
+    /// The name of the workflow, eg. `install_d_rymcg_tech`.
+    pub name: AttrValue,
+    /// Called once a run has finished, with whether it succeeded.
+    #[prop_or_default]
+    pub on_complete: Callback<bool>,
+}
+
+/// Runs a workflow, showing the progress of each step, and its
+/// transcript.
+#[function_component(WorkflowView)]
+pub fn workflow_view(props: &WorkflowViewProps) -> Html {
+    let process_socket =
+        use_context::<ProcessSocket>().expect("Must be nested inside a ProcessSocket");
+    let workflow = use_state(|| None::<Result<Workflow, String>>);
+    let run = use_reducer(WorkflowRunState::default);
+    let expanded = use_state_eq(|| None::<String>);
+    let profile = use_profile();
+
+    {
+        let workflow = workflow.clone();
+        use_effect_with(props.name.clone(), move |name| {
+            let url = api::url(&format!("/api/workstation/workflow/{name}/"));
+            wasm_bindgen_futures::spawn_local(async move {
+                let fetched = match Request::get(&url).send().await {
+                    Ok(response) if response.ok() => {
+                        response.json::<Workflow>().await.map_err(|e| e.to_string())
+                    }
+                    Ok(response) => Err(response.status_text()),
+                    Err(e) => Err(e.to_string()),
+                };
+                workflow.set(Some(fetched));
+            });
+        });
+    }
+
+    let onrun = {
+        let process_socket = process_socket.clone();
+        let run = run.clone();
+        let name = props.name.clone();
+        let profile = profile.clone();
+        let on_complete = props.on_complete.clone();
+        Callback::from(move |_: MouseEvent| {
+            let on_message = {
+                let run = run.clone();
+                let on_complete = on_complete.clone();
+                Callback::from(move |msg: ServerMsg| {
+                    if let ServerMsg::WorkflowComplete(complete) = &msg {
+                        on_complete.emit(complete.succeeded);
+                    }
+                    run.dispatch(WorkflowAction::Message(msg))
+                })
+            };
+            let run_id =
+                process_socket.run_workflow(&name, BTreeMap::new(), (*profile).clone(), on_message);
+            run.dispatch(WorkflowAction::Start(run_id));
+        })
+    };
+
+    let onstop = {
+        let run_id = run.run_id;
+        Callback::from(move |_: MouseEvent| {
+            if let Some(run_id) = run_id {
+                process_socket.cancel(run_id);
+            }
+        })
+    };
+
+    let workflow = match &*workflow {
+        None => return html! { <LoadingState /> },
+        Some(Err(e)) => {
+            return html! {
+                <Alert inline=true r#type={AlertType::Danger} title={format!("Could not load the workflow: {e}")} />
+            }
+        }
+        Some(Ok(workflow)) => workflow,
+    };
+    let running = run.run_id.is_some() && run.succeeded.is_none();
+
+    let steps = workflow.steps.iter().map(|step| {
+        let state = run
+            .steps
+            .get(&step.id)
+            .copied()
+            .unwrap_or(StepState::Pending);
+        let title = format!(
+            "{} {} — {}{}",
+            step_icon(state),
+            step.id,
+            step.script,
+            condition_label(step.when)
+        );
+        let is_expanded = expanded.as_deref() == Some(step.id.as_str());
+        let onclick = {
+            let expanded = expanded.clone();
+            let id = step.id.clone();
+            Callback::from(move |_| {
+                expanded.set(match expanded.as_deref() == Some(id.as_str()) {
+                    true => None,
+                    false => Some(id.clone()),
+                })
+            })
+        };
+        let transcript = run.transcript(&step.id);
+        let transcript = transcript.borrow();
+        let lines = transcript.shown_lines(true);
+        html_nested! {
+            <AccordionItem {title} expanded={is_expanded} {onclick}>
+                if lines.is_empty() {
+                    <p>{"This step has not run."}</p>
+                } else {
+                    <pre class="workflow-transcript">
+                        { for lines.iter().map(|(_, line)| html! {
+                            <div class={line_class(line)}>{ &line.text }</div>
+                        }) }
+                    </pre>
+                }
+            </AccordionItem>
+        }
+    });
+
+    html! {
+        <Card>
+            <CardTitle><h1>{ &workflow.title }</h1></CardTitle>
+            <CardBody>
+                if !workflow.description.is_empty() {
+                    <MarkdownContent source={workflow.description.clone()} />
+                }
+                <Accordion>
+                    { for steps }
+                </Accordion>
+                <br/>
+                if running {
+                    <Button label="Stop" variant={ButtonVariant::Danger} onclick={onstop} />
+                } else {
+                    <Button label="Run workflow" variant={ButtonVariant::Primary} onclick={onrun} />{" "}
+                    <ProfileSelect selected={(*profile).clone()} onchange={let profile = profile.clone(); move |p| profile.set(p)} />
+                }
+                if let Some(error) = &run.error {
+                    <Alert inline=true r#type={AlertType::Danger} title={error.clone()} />
+                } else if let Some(succeeded) = run.succeeded {
+                    if succeeded {
+                        <Alert inline=true r#type={AlertType::Success} title="The workflow is complete." />
+                    } else {
+                        <Alert inline=true r#type={AlertType::Danger} title="The workflow did not complete." />
+                    }
+                }
+            </CardBody>
+        </Card>
+    }
+}
@@ -1,7 +1,9 @@
+mod api;
 mod app;
 mod components;
 mod pages;
 mod random;
+mod websocket;
 use browser_panic_hook::{CustomBody, IntoPanicHook};
 
 #[cfg(not(debug_assertions))]
@@ -2,5 +2,9 @@ pub mod apps;
 pub mod hello;
 pub mod index;
 pub mod login;
+pub mod logs;
+pub mod profiles;
 pub mod routes;
+pub mod schedules;
+pub mod secrets;
 pub mod workstation;
@@ -12,7 +12,10 @@ fn hello_server() -> Html {
         use_effect(move || {
             if data.is_none() {
                 spawn_local(async move {
-                    let resp = Request::get("/api/session/").send().await.unwrap();
+                    let resp = Request::get(&crate::api::url("/api/session/"))
+                        .send()
+                        .await
+                        .unwrap();
                     let result = {
                         if !resp.ok() {
                             Err(format!(
@@ -1,3 +1,4 @@
+use crate::api;
 use crate::app::{AppRoute, SessionState};
 use gloo::utils::window;
 use gloo_net::http::Request;
@@ -58,7 +59,7 @@ pub fn login(props: &LoginProps) -> Html {
                     let toast = toast.clone();
                     wasm_bindgen_futures::spawn_local(async move {
                         let login_data = LoginData { token };
-                        let response = Request::post("/api/session/login/")
+                        let response = Request::post(&api::url("/api/session/login/"))
                             .header("Content-Type", "application/json")
                             .json(&login_data)
                             .expect("Failed to serialize request")
@@ -112,7 +113,7 @@ pub fn login(props: &LoginProps) -> Html {
 
                     wasm_bindgen_futures::spawn_local(async move {
                         let login_data = LoginData { token: token_clone };
-                        let response = Request::post("/api/session/login/")
+                        let response = Request::post(&api::url("/api/session/login/"))
                             .header("Content-Type", "application/json")
                             .json(&login_data)
                             .expect("Failed to serialize request")
@@ -158,7 +159,9 @@ pub fn login(props: &LoginProps) -> Html {
             let router = Rc::clone(&router);
 
             wasm_bindgen_futures::spawn_local(async move {
-                let response = Request::post("/api/session/logout/").send().await;
+                let response = Request::post(&api::url("/api/session/logout/"))
+                    .send()
+                    .await;
 
                 match response {
                     Ok(res) if res.ok() => {
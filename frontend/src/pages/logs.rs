@@ -0,0 +1,191 @@
+use crate::websocket::use_subscription;
+use dry_console_dto::logs::{LogEvent, LogLevel, LogServerMsg};
+use patternfly_yew::prelude::*;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use strum::IntoEnumIterator;
+use web_sys::js_sys::Date;
+use web_sys::{HtmlElement, HtmlInputElement};
+use yew::prelude::*;
+
+/// Same as the server's ring buffer, so a reconnect never loses history.
+const MAX_EVENTS: usize = 2000;
+
+#[derive(Default, PartialEq)]
+struct LogState {
+    events: VecDeque<LogEvent>,
+    connected: bool,
+}
+
+enum LogAction {
+    Connected,
+    Disconnected,
+    Append(LogEvent),
+}
+
+impl Reducible for LogState {
+    type Action = LogAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        match action {
+            LogAction::Connected => LogState {
+                // The server resends its whole buffer on connect:
+                events: VecDeque::new(),
+                connected: true,
+            },
+            LogAction::Disconnected => LogState {
+                events: self.events.clone(),
+                connected: false,
+            },
+            LogAction::Append(event) => {
+                let mut events = self.events.clone();
+                if events.len() == MAX_EVENTS {
+                    events.pop_front();
+                }
+                events.push_back(event);
+                LogState {
+                    events,
+                    connected: self.connected,
+                }
+            }
+        }
+        .into()
+    }
+}
+
+fn level_color(level: LogLevel) -> Color {
+    match level {
+        LogLevel::Error => Color::Red,
+        LogLevel::Warn => Color::Orange,
+        LogLevel::Info => Color::Blue,
+        LogLevel::Debug => Color::Grey,
+        LogLevel::Trace => Color::Grey,
+    }
+}
+
+fn format_time(timestamp_ms: u64) -> String {
+    let date = Date::new(&(timestamp_ms as f64).into());
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        date.get_hours(),
+        date.get_minutes(),
+        date.get_seconds(),
+        date.get_milliseconds()
+    )
+}
+
+#[function_component(Logs)]
+pub fn logs() -> Html {
+    let state = use_reducer(LogState::default);
+    // Show events of this level, or more severe:
+    let level = use_state_eq(|| LogLevel::Info);
+    let search = use_state_eq(String::new);
+    let follow = use_state_eq(|| true);
+    let content_ref = use_node_ref();
+
+    // Stream the server logs for as long as the page is open:
+    use_subscription(
+        "/api/logs/",
+        {
+            let state = state.clone();
+            Callback::from(move |connected: bool| {
+                state.dispatch(match connected {
+                    true => LogAction::Connected,
+                    false => LogAction::Disconnected,
+                })
+            })
+        },
+        {
+            let state = state.clone();
+            Callback::from(move |msg| {
+                if let LogServerMsg::Log(event) = msg {
+                    state.dispatch(LogAction::Append(event))
+                }
+            })
+        },
+    );
+
+    // Keep the newest event in view, unless the user scrolled up:
+    {
+        let content_ref = content_ref.clone();
+        let follow = *follow;
+        use_effect_with((state.events.len(), follow), move |_| {
+            if follow {
+                if let Some(content) = content_ref.cast::<HtmlElement>() {
+                    content.set_scroll_top(content.scroll_height());
+                }
+            }
+        });
+    }
+    let onscroll = {
+        let follow = follow.clone();
+        Callback::from(move |e: Event| {
+            let content: HtmlElement = e.target_unchecked_into();
+            follow
+                .set(content.scroll_top() + content.client_height() >= content.scroll_height() - 5);
+        })
+    };
+
+    let oninput = {
+        let search = search.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            search.set(input.value());
+        })
+    };
+
+    let shown: Vec<&LogEvent> = state
+        .events
+        .iter()
+        .filter(|e| e.level <= *level)
+        .filter(|e| search.is_empty() || e.matches(&search))
+        .collect();
+
+    html! {
+        <PageSection>
+            <Toolbar>
+                <ToolbarContent>
+                    <ToolbarItem>
+                        <ToggleGroup>
+                            { for LogLevel::iter().map(|l| {
+                                let level = level.clone();
+                                html_nested! {
+                                    <ToggleGroupItem
+                                        key={l.as_ref()}
+                                        text={l.to_string()}
+                                        selected={l == *level}
+                                        onchange={move |_| level.set(l)}
+                                    />
+                                }
+                            }) }
+                        </ToggleGroup>
+                    </ToolbarItem>
+                    <ToolbarItem>
+                        <TextInput
+                            r#type={TextInputType::Search}
+                            placeholder="Search"
+                            value={(*search).clone()}
+                            {oninput}
+                        />
+                    </ToolbarItem>
+                    <ToolbarItem>
+                        { format!("{} of {} events", shown.len(), state.events.len()) }
+                        if !state.connected {
+                            {" (disconnected)"}
+                        }
+                    </ToolbarItem>
+                </ToolbarContent>
+            </Toolbar>
+            <div class="log-viewer" ref={content_ref} {onscroll}>
+                { for shown.into_iter().map(|event| html! {
+                    <div class="log-event">
+                        <span class="log-time">{ format_time(event.timestamp_ms) }</span>
+                        <Label compact=true color={level_color(event.level)} label={event.level.to_string()} />
+                        <span class="log-target">{ &event.target }</span>
+                        <span class="log-message">{ &event.message }</span>
+                    </div>
+                }) }
+            </div>
+        </PageSection>
+    }
+}
@@ -0,0 +1,255 @@
+use crate::api;
+use crate::components::loading_state::LoadingState;
+use crate::components::profile_select::fetch_profiles;
+use dry_console_dto::profile::EnvProfile;
+use gloo::net::http::{Request, RequestBuilder};
+use patternfly_yew::prelude::*;
+use std::collections::BTreeMap;
+use yew::prelude::*;
+
+async fn send(request: RequestBuilder, profile: Option<&EnvProfile>) -> Result<(), String> {
+    let response = match profile {
+        Some(profile) => {
+            request
+                .json(profile)
+                .map_err(|e| e.to_string())?
+                .send()
+                .await
+        }
+        None => request.send().await,
+    }
+    .map_err(|e| e.to_string())?;
+    match response.ok() {
+        true => Ok(()),
+        false => Err(response
+            .text()
+            .await
+            .unwrap_or_else(|_| response.status_text())),
+    }
+}
+
+async fn fetch_env(name: &str) -> Result<BTreeMap<String, String>, String> {
+    let url = api::url(&format!("/api/profiles/{name}/env/"));
+    let response = Request::get(&url).send().await.map_err(|e| e.to_string())?;
+    match response.ok() {
+        true => response.json().await.map_err(|e| e.to_string()),
+        false => Err(response.status_text()),
+    }
+}
+
+/// The variables, one `NAME=value` per line.
+fn format_vars(vars: &BTreeMap<String, String>) -> String {
+    vars.iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn parse_vars(text: &str) -> Result<BTreeMap<String, String>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_once('=') {
+            Some((name, value)) => Ok((name.trim().to_string(), value.to_string())),
+            None => Err(format!("Expected NAME=value, not: {line}")),
+        })
+        .collect()
+}
+
+#[derive(Properties, PartialEq)]
+struct ProfileEditorProps {
+    /// With its secrets masked (as the server sends it).
+    profile: EnvProfile,
+    on_changed: Callback<()>,
+}
+
+/// Edits one profile. Masked secrets are left as they are, unless they
+/// are changed.
+#[function_component(ProfileEditor)]
+fn profile_editor(props: &ProfileEditorProps) -> Html {
+    let description = use_state(|| props.profile.description.clone());
+    let vars = use_state(|| format_vars(&props.profile.vars));
+    let secrets = use_state(|| {
+        props
+            .profile
+            .secrets
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ")
+    });
+    let error = use_state_eq(|| None::<String>);
+    let env = use_state(|| None::<BTreeMap<String, String>>);
+    let url = api::url(&format!("/api/profiles/{}/", props.profile.name));
+
+    let onsave = {
+        let (description, vars, secrets) = (description.clone(), vars.clone(), secrets.clone());
+        let (error, env) = (error.clone(), env.clone());
+        let on_changed = props.on_changed.clone();
+        let name = props.profile.name.clone();
+        let url = url.clone();
+        Callback::from(move |_: MouseEvent| {
+            let vars = match parse_vars(&vars) {
+                Ok(vars) => vars,
+                Err(e) => return error.set(Some(e)),
+            };
+            let profile = EnvProfile {
+                name: name.clone(),
+                description: (*description).clone(),
+                vars,
+                secrets: secrets
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            };
+            let (error, env, on_changed, url) =
+                (error.clone(), env.clone(), on_changed.clone(), url.clone());
+            wasm_bindgen_futures::spawn_local(async move {
+                match send(Request::put(&url), Some(&profile)).await {
+                    Ok(()) => {
+                        error.set(None);
+                        env.set(None);
+                        on_changed.emit(());
+                    }
+                    Err(e) => error.set(Some(e)),
+                }
+            });
+        })
+    };
+    let ondelete = {
+        let error = error.clone();
+        let on_changed = props.on_changed.clone();
+        Callback::from(move |_: MouseEvent| {
+            let (error, on_changed, url) = (error.clone(), on_changed.clone(), url.clone());
+            wasm_bindgen_futures::spawn_local(async move {
+                match send(Request::delete(&url), None).await {
+                    Ok(()) => on_changed.emit(()),
+                    Err(e) => error.set(Some(e)),
+                }
+            });
+        })
+    };
+    let onshowenv = {
+        let (error, env) = (error.clone(), env.clone());
+        let name = props.profile.name.clone();
+        Callback::from(move |_: MouseEvent| {
+            let (error, env, name) = (error.clone(), env.clone(), name.clone());
+            wasm_bindgen_futures::spawn_local(async move {
+                match fetch_env(&name).await {
+                    Ok(fetched) => env.set(Some(fetched)),
+                    Err(e) => error.set(Some(e)),
+                }
+            });
+        })
+    };
+
+    html! {
+        <Card>
+            <CardTitle><h2>{ &props.profile.name }</h2></CardTitle>
+            <CardBody>
+                if let Some(e) = &*error {
+                    <Alert inline=true r#type={AlertType::Danger} title={e.clone()} />
+                }
+                <Form>
+                    <FormGroup label="Description">
+                        <TextInput value={(*description).clone()} onchange={let description = description.clone(); move |value| description.set(value)} />
+                    </FormGroup>
+                    <FormGroup label="Variables (NAME=value, one per line)">
+                        <TextArea value={(*vars).clone()} rows={6} onchange={let vars = vars.clone(); move |value| vars.set(value)} />
+                    </FormGroup>
+                    <FormGroup label="Secrets (variables whose values are never shown, besides those named like PASSWORD or TOKEN)">
+                        <TextInput value={(*secrets).clone()} onchange={let secrets = secrets.clone(); move |value| secrets.set(value)} />
+                    </FormGroup>
+                </Form>
+                <br/>
+                <Button label="Save" variant={ButtonVariant::Primary} onclick={onsave} />{" "}
+                <Button label="Show environment" variant={ButtonVariant::Secondary} onclick={onshowenv} />{" "}
+                <Button label="Delete" variant={ButtonVariant::Danger} onclick={ondelete} />
+                if let Some(env) = &*env {
+                    <pre class="profile-env">{ format_vars(env) }</pre>
+                }
+            </CardBody>
+        </Card>
+    }
+}
+
+#[function_component(Profiles)]
+pub fn profiles() -> Html {
+    let profiles = use_state(|| None::<Result<Vec<EnvProfile>, String>>);
+    // Bumped to fetch the profiles again:
+    let reload = use_state(|| 0);
+    let name = use_state(String::new);
+    let error = use_state_eq(|| None::<String>);
+
+    {
+        let profiles = profiles.clone();
+        use_effect_with(*reload, move |_| {
+            wasm_bindgen_futures::spawn_local(async move {
+                profiles.set(Some(fetch_profiles().await));
+            });
+        });
+    }
+    let on_changed = {
+        let reload = reload.clone();
+        Callback::from(move |_| reload.set(*reload + 1))
+    };
+
+    let onsubmit = {
+        let (name, error) = (name.clone(), error.clone());
+        let on_changed = on_changed.clone();
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            let profile = EnvProfile {
+                name: (*name).clone(),
+                ..Default::default()
+            };
+            let url = api::url(&format!("/api/profiles/{}/", profile.name));
+            let (name, error, on_changed) = (name.clone(), error.clone(), on_changed.clone());
+            wasm_bindgen_futures::spawn_local(async move {
+                match send(Request::put(&url), Some(&profile)).await {
+                    Ok(()) => {
+                        name.set(String::new());
+                        error.set(None);
+                        on_changed.emit(());
+                    }
+                    Err(e) => error.set(Some(e)),
+                }
+            });
+        })
+    };
+
+    let editors = match &*profiles {
+        None => html! { <LoadingState /> },
+        Some(Err(e)) => html! {
+            <Alert inline=true r#type={AlertType::Danger} title={format!("Could not load the profiles: {e}")} />
+        },
+        Some(Ok(profiles)) if profiles.is_empty() => {
+            html! { <p>{"There are no profiles yet."}</p> }
+        }
+        Some(Ok(profiles)) => html! {
+            { for profiles.iter().map(|profile| html! {
+                // (Keyed by the whole profile, so that the editor is reset when it is saved.)
+                <ProfileEditor key={format!("{profile:?}")} profile={profile.clone()} on_changed={on_changed.clone()} />
+            }) }
+        },
+    };
+
+    html! {
+        <PageSection>
+            <Title>{"Environment profiles"}</Title>
+            <p>{"Scripts run with only the basic variables of the server's environment (PATH, HOME, etc.), and those of the profile they are run with (eg. DOCKER_CONTEXT, ROOT_DIR or HTTP_PROXY)."}</p>
+            { editors }
+            <Form id="profile-form" {onsubmit}>
+                if let Some(e) = &*error {
+                    <Alert inline=true r#type={AlertType::Danger} title={e.clone()} />
+                }
+                <FormGroup label="Name">
+                    <TextInput value={(*name).clone()} onchange={let name = name.clone(); move |value| name.set(value)} />
+                </FormGroup>
+                <Button label="Add profile" variant={ButtonVariant::Primary} r#type={ButtonType::Submit} />
+            </Form>
+        </PageSection>
+    }
+}
@@ -0,0 +1,221 @@
+use crate::api;
+use crate::components::loading_state::LoadingState;
+use crate::components::profile_select::ProfileSelect;
+use crate::websocket::use_event;
+use dry_console_dto::events::Event;
+use dry_console_dto::history::RunRecord;
+use dry_console_dto::schedule::{Schedule, ScheduleStatus};
+use gloo::net::http::{Request, RequestBuilder};
+use patternfly_yew::prelude::*;
+use std::collections::BTreeMap;
+use wasm_bindgen::JsValue;
+use web_sys::js_sys::Date;
+use yew::prelude::*;
+
+async fn fetch_schedules() -> Result<Vec<ScheduleStatus>, String> {
+    let response = Request::get(&api::url("/api/schedules/"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.ok() {
+        true => response.json().await.map_err(|e| e.to_string()),
+        false => Err(response.status_text()),
+    }
+}
+
+/// POST (or DELETE) to the schedules API, with an optional new schedule.
+async fn send(request: RequestBuilder, schedule: Option<Schedule>) -> Result<(), String> {
+    let response = match schedule {
+        Some(schedule) => {
+            request
+                .json(&schedule)
+                .map_err(|e| e.to_string())?
+                .send()
+                .await
+        }
+        None => request.send().await,
+    }
+    .map_err(|e| e.to_string())?;
+    match response.ok() {
+        true => Ok(()),
+        false => Err(response
+            .text()
+            .await
+            .unwrap_or_else(|_| response.status_text())),
+    }
+}
+
+fn format_time(timestamp_ms: u64) -> String {
+    Date::new(&(timestamp_ms as f64).into())
+        .to_locale_string("default", &JsValue::UNDEFINED)
+        .into()
+}
+
+fn last_run_label(record: &RunRecord) -> Html {
+    let (color, text) = match (record.finished_ms, record.unmet_requirements.is_empty()) {
+        (None, _) => (Color::Blue, "Running".to_string()),
+        (Some(_), false) => (Color::Orange, "Rejected".to_string()),
+        (Some(_), true) if record.succeeded() => (Color::Green, "Succeeded".to_string()),
+        (Some(_), true) => match record.code {
+            Some(code) => (Color::Red, format!("Failed ({code})")),
+            None => (Color::Red, "Failed".to_string()),
+        },
+    };
+    html! {
+        <>
+            <Label compact=true {color} label={text} />
+            {" "}{ format_time(record.started_ms) }
+        </>
+    }
+}
+
+#[function_component(Schedules)]
+pub fn schedules() -> Html {
+    let schedules = use_state(|| None::<Result<Vec<ScheduleStatus>, String>>);
+    // Bumped to fetch the schedules again:
+    let reload = use_state(|| 0);
+    let error = use_state_eq(|| None::<String>);
+    let name = use_state(String::new);
+    let cron = use_state(String::new);
+    let script = use_state(String::new);
+    let profile = use_state(|| None::<String>);
+
+    {
+        let schedules = schedules.clone();
+        use_effect_with(*reload, move |_| {
+            wasm_bindgen_futures::spawn_local(async move {
+                schedules.set(Some(fetch_schedules().await));
+            });
+        });
+    }
+    // A scheduled run may start or finish at any time:
+    {
+        let reload = reload.clone();
+        use_event(Callback::from(move |event| {
+            if let Event::ProcessStarted(_) | Event::ProcessFinished(_) = event {
+                reload.set(*reload + 1);
+            }
+        }));
+    }
+
+    let act = {
+        let reload = reload.clone();
+        let error = error.clone();
+        Callback::from(
+            move |(request, schedule): (RequestBuilder, Option<Schedule>)| {
+                let reload = reload.clone();
+                let error = error.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    match send(request, schedule).await {
+                        Ok(()) => error.set(None),
+                        Err(e) => error.set(Some(e)),
+                    }
+                    reload.set(*reload + 1);
+                });
+            },
+        )
+    };
+
+    let onsubmit = {
+        let act = act.clone();
+        let (name, cron, script) = (name.clone(), cron.clone(), script.clone());
+        let profile = profile.clone();
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            let schedule = Schedule {
+                name: (*name).clone(),
+                cron: (*cron).clone(),
+                script: (*script).clone(),
+                params: BTreeMap::new(),
+                profile: (*profile).clone(),
+                paused: false,
+            };
+            act.emit((Request::post(&api::url("/api/schedules/")), Some(schedule)));
+        })
+    };
+
+    let rows = match &*schedules {
+        None => html! { <LoadingState /> },
+        Some(Err(e)) => html! {
+            <Alert inline=true r#type={AlertType::Danger} title={format!("Could not load the schedules: {e}")} />
+        },
+        Some(Ok(schedules)) if schedules.is_empty() => {
+            html! { <p>{"There are no schedules yet."}</p> }
+        }
+        Some(Ok(schedules)) => html! {
+            <List r#type={ListType::Bordered}>
+                { for schedules.iter().map(|status| {
+                    let schedule = &status.schedule;
+                    let path = |action: &str| api::url(&format!("/api/schedules/{}/{action}", schedule.name));
+                    let (pause_label, pause_path) = match schedule.paused {
+                        true => ("Resume", path("resume/")),
+                        false => ("Pause", path("pause/")),
+                    };
+                    let onpause = act.reform(move |_: MouseEvent| (Request::post(&pause_path), None));
+                    let run_path = path("run/");
+                    let onrun = act.reform(move |_: MouseEvent| (Request::post(&run_path), None));
+                    let delete_path = path("");
+                    let ondelete = act.reform(move |_: MouseEvent| (Request::delete(&delete_path), None));
+                    html_nested! {
+                        <ListItem>
+                            <strong>{ &schedule.name }</strong>{" "}
+                            <code>{ &schedule.cron }</code>{" runs "}<code>{ &schedule.script }</code>
+                            if let Some(profile) = &schedule.profile {
+                                {" with the profile "}<code>{ profile }</code>
+                            }
+                            <DescriptionList>
+                                <DescriptionGroup term="Next run">
+                                    { match (schedule.paused, status.next_run_ms) {
+                                        (true, _) => "Paused".to_string(),
+                                        (false, Some(next)) => format_time(next),
+                                        (false, None) => "Never".to_string(),
+                                    } }
+                                </DescriptionGroup>
+                                <DescriptionGroup term="Last run">
+                                    { match &status.last_run {
+                                        Some(record) => last_run_label(record),
+                                        None => html! { "Never" },
+                                    } }
+                                </DescriptionGroup>
+                                if let Some(record) = status.last_run.as_ref().filter(|r| !r.output_tail.is_empty()) {
+                                    <DescriptionGroup term="Output">
+                                        <pre class="schedule-output">{ record.output_tail.join("\n") }</pre>
+                                    </DescriptionGroup>
+                                }
+                            </DescriptionList>
+                            <Button label="Run now" variant={ButtonVariant::Primary} disabled={status.running} onclick={onrun} />{" "}
+                            <Button label={pause_label} variant={ButtonVariant::Secondary} onclick={onpause} />{" "}
+                            <Button label="Delete" variant={ButtonVariant::Danger} onclick={ondelete} />
+                        </ListItem>
+                    }
+                }) }
+            </List>
+        },
+    };
+
+    html! {
+        <PageSection>
+            <Title>{"Schedules"}</Title>
+            <p>{"Library scripts that run by themselves, while the server is up, whenever their cron expression is due (in the server's local time)."}</p>
+            if let Some(e) = &*error {
+                <Alert inline=true r#type={AlertType::Danger} title={e.clone()} />
+            }
+            { rows }
+            <Form id="schedule-form" {onsubmit}>
+                <FormGroup label="Name">
+                    <TextInput value={(*name).clone()} onchange={let name = name.clone(); move |value| name.set(value)} />
+                </FormGroup>
+                <FormGroup label="Cron expression (minute hour day month weekday)">
+                    <TextInput placeholder="0 3 * * SUN" value={(*cron).clone()} onchange={let cron = cron.clone(); move |value| cron.set(value)} />
+                </FormGroup>
+                <FormGroup label="Library script">
+                    <TextInput placeholder="PruneImages" value={(*script).clone()} onchange={let script = script.clone(); move |value| script.set(value)} />
+                </FormGroup>
+                <FormGroup label="Environment profile">
+                    <ProfileSelect selected={(*profile).clone()} onchange={let profile = profile.clone(); move |p| profile.set(p)} />
+                </FormGroup>
+                <Button label="Add schedule" variant={ButtonVariant::Primary} r#type={ButtonType::Submit} />
+            </Form>
+        </PageSection>
+    }
+}
@@ -0,0 +1,197 @@
+use crate::api;
+use crate::components::loading_state::LoadingState;
+use dry_console_dto::secrets::{SecretValue, VaultPassphrase, VaultStatus};
+use gloo::net::http::{Request, RequestBuilder};
+use patternfly_yew::prelude::*;
+use serde::Serialize;
+use yew::prelude::*;
+
+async fn fetch_status() -> Result<VaultStatus, String> {
+    send(Request::get(&api::url("/api/secrets/")), None::<&()>).await
+}
+
+/// Send the request (with the body, if any), and return the new status
+/// of the vault.
+async fn send<T: Serialize>(
+    request: RequestBuilder,
+    body: Option<&T>,
+) -> Result<VaultStatus, String> {
+    let response = match body {
+        Some(body) => request.json(body).map_err(|e| e.to_string())?.send().await,
+        None => request.send().await,
+    }
+    .map_err(|e| e.to_string())?;
+    match response.ok() {
+        true => response.json().await.map_err(|e| e.to_string()),
+        false => Err(response
+            .text()
+            .await
+            .unwrap_or_else(|_| response.status_text())),
+    }
+}
+
+#[function_component(Secrets)]
+pub fn secrets() -> Html {
+    let status = use_state(|| None::<Result<VaultStatus, String>>);
+    let passphrase = use_state(String::new);
+    let confirm = use_state(String::new);
+    let name = use_state(String::new);
+    let value = use_state(String::new);
+    let error = use_state_eq(|| None::<String>);
+    let unlocking = use_state_eq(|| false);
+
+    {
+        let status = status.clone();
+        use_effect_with((), move |_| {
+            wasm_bindgen_futures::spawn_local(async move {
+                status.set(Some(fetch_status().await));
+            });
+        });
+    }
+    // Run the request, and show the new status of the vault (or the error):
+    let update = {
+        let (status, error) = (status.clone(), error.clone());
+        Callback::from(
+            move |(request, body): (RequestBuilder, Option<serde_json::Value>)| {
+                let (status, error) = (status.clone(), error.clone());
+                wasm_bindgen_futures::spawn_local(async move {
+                    match send(request, body.as_ref()).await {
+                        Ok(new_status) => {
+                            error.set(None);
+                            status.set(Some(Ok(new_status)));
+                        }
+                        Err(e) => error.set(Some(e)),
+                    }
+                });
+            },
+        )
+    };
+
+    let onunlock = {
+        let (passphrase, confirm) = (passphrase.clone(), confirm.clone());
+        let (unlocking, error, status) = (unlocking.clone(), error.clone(), status.clone());
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            let exists = matches!(&*status, Some(Ok(status)) if status.exists);
+            let body = VaultPassphrase {
+                passphrase: (*passphrase).clone(),
+                confirm: (!exists).then(|| (*confirm).clone()),
+            };
+            let (passphrase, confirm, unlocking, error, status) = (
+                passphrase.clone(),
+                confirm.clone(),
+                unlocking.clone(),
+                error.clone(),
+                status.clone(),
+            );
+            unlocking.set(true);
+            wasm_bindgen_futures::spawn_local(async move {
+                match send(
+                    Request::post(&api::url("/api/secrets/unlock/")),
+                    Some(&body),
+                )
+                .await
+                {
+                    Ok(new_status) => {
+                        passphrase.set(String::new());
+                        confirm.set(String::new());
+                        error.set(None);
+                        status.set(Some(Ok(new_status)));
+                    }
+                    Err(e) => error.set(Some(e)),
+                }
+                unlocking.set(false);
+            });
+        })
+    };
+    let onlock = {
+        let update = update.clone();
+        Callback::from(move |_: MouseEvent| {
+            update.emit((Request::post(&api::url("/api/secrets/lock/")), None));
+        })
+    };
+    let onsave = {
+        let (name, value, update) = (name.clone(), value.clone(), update.clone());
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            let body = SecretValue {
+                value: (*value).clone(),
+            };
+            let url = api::url(&format!("/api/secrets/{}/", *name));
+            update.emit((Request::put(&url), serde_json::to_value(body).ok()));
+            name.set(String::new());
+            value.set(String::new());
+        })
+    };
+
+    let vault = match &*status {
+        None => html! { <LoadingState /> },
+        Some(Err(e)) => html! {
+            <Alert inline=true r#type={AlertType::Danger} title={format!("Could not load the vault: {e}")} />
+        },
+        Some(Ok(status)) if !status.unlocked => html! {
+            <Form id="vault-unlock-form" onsubmit={onunlock}>
+                <p>{ match status.exists {
+                    true => "The vault is locked. Scripts that need its secrets will not run until it is unlocked.",
+                    false => "There is no vault yet. It is created with the passphrase that it is first unlocked with.",
+                } }</p>
+                <FormGroup label="Passphrase">
+                    <TextInput r#type={TextInputType::Password} value={(*passphrase).clone()} onchange={let passphrase = passphrase.clone(); move |value| passphrase.set(value)} />
+                </FormGroup>
+                if !status.exists {
+                    <FormGroup label="Confirm passphrase">
+                        <TextInput r#type={TextInputType::Password} value={(*confirm).clone()} onchange={let confirm = confirm.clone(); move |value| confirm.set(value)} />
+                    </FormGroup>
+                }
+                <Button label={if status.exists { "Unlock" } else { "Create vault" }} variant={ButtonVariant::Primary} r#type={ButtonType::Submit} disabled={*unlocking} />
+            </Form>
+        },
+        Some(Ok(status)) => html! {
+            <>
+                <p>
+                    {"The vault is unlocked, until it is locked again or the server stops. "}
+                    <Button label="Lock" variant={ButtonVariant::Secondary} onclick={onlock} />
+                </p>
+                if status.names.is_empty() {
+                    <p>{"There are no secrets yet."}</p>
+                } else {
+                    <ul class="secret-names">
+                        { for status.names.iter().map(|name| {
+                            let url = api::url(&format!("/api/secrets/{name}/"));
+                            let update = update.clone();
+                            let ondelete = Callback::from(move |_: MouseEvent| {
+                                update.emit((Request::delete(&url), None));
+                            });
+                            html! {
+                                <li>
+                                    <code>{ name }</code>{" "}
+                                    <Button label="Delete" variant={ButtonVariant::Link} onclick={ondelete} />
+                                </li>
+                            }
+                        }) }
+                    </ul>
+                }
+                <Form id="secret-form" onsubmit={onsave}>
+                    <FormGroup label="Name">
+                        <TextInput value={(*name).clone()} onchange={let name = name.clone(); move |value| name.set(value)} />
+                    </FormGroup>
+                    <FormGroup label="Value">
+                        <TextInput r#type={TextInputType::Password} value={(*value).clone()} onchange={let value = value.clone(); move |input| value.set(input)} />
+                    </FormGroup>
+                    <Button label="Save secret" variant={ButtonVariant::Primary} r#type={ButtonType::Submit} />
+                </Form>
+            </>
+        },
+    };
+
+    html! {
+        <PageSection>
+            <Title>{"Secrets"}</Title>
+            <p>{"Secrets are kept encrypted on disk, and passed to the scripts that ask for them (with a "}<code>{"# secrets: NAME, ..."}</code>{" line), as environment variables. Their values are never shown, and are replaced with **** in the output of scripts."}</p>
+            if let Some(e) = &*error {
+                <Alert inline=true r#type={AlertType::Danger} title={e.clone()} />
+            }
+            { vault }
+        </PageSection>
+    }
+}
@@ -1,3 +1,7 @@
+use crate::api;
+use crate::websocket::use_event;
+use dry_console_dto::events::Event;
+use dry_console_dto::sudo::SudoState;
 use dry_console_dto::workstation::{Platform, WorkstationState, WorkstationUser};
 use gloo::console::{debug};
 use gloo::net::http::Request;
@@ -21,11 +25,6 @@ pub struct SystemInfo {
     user: WorkstationUser,
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct SystemInfoContext {
-    pub system_info: UseStateHandle<Option<Rc<SystemInfo>>>,
-}
-
 #[derive(Clone, Debug, Properties, PartialEq)]
 struct SystemInfoProps {
     children: Children,
@@ -38,7 +37,7 @@ fn system_info_provider(props: &SystemInfoProps) -> Html {
         let system_info = system_info.clone();
         wasm_bindgen_futures::spawn_local(async move {
             if system_info.is_none() {
-                let fetched_data: WorkstationState = Request::get("/api/workstation/")
+                let fetched_data: WorkstationState = Request::get(&api::url("/api/workstation/"))
                     .send()
                     .await
                     .expect("Failed to fetch data")
@@ -60,6 +59,21 @@ fn system_info_provider(props: &SystemInfoProps) -> Html {
         });
     }
 
+    // Sudo may be acquired, or lost, at any time:
+    {
+        let system_info = system_info.clone();
+        use_event(Callback::from(move |event| {
+            if let (Event::SudoStatus(status), Some(info)) = (event, &*system_info) {
+                let can_sudo = status.state == SudoState::Active;
+                if info.user.can_sudo != can_sudo {
+                    let mut info = (**info).clone();
+                    info.user.can_sudo = can_sudo;
+                    system_info.set(Some(Rc::new(info)));
+                }
+            }
+        }));
+    }
+
     let context =
         use_context::<UseStateHandle<Option<Rc<SystemInfo>>>>().expect("context not found");
     context.set((*system_info).clone());
@@ -1,8 +1,11 @@
+use crate::api;
 use crate::components::manual_intervention::ManualIntervention;
 use crate::components::terminal::TerminalOutput;
 use crate::components::ButtonLink;
 use crate::pages::workstation::WorkstationTab;
+use crate::websocket::use_event;
 use anyhow::anyhow;
+use dry_console_dto::events::Event;
 use dry_console_dto::workstation::WorkstationPackage;
 use gloo::net::http::Request;
 use patternfly_yew::prelude::*;
@@ -40,7 +43,7 @@ struct WorkstationDependency {
 }
 impl WorkstationDependency {
     async fn get_installed_state(&mut self) -> Result<WorkstationDependency, anyhow::Error> {
-        let url = format!("/api/workstation/dependency/{}/", self.name);
+        let url = api::url(&format!("/api/workstation/dependency/{}/", self.name));
 
         let response = match Request::get(&url).send().await {
             Ok(r) => r,
@@ -66,76 +69,6 @@ impl WorkstationDependency {
     }
 }
 
-#[derive(Properties, PartialEq, Clone)]
-struct DependencyItemProps {
-    dependency: WorkstationDependency,
-    is_expanded: bool,
-    on_toggle: Callback<String>,
-}
-#[function_component(DependencyItem)]
-fn dependency_item(props: &DependencyItemProps) -> Html {
-    let title = match props.dependency.clone().validate() {
-        Some(true) => format!("✅ {}", props.dependency.name),
-        Some(false) => format!("⚠️ {}", props.dependency.name),
-        None => format!("⏳️ {}", props.dependency.name),
-    };
-
-    let on_toggle = {
-        let name = props.dependency.name.clone();
-        let toggle = props.on_toggle.clone();
-        Callback::from(move |_| toggle.emit(name.clone()))
-    };
-
-    html_nested! {
-        <AccordionItem title={title} expanded={props.is_expanded} onclick={on_toggle}>
-            <div>
-                { match props.dependency.installed {
-                    None => html! {"Dependency check is pending ..."},
-                    Some(false) => {
-                        html! { format!("{} is not installed", props.dependency.name) }
-                    }
-                    Some(true) => {//
-                        match props.dependency.clone().validate() {
-                            Some(is_valid) => {
-                                match is_valid {
-                                    true => {
-                                        html! {
-                                            <DescriptionList>
-                                                <DescriptionGroup term="Path">
-                                                    <code>
-                                                    { props.dependency.path.to_string() }
-                                                    </code>
-                                                </DescriptionGroup>
-                                                <DescriptionGroup term="Version">
-                                                    <code>
-                                                    { props.dependency.version.to_string() }
-                                                    </code>
-                                                </DescriptionGroup>
-                                            </DescriptionList>
-                                        }
-                                    },
-                                    false => {
-                                        if props.dependency.clone().path.is_empty() {
-                                            html! {"Validation error: path is empty"}
-                                        } else if props.dependency.clone().version.is_empty() {
-                                            html! {"Validation error: version is empty"}
-                                        } else {
-                                            html! {"Validation error"}
-                                        }
-                                    }
-                                }
-
-                            },
-                            None => {html! {"Dependency check is pending ..."}}
-                        }
-                    }
-                }}
-            </div>
-        </AccordionItem>
-    }
-    .into() // Convert the VChild into a VNode
-}
-
 #[function_component(LoadingState)]
 fn loading_state() -> Html {
     html! {
@@ -197,7 +130,7 @@ fn create_fetch_dependencies_callback(
         has_fetched.set(true);
 
         spawn_local(async move {
-            match gloo_net::http::Request::get("/api/workstation/dependencies/")
+            match gloo_net::http::Request::get(&api::url("/api/workstation/dependencies/"))
                 .send()
                 .await
             {
@@ -360,6 +293,16 @@ pub fn dependency_list(props: &DependencyListProps) -> Html {
         });
     }
 
+    // Check again whenever the server sees a dependency installed or removed:
+    {
+        let has_fetched = has_fetched.clone();
+        use_event(Callback::from(move |event| {
+            if let Event::DependencyChanged(_) = event {
+                has_fetched.set(false);
+            }
+        }));
+    }
+
     // on_click only resets the `has_fetched` state
     let on_click = {
         let has_fetched = has_fetched.clone();
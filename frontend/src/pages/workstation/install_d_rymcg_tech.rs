@@ -1,8 +1,47 @@
-use crate::components::terminal::{TerminalOutput, TerminalOutputProps};
+use crate::api;
+use crate::components::loading_state::LoadingState;
+use crate::components::profile_select::stored_profile;
+use crate::components::workflow::WorkflowView;
 use crate::pages::workstation::WorkstationTab;
+use dry_console_dto::workstation::{DRymcgTechCheckout, DRymcgTechInstall};
+use gloo::net::http::Request;
 use patternfly_yew::prelude::*;
 use yew::prelude::*;
 
+/// Where d.rymcg.tech is installed, as the installer would see it with
+/// the profile that was last picked.
+async fn fetch_install() -> Result<DRymcgTechInstall, String> {
+    let url = match stored_profile() {
+        Some(profile) => format!("/api/workstation/d_rymcg_tech/?profile={profile}"),
+        None => "/api/workstation/d_rymcg_tech/".to_string(),
+    };
+    let response = Request::get(&api::url(&url))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.ok() {
+        true => response.json().await.map_err(|e| e.to_string()),
+        false => Err(response.status_text()),
+    }
+}
+
+fn checkout_details(checkout: &DRymcgTechCheckout) -> Html {
+    html! {
+        <DescriptionList compact=true>
+            <DescriptionGroup term="Remote">
+                { checkout.remote.clone().unwrap_or_else(|| "(none)".to_string()) }
+            </DescriptionGroup>
+            <DescriptionGroup term="Branch">
+                { checkout.branch.clone().unwrap_or_else(|| "(detached)".to_string()) }
+            </DescriptionGroup>
+            <DescriptionGroup term="Commit"><code>{ &checkout.commit }</code></DescriptionGroup>
+            <DescriptionGroup term="CLI">
+                { checkout.cli.clone().unwrap_or_else(|| "(not on the PATH)".to_string()) }
+            </DescriptionGroup>
+        </DescriptionList>
+    }
+}
+
 #[derive(Properties, PartialEq)]
 pub struct InstallDRyMcGTechProps {
     pub reload_trigger: u32,
@@ -11,13 +50,50 @@ pub struct InstallDRyMcGTechProps {
 
 #[function_component(InstallDRyMcGTech)]
 pub fn install(props: &InstallDRyMcGTechProps) -> Html {
+    let install = use_state(|| None::<Result<DRymcgTechInstall, String>>);
+    // Bumped to detect the install again, once the installer has run:
+    let revision = use_state(|| 0u32);
+    {
+        let install = install.clone();
+        use_effect_with((props.reload_trigger, *revision), move |_| {
+            wasm_bindgen_futures::spawn_local(async move {
+                install.set(Some(fetch_install().await));
+            });
+        });
+    }
+    let on_complete = {
+        let revision = revision.clone();
+        Callback::from(move |_| revision.set(*revision + 1))
+    };
+
+    let body = match &*install {
+        None => html! { <LoadingState /> },
+        Some(Ok(DRymcgTechInstall {
+            checkout: Some(checkout),
+            ..
+        })) => html! {
+            <>
+                <Alert inline=true r#type={AlertType::Success} title={format!("d.rymcg.tech is installed at {}", checkout.path)} />
+                <br/>
+                { checkout_details(checkout) }
+            </>
+        },
+        Some(Ok(DRymcgTechInstall { root_dir, .. })) => html! {
+            <>
+                <p>{ format!("d.rymcg.tech is not installed yet. It will be installed at {root_dir} (set ROOT_DIR in a profile to choose another directory).") }</p>
+                <br/>
+                <WorkflowView name="install_d_rymcg_tech" {on_complete} />
+            </>
+        },
+        Some(Err(e)) => html! {
+            <Alert inline=true r#type={AlertType::Danger} title={format!("Could not detect an existing install: {e}")} />
+        },
+    };
     html! {
         <Card>
             <CardTitle><h1>{"Install d.rymcg.tech"}</h1></CardTitle>
             <CardBody>
-            <TerminalOutput script="InstallDRymcgTech" reload_trigger={props.reload_trigger} selected_tab={props.selected_tab.clone()} on_done={TerminalOutputProps::default_on_done()}/>
-            <TerminalOutput script="InstallDependencies" reload_trigger={props.reload_trigger} selected_tab={props.selected_tab.clone()} on_done={TerminalOutputProps::default_on_done()} />
-            <TerminalOutput script="TestExampleOne" reload_trigger={props.reload_trigger} selected_tab={props.selected_tab.clone()} on_done={TerminalOutputProps::default_on_done()}/>
+            { body }
             </CardBody>
         </Card>
     }
@@ -1 +1,488 @@
+use crate::api;
+use dry_console_dto::events::{self, EventServerMsg};
+use dry_console_dto::websocket::{
+    CancelProcess, Capability, ClientMsg, Command, Hello, ProcessError, RunWorkflow, ServerMsg,
+    Signal, SignalProcess, SubscriberMsg, WebSocketMessage, Welcome, WorkflowStepStatus,
+    PROTOCOL_VERSION,
+};
+use dry_console_dto::workflow::StepState;
+use gloo::console::error;
+use gloo::timers::callback::Timeout;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use ulid::Ulid;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::js_sys::{ArrayBuffer, Date, Uint8Array};
+use web_sys::{BinaryType, CloseEvent, MessageEvent, WebSocket};
+use yew::prelude::*;
 
+const PROCESS_SOCKET_PATH: &str = "/api/workstation/command_execute/";
+
+/// The optional features of the command socket that this build needs:
+const CAPABILITIES: &[Capability] = &[Capability::Multiplex, Capability::Workflow];
+
+/// Can this build talk to the server that sent the Welcome? Any other
+/// server version is treated as incompatible, because the page would
+/// still be running the old build of the frontend.
+pub fn compatible(welcome: &Welcome) -> bool {
+    welcome.protocol_version == PROTOCOL_VERSION
+        && welcome.server_version == env!("CARGO_PKG_VERSION")
+        && CAPABILITIES
+            .iter()
+            .all(|c| welcome.capabilities.contains(c))
+}
+
+/// The payload of a binary (ArrayBuffer) or text message.
+fn message_bytes(event: &MessageEvent) -> Vec<u8> {
+    match event.data().dyn_into::<ArrayBuffer>() {
+        Ok(buffer) => Uint8Array::new(&buffer).to_vec(),
+        Err(data) => data.as_string().unwrap_or_default().into_bytes(),
+    }
+}
+
+/// How long a subscription waits to reconnect after it was closed. It
+/// doubles with every failed attempt, up to the maximum.
+const RECONNECT_DELAY_MS: u32 = 1000;
+const MAX_RECONNECT_DELAY_MS: u32 = 30_000;
+
+/// One subscription, across every reconnect.
+struct Subscription<T> {
+    path: &'static str,
+    on_connected: Callback<bool>,
+    on_message: Callback<T>,
+    ws: Option<WebSocket>,
+    callbacks: Vec<JsValue>,
+    reconnect: Option<Timeout>,
+    delay_ms: u32,
+    /// The component was unmounted, so it must not reconnect:
+    closed: bool,
+}
+
+impl<T> Subscription<T>
+where
+    T: WebSocketMessage + PartialEq + 'static,
+{
+    fn connect(subscription: &Rc<RefCell<Self>>) {
+        let Ok(ws) = WebSocket::new(&api::url(subscription.borrow().path)) else {
+            Self::reconnect_later(subscription);
+            return;
+        };
+        ws.set_binary_type(BinaryType::Arraybuffer);
+        let weak = Rc::downgrade(subscription);
+        let onopen = {
+            let weak = weak.clone();
+            Closure::<dyn FnMut(Event)>::new(move |_| {
+                if let Some(subscription) = weak.upgrade() {
+                    let on_connected = {
+                        let mut subscription = subscription.borrow_mut();
+                        subscription.delay_ms = RECONNECT_DELAY_MS;
+                        subscription.on_connected.clone()
+                    };
+                    on_connected.emit(true);
+                }
+            })
+        };
+        let onclose = Closure::<dyn FnMut(CloseEvent)>::new(move |_| {
+            if let Some(subscription) = weak.upgrade() {
+                let on_connected = subscription.borrow().on_connected.clone();
+                on_connected.emit(false);
+                Self::reconnect_later(&subscription);
+            }
+        });
+        let onmessage = {
+            let ws = ws.clone();
+            let path = subscription.borrow().path;
+            let on_message = subscription.borrow().on_message.clone();
+            Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                match serde_json::from_slice::<T>(&message_bytes(&event)) {
+                    Ok(msg) if msg == T::PING => {
+                        if let Ok(pong) = serde_json::to_string(&SubscriberMsg::Pong) {
+                            ws.send_with_str(&pong).ok();
+                        }
+                    }
+                    Ok(msg) => on_message.emit(msg),
+                    Err(e) => error!(format!("Failed to parse message from {path}: {e}")),
+                }
+            })
+        };
+        ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+        ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+        ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        let mut subscription = subscription.borrow_mut();
+        subscription.ws = Some(ws);
+        subscription.callbacks = vec![
+            onopen.into_js_value(),
+            onclose.into_js_value(),
+            onmessage.into_js_value(),
+        ];
+    }
+
+    fn reconnect_later(subscription: &Rc<RefCell<Self>>) {
+        let mut inner = subscription.borrow_mut();
+        if inner.closed {
+            return;
+        }
+        let delay_ms = inner.delay_ms;
+        inner.delay_ms = (delay_ms * 2).min(MAX_RECONNECT_DELAY_MS);
+        let weak = Rc::downgrade(subscription);
+        inner.reconnect = Some(Timeout::new(delay_ms, move || {
+            if let Some(subscription) = weak.upgrade() {
+                Self::connect(&subscription);
+            }
+        }));
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.reconnect = None;
+        if let Some(ws) = self.ws.take() {
+            ws.set_onopen(None);
+            ws.set_onclose(None);
+            ws.set_onmessage(None);
+            ws.close().ok();
+        }
+        self.callbacks.clear();
+    }
+}
+
+/// Subscribe to a websocket that only pushes updates from the server
+/// (eg. `/api/logs/`), for as long as the component is mounted. Pings
+/// are answered here, every other message is passed to on_message.
+/// on_connected is called with true when the socket opens, and with
+/// false when it closes. A closed socket (eg. when the server restarts)
+/// is reconnected, waiting longer after each attempt that fails.
+#[hook]
+pub fn use_subscription<T>(
+    path: &'static str,
+    on_connected: Callback<bool>,
+    on_message: Callback<T>,
+) where
+    T: WebSocketMessage + PartialEq + 'static,
+{
+    use_effect_with(path, move |path| {
+        let subscription = Rc::new(RefCell::new(Subscription {
+            path,
+            on_connected,
+            on_message,
+            ws: None,
+            callbacks: Vec::new(),
+            reconnect: None,
+            delay_ms: RECONNECT_DELAY_MS,
+            closed: false,
+        }));
+        Subscription::connect(&subscription);
+        move || subscription.borrow_mut().close()
+    });
+}
+
+/// Passes every server event (from the one /api/events/ subscription,
+/// see EventSubscription) on to the components that asked for them
+/// with use_event.
+#[derive(Clone, Default)]
+pub struct EventBus(Rc<RefCell<EventBusInner>>);
+
+#[derive(Default)]
+struct EventBusInner {
+    next_id: usize,
+    listeners: HashMap<usize, Rc<RefCell<Callback<events::Event>>>>,
+}
+
+impl PartialEq for EventBus {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl EventBus {
+    pub fn emit(&self, event: events::Event) {
+        // Don't hold the borrow while the callbacks run:
+        let listeners: Vec<_> = self.0.borrow().listeners.values().cloned().collect();
+        for listener in listeners {
+            let on_event = listener.borrow().clone();
+            on_event.emit(event.clone());
+        }
+    }
+}
+
+/// Subscribe to the server events, for as long as the component is
+/// mounted (the latest on_event is always the one called).
+#[hook]
+pub fn use_event(on_event: Callback<events::Event>) {
+    let bus = use_context::<EventBus>().expect("Must be nested inside an EventBus");
+    let listener = use_mut_ref(|| on_event.clone());
+    *listener.borrow_mut() = on_event;
+    use_effect_with(bus, move |bus| {
+        let id = {
+            let mut inner = bus.0.borrow_mut();
+            let id = inner.next_id;
+            inner.next_id += 1;
+            inner.listeners.insert(id, listener);
+            id
+        };
+        let bus = bus.clone();
+        move || {
+            bus.0.borrow_mut().listeners.remove(&id);
+        }
+    });
+}
+
+/// Holds the /api/events/ subscription open, passing every event on to
+/// the EventBus.
+#[function_component(EventSubscription)]
+pub fn event_subscription() -> Html {
+    let bus = use_context::<EventBus>().expect("Must be nested inside an EventBus");
+    use_subscription(
+        "/api/events/",
+        Callback::noop(),
+        Callback::from(move |msg| {
+            if let EventServerMsg::Event(event) = msg {
+                bus.emit(event);
+            }
+        }),
+    );
+    html! {}
+}
+
+/// The one connection to the command socket, shared (via context) by
+/// every TerminalOutput. Any number of processes may run on it at
+/// once: each is tagged with its own ULID, and the messages about it
+/// are passed to the callback that started it.
+#[derive(Clone, Default)]
+pub struct ProcessSocket(Rc<RefCell<ProcessSocketInner>>);
+
+#[derive(Default)]
+struct ProcessSocketInner {
+    ws: Option<WebSocket>,
+    /// Messages sent before the socket was open:
+    pending: Vec<String>,
+    processes: HashMap<Ulid, Callback<ServerMsg>>,
+    callbacks: Vec<JsValue>,
+    /// The Welcome of the current connection:
+    welcome: Option<Welcome>,
+    on_welcome: Option<Callback<Welcome>>,
+}
+
+impl PartialEq for ProcessSocket {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl ProcessSocket {
+    /// Start a command (with the environment profile, if any), passing
+    /// every message about the new process to on_message. Returns the
+    /// process id.
+    pub fn start(
+        &self,
+        command_id: Ulid,
+        profile: Option<String>,
+        on_message: Callback<ServerMsg>,
+    ) -> Ulid {
+        // Ulid::new() needs the system clock, which wasm does not have:
+        let process_id = Ulid::from_parts(Date::now() as u64, rand::random());
+        self.0.borrow_mut().processes.insert(process_id, on_message);
+        self.send(&ClientMsg::Command(Command {
+            id: command_id,
+            process_id,
+            profile,
+        }));
+        process_id
+    }
+
+    /// Run a workflow, passing every message about the run (and about
+    /// the processes of its steps) to on_message. Returns the run id.
+    pub fn run_workflow(
+        &self,
+        name: &str,
+        params: BTreeMap<String, String>,
+        profile: Option<String>,
+        on_message: Callback<ServerMsg>,
+    ) -> Ulid {
+        let run_id = Ulid::from_parts(Date::now() as u64, rand::random());
+        self.0.borrow_mut().processes.insert(run_id, on_message);
+        self.send(&ClientMsg::Workflow(RunWorkflow {
+            name: name.to_string(),
+            run_id,
+            params,
+            profile,
+        }));
+        run_id
+    }
+
+    /// Connect now, rather than when the first command is started, so
+    /// that the versions are compared as soon as possible.
+    pub fn open(&self) {
+        self.connect();
+    }
+
+    /// Pass on the Welcome of every connection (including the current
+    /// one, if it has already been received).
+    pub fn on_welcome(&self, on_welcome: Callback<Welcome>) {
+        let welcome = {
+            let mut inner = self.0.borrow_mut();
+            inner.on_welcome = Some(on_welcome.clone());
+            inner.welcome.clone()
+        };
+        if let Some(welcome) = welcome {
+            on_welcome.emit(welcome);
+        }
+    }
+
+    pub fn cancel(&self, process_id: Ulid) {
+        self.send(&ClientMsg::Cancel(CancelProcess { id: process_id }));
+    }
+
+    pub fn signal(&self, process_id: Ulid, signal: Signal) {
+        self.send(&ClientMsg::Signal(SignalProcess {
+            id: process_id,
+            signal,
+        }));
+    }
+
+    /// Stop passing on messages about the process (eg. when its
+    /// terminal is reset).
+    pub fn forget(&self, process_id: Ulid) {
+        self.0.borrow_mut().processes.remove(&process_id);
+    }
+
+    fn send(&self, msg: &ClientMsg) {
+        let Ok(msg) = serde_json::to_string(msg) else {
+            return;
+        };
+        let ws = self.connect();
+        if ws.ready_state() == WebSocket::OPEN {
+            ws.send_with_str(&msg).ok();
+        } else {
+            self.0.borrow_mut().pending.push(msg);
+        }
+    }
+
+    /// The open (or opening) socket, connecting again if it was closed.
+    fn connect(&self) -> WebSocket {
+        if let Some(ws) = &self.0.borrow().ws {
+            if ws.ready_state() <= WebSocket::OPEN {
+                return ws.clone();
+            }
+        }
+        let ws = WebSocket::new(&api::url(PROCESS_SOCKET_PATH))
+            .expect("Failed to create the command socket");
+        ws.set_binary_type(BinaryType::Arraybuffer);
+        // The callbacks must not keep the socket alive:
+        let inner = Rc::downgrade(&self.0);
+        let onopen = {
+            let inner = inner.clone();
+            let ws = ws.clone();
+            Closure::<dyn FnMut(Event)>::new(move |_| {
+                if let Some(inner) = inner.upgrade() {
+                    for msg in inner.borrow_mut().pending.drain(..) {
+                        ws.send_with_str(&msg).ok();
+                    }
+                }
+            })
+        };
+        let onmessage = {
+            let inner = inner.clone();
+            let ws = ws.clone();
+            Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                match serde_json::from_slice::<ServerMsg>(&message_bytes(&event)) {
+                    Ok(ServerMsg::Ping) => {
+                        if let Ok(pong) = serde_json::to_string(&ClientMsg::Pong) {
+                            ws.send_with_str(&pong).ok();
+                        }
+                    }
+                    Ok(ServerMsg::Welcome(welcome)) => Self::welcome(&inner, welcome),
+                    Ok(msg) => Self::dispatch(&inner, msg),
+                    Err(e) => error!(format!("Failed to parse process message: {e}")),
+                }
+            })
+        };
+        let onclose = Closure::<dyn FnMut(CloseEvent)>::new(move |_| {
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+            // Nothing more will be heard about the running processes:
+            let processes: Vec<_> = inner.borrow_mut().processes.drain().collect();
+            for (id, on_message) in processes {
+                on_message.emit(ServerMsg::ProcessError(ProcessError {
+                    id,
+                    message: "The connection to the server was closed.".to_string(),
+                }));
+            }
+        });
+        ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+        ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+        let hello = serde_json::to_string(&ClientMsg::Hello(Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities: CAPABILITIES.to_vec(),
+        }))
+        .expect("Failed to serialize Hello");
+        let mut inner = self.0.borrow_mut();
+        // Hello must be the first message on every connection:
+        inner.pending.insert(0, hello);
+        inner.welcome = None;
+        inner.callbacks = vec![
+            onopen.into_js_value(),
+            onmessage.into_js_value(),
+            onclose.into_js_value(),
+        ];
+        inner.ws = Some(ws.clone());
+        ws
+    }
+
+    fn welcome(inner: &Weak<RefCell<ProcessSocketInner>>, welcome: Welcome) {
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        if !compatible(&welcome) {
+            error!(format!(
+                "Incompatible server version {} (protocol {}), this page is version {} (protocol {PROTOCOL_VERSION})",
+                welcome.server_version,
+                welcome.protocol_version,
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+        let on_welcome = {
+            let mut inner = inner.borrow_mut();
+            inner.welcome = Some(welcome.clone());
+            inner.on_welcome.clone()
+        };
+        if let Some(on_welcome) = on_welcome {
+            on_welcome.emit(welcome);
+        }
+    }
+
+    /// Pass a message on to the callback of the process it is about.
+    fn dispatch(inner: &Weak<RefCell<ProcessSocketInner>>, msg: ServerMsg) {
+        let (Some(inner), Some(id)) = (inner.upgrade(), msg.process_id()) else {
+            return;
+        };
+        // Don't hold the borrow while the callback runs:
+        let on_message = match msg.is_final() {
+            true => inner.borrow_mut().processes.remove(&id),
+            false => inner.borrow().processes.get(&id).cloned(),
+        };
+        // The messages about the process of a workflow step go to the run:
+        if let (
+            Some(on_message),
+            ServerMsg::WorkflowStep(WorkflowStepStatus {
+                state: StepState::Running,
+                process_id: Some(process_id),
+                ..
+            }),
+        ) = (&on_message, &msg)
+        {
+            inner
+                .borrow_mut()
+                .processes
+                .insert(*process_id, on_message.clone());
+        }
+        if let Some(on_message) = on_message {
+            on_message.emit(msg);
+        }
+    }
+}
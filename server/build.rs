@@ -1,5 +1,7 @@
 use convert_case::Casing;
 use dry_console_common::token::generate_deterministic_ulid_from_seed;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
 use std::env;
 use std::fs;
 use std::fs::File;
@@ -7,10 +9,46 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+/// Precompresses an embedded asset with gzip and brotli, writing the
+/// variants into OUT_DIR, and emits a `StaticAsset` entry for it.
+/// Variants that do not shrink the asset (eg. woff2 fonts) are omitted.
 fn write_source(file: &mut std::fs::File, destination: &str, source: &str, file_type: &str) {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let content = fs::read(source).unwrap_or_else(|e| panic!("Could not read {source}: {e}"));
+    let digest = Sha256::digest(&content);
+    let hash: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+
+    let release = env::var("PROFILE").unwrap() == "release";
+    // The live-reload layer (debug builds only) can't inject into encoded HTML:
+    let compress = release || !file_type.starts_with("text/html");
+    let variant = |extension: &str, compressed: Vec<u8>| -> String {
+        if !compress || compressed.len() >= content.len() {
+            return "None".to_string();
+        }
+        let path = Path::new(&out_dir).join(format!("{hash}.{extension}"));
+        fs::write(&path, compressed).expect("Failed to write compressed asset");
+        format!("Some(include_bytes!(\"{}\"))", path.to_string_lossy())
+    };
+    // Maximum compression is slow, so only spend the time on release builds:
+    let (gzip_level, brotli_quality) = if release { (9, 11) } else { (6, 5) };
+    let gzip = variant("gz", {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::new(gzip_level));
+        encoder.write_all(&content).unwrap();
+        encoder.finish().unwrap()
+    });
+    let brotli = variant("br", {
+        let mut compressed = Vec::new();
+        let params = brotli::enc::BrotliEncoderParams {
+            quality: brotli_quality,
+            ..Default::default()
+        };
+        brotli::BrotliCompress(&mut content.as_slice(), &mut compressed, &params).unwrap();
+        compressed
+    });
+
     writeln!(
         file,
-        "        (\"{destination}\", include_bytes!(\"{source}\"), \"{file_type}\"),",
+        "        StaticAsset {{ path: \"{destination}\", content_type: \"{file_type}\", hash: \"{hash}\", identity: include_bytes!(\"{source}\"), gzip: {gzip}, brotli: {brotli} }},",
     )
     .unwrap();
 }
@@ -46,12 +84,17 @@ fn main() {
         .to_string_lossy()
         .to_string();
 
+    // The assets live outside of this package, so cargo must be told
+    // to rebuild (and recompress) them whenever they change:
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/api/workstation/scripts");
+    println!("cargo:rerun-if-changed=src/api/workstation/workflows");
+    println!("cargo:rerun-if-changed=src/api/workstation/libraries");
+    println!("cargo:rerun-if-changed={project_root}/frontend/style.css");
+    println!("cargo:rerun-if-changed={dist_dir}");
+
     // Write the start of the function definition to the generated file
-    writeln!(
-        file,
-        "pub fn get_inline_files() -> Vec<(&'static str, &'static [u8], &'static str)> {{"
-    )
-    .unwrap();
+    writeln!(file, "pub fn get_inline_files() -> Vec<StaticAsset> {{").unwrap();
     writeln!(file, "    vec![").unwrap();
 
     // Walk through the ../dist/snippets directory and find all javascript files
@@ -63,7 +106,7 @@ fn main() {
             && entry
                 .file_name()
                 .to_str()
-                .map_or(false, |f| f.ends_with(".js"))
+                .is_some_and(|f| f.ends_with(".js"))
         {
             let asset_path = entry.path();
 
@@ -79,6 +122,26 @@ fn main() {
         }
     }
 
+    // Client application
+    write_source(
+        &mut file,
+        "/index.html",
+        format!("{dist_dir}/index.html").as_str(),
+        "text/html; charset=utf-8",
+    );
+    write_source(
+        &mut file,
+        "/frontend.js",
+        format!("{dist_dir}/frontend.js").as_str(),
+        "application/javascript",
+    );
+    write_source(
+        &mut file,
+        "/frontend_bg.wasm",
+        format!("{dist_dir}/frontend_bg.wasm").as_str(),
+        "application/wasm",
+    );
+
     // App CSS
     write_source(
         &mut file,
@@ -140,9 +203,71 @@ fn main() {
     writeln!(file, "    ]").unwrap();
     writeln!(file, "}}").unwrap();
 
+    include_workflows(&out_dir, &project_root);
+    include_shell_libraries(&out_dir, &project_root);
     include_shell_scripts(out_dir, project_root);
 }
 
+/// Embeds every workflow definition (src/api/workstation/workflows/*.json),
+/// named after its file.
+fn include_workflows(out_dir: &str, project_root: &str) {
+    let dest_path = Path::new(out_dir).join("generated_workflow_library.rs");
+    let workflow_dir = Path::new(project_root)
+        .join("server/src/api/workstation/workflows")
+        .canonicalize()
+        .expect("Could not find workflow directory.");
+
+    let mut workflows: Vec<PathBuf> = fs::read_dir(&workflow_dir)
+        .expect("Failed to read workflow directory")
+        .map(|entry| entry.expect("Failed to read directory entry").path())
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("json"))
+        .collect();
+    workflows.sort();
+
+    let mut output = String::new();
+    output.push_str("pub const WORKFLOW_SOURCES: &[(&str, &str)] = &[\n");
+    for path in workflows {
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap();
+        output.push_str(&format!(
+            "    (\"{name}\", include_str!(\"{}\")),\n",
+            path.to_str().unwrap()
+        ));
+    }
+    output.push_str("];\n");
+
+    fs::write(dest_path, output).expect("Failed to write generated file");
+}
+
+/// Embeds every builtin shell library (src/api/workstation/libraries/*.sh),
+/// named after its file.
+fn include_shell_libraries(out_dir: &str, project_root: &str) {
+    let dest_path = Path::new(out_dir).join("generated_shell_library.rs");
+    let library_dir = Path::new(project_root)
+        .join("server/src/api/workstation/libraries")
+        .canonicalize()
+        .expect("Could not find shell library directory.");
+
+    let mut libraries: Vec<PathBuf> = fs::read_dir(&library_dir)
+        .expect("Failed to read shell library directory")
+        .map(|entry| entry.expect("Failed to read directory entry").path())
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("sh"))
+        .collect();
+    libraries.sort();
+
+    let mut output = String::new();
+    output.push_str("pub const SHELL_LIBRARY_SOURCES: &[(&str, &str)] = &[\n");
+    for path in libraries {
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap();
+        output.push_str(&format!(
+            "    (\"{name}\", include_str!(\"{}\")),\n",
+            path.to_str().unwrap()
+        ));
+    }
+    output.push_str("];\n");
+
+    fs::write(dest_path, output).expect("Failed to write generated file");
+}
+
 fn include_shell_scripts(out_dir: String, project_root: String) {
     let dest_path = Path::new(&out_dir).join("generated_command_library.rs");
 
@@ -12,7 +12,14 @@ use enum_iterator::{all, Sequence};
 mod admin;
 pub mod auth;
 mod docs;
+mod events;
+mod history;
+mod logs;
+mod profiles;
+mod schedules;
+mod secrets;
 mod session;
+mod sudo;
 pub mod test;
 pub use dry_console_common::token;
 pub mod websocket;
@@ -36,6 +43,13 @@ pub trait ApiModule {
 #[derive(Debug, PartialEq, Sequence, Clone)]
 pub enum APIModule {
     Admin,
+    Events,
+    History,
+    Logs,
+    Profiles,
+    Schedules,
+    Secrets,
+    Sudo,
     Test,
     Workstation,
     // Modules not listed that are handled separately:
@@ -57,6 +71,13 @@ impl ApiModule for APIModule {
     fn router(&self, shutdown: broadcast::Sender<()>, state: State<SharedState>) -> AppRouter {
         match self {
             APIModule::Admin => admin::router(),
+            APIModule::Events => events::router(shutdown, state),
+            APIModule::History => history::router(),
+            APIModule::Logs => logs::router(shutdown, state),
+            APIModule::Profiles => profiles::router(),
+            APIModule::Schedules => schedules::router(),
+            APIModule::Secrets => secrets::router(),
+            APIModule::Sudo => sudo::router(shutdown, state),
             APIModule::Test => test::router(shutdown, state.clone()),
             APIModule::Workstation => workstation::router(shutdown, state),
         }
@@ -75,11 +96,15 @@ pub fn router(
     auth_backend: Backend,
     shutdown: broadcast::Sender<()>,
     state: State<SharedState>,
+    base_path: &str,
 ) -> AppRouter {
     let key = cookie::Key::generate();
     let session_store = MemoryStore::default();
+    // Scope the cookie to the base path, so that several instances
+    // may share a single hostname behind a reverse proxy:
     let session_layer = SessionManagerLayer::new(session_store)
         .with_secure(false)
+        .with_path(session::cookie_path(base_path))
         .with_signed(key.clone());
     let auth_layer =
         AuthManagerLayerBuilder::new(auth_backend.clone(), session_layer.clone()).build();
@@ -91,7 +116,7 @@ pub fn router(
         // everything above auth_layer is private and requires authentication
         // everything after auth_layer is public and requires no authentication
         .layer(session_layer)
-        .nest("/docs/", docs::router())
+        .nest("/docs/", docs::router(base_path))
         .route(
             "/unprotected",
             get(|| async { "Hi there, this page is unprotected!" }),
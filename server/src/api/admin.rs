@@ -6,15 +6,24 @@ use crate::{
     AppRouter,
 };
 use axum::{
-    extract::State, http::StatusCode, response::IntoResponse, routing::post, Extension, Router,
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post, put},
+    Extension, Json, Router,
 };
 use axum_login::AuthSession;
+use dry_console_dto::queue::{QueueOrder, RunQueueStatus};
 use dry_console_dto::session::Credentials;
 use std::sync::Arc;
 use tokio::sync::{oneshot, Mutex};
 
 pub fn router() -> AppRouter {
-    Router::new().merge(shutdown()).merge(enable_login())
+    Router::new()
+        .merge(shutdown())
+        .merge(enable_login())
+        .merge(queue())
+        .merge(reorder_queue())
 }
 
 #[utoipa::path(
@@ -66,3 +75,37 @@ fn enable_login() -> AppRouter {
     }
     route("/enable_login", post(handler))
 }
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/queue/",
+    responses(
+        (status = OK, body = RunQueueStatus, description = "The runs of library scripts, and those waiting in the run queue for their locks")
+    )
+)]
+fn queue() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<RunQueueStatus> {
+        Ok(AppJson(state.read().await.run_queue.status()))
+    }
+    route("/queue", get(handler))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/queue/",
+    request_body = QueueOrder,
+    responses(
+        (status = OK, body = RunQueueStatus, description = "Move the listed runs to the front of the run queue, in that order")
+    )
+)]
+fn reorder_queue() -> AppRouter {
+    async fn handler(
+        State(state): State<SharedState>,
+        Json(order): Json<QueueOrder>,
+    ) -> JsonResult<RunQueueStatus> {
+        let run_queue = state.read().await.run_queue.clone();
+        run_queue.reorder(&order.order);
+        Ok(AppJson(run_queue.status()))
+    }
+    route("/queue", put(handler))
+}
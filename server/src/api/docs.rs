@@ -1,6 +1,7 @@
 use crate::{api::route, app_state::SharedState};
-use axum::{response::Redirect, routing::get, Json, Router};
+use axum::{extract::State, response::Redirect, routing::get, Json, Router};
 //use serde_json::json;
+use utoipa::openapi::Server;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 use utoipauto::utoipauto;
@@ -10,11 +11,23 @@ use utoipauto::utoipauto;
 #[openapi(info(contact()))]
 pub struct ApiDoc;
 
-pub fn router() -> Router<SharedState> {
+/// The OpenAPI spec, with documented paths relative to the --base-path.
+fn openapi(base_path: &str) -> utoipa::openapi::OpenApi {
+    let mut openapi = ApiDoc::openapi();
+    if !base_path.is_empty() {
+        openapi.servers = Some(vec![Server::new(base_path)]);
+    }
+    openapi
+}
+
+pub fn router(base_path: &str) -> Router<SharedState> {
     Router::new()
-        .merge(SwaggerUi::new("/ui").url("/api/docs/openapi.json/", ApiDoc::openapi()))
+        .merge(SwaggerUi::new("/ui").url(
+            format!("{base_path}/api/docs/openapi.json/"),
+            openapi(base_path),
+        ))
         .merge(docs())
-        .merge(ui())
+        .merge(ui(base_path.to_string()))
 }
 
 #[utoipa::path(
@@ -24,14 +37,17 @@ pub fn router() -> Router<SharedState> {
         (status = 200, description = "OpenAPI spec JSON file", body = ())
     )
 )]
-async fn handler() -> Json<utoipa::openapi::OpenApi> {
-    Json(ApiDoc::openapi())
+async fn handler(State(state): State<SharedState>) -> Json<utoipa::openapi::OpenApi> {
+    Json(openapi(&state.read().await.opt.base_path()))
 }
 
 fn docs() -> Router<SharedState> {
     route("/openapi.json", get(handler))
 }
 
-fn ui() -> Router<SharedState> {
-    route("/", get(|| async { Redirect::permanent("/api/docs/ui/") }))
+fn ui(base_path: String) -> Router<SharedState> {
+    route(
+        "/",
+        get(|| async move { Redirect::permanent(&format!("{base_path}/api/docs/ui/")) }),
+    )
 }
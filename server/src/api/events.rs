@@ -0,0 +1,69 @@
+use crate::api::websocket::push_websocket;
+use crate::{api::route, AppRouter};
+use crate::{app_state::SharedState, broadcast};
+use axum::extract::State;
+use axum::{response::IntoResponse, routing::get, Router};
+use axum_login::tower_sessions::Session;
+use axum_typed_websockets::{WebSocket, WebSocketUpgrade};
+use dry_console_dto::events::{Event, EventServerMsg};
+use dry_console_dto::websocket::SubscriberMsg;
+use futures::StreamExt;
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::debug;
+
+pub fn router(shutdown: broadcast::Sender<()>, state: State<SharedState>) -> AppRouter {
+    Router::new().merge(events(shutdown, state))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/events/",
+    responses(
+        (status = OK, description = "Open websocket connection to receive every server event (processes, dependencies, sudo and session changes) as it happens")
+    )
+)]
+fn events(shutdown: broadcast::Sender<()>, state: State<SharedState>) -> AppRouter {
+    /// WebSocket connection handler
+    async fn websocket(
+        socket: WebSocket<EventServerMsg, SubscriberMsg>,
+        shutdown: broadcast::Receiver<()>,
+        State(state): State<SharedState>,
+        session_id: Option<String>,
+    ) {
+        let events = state.read().await.events.subscribe();
+        // If the client can't keep up, skip what it missed, and only tell
+        // it about its own session being revoked:
+        let events = BroadcastStream::new(events)
+            .filter_map(move |event| {
+                let session_id = session_id.clone();
+                async move {
+                    match event.ok()? {
+                        Event::SessionRevoked(id) if Some(&id) != session_id.as_ref() => None,
+                        event => Some(event),
+                    }
+                }
+            })
+            .map(EventServerMsg::Event);
+        push_websocket(socket, shutdown, events).await;
+    }
+
+    /// Upgrade HTTP connection to WebSocket
+    async fn upgrade(
+        ws: WebSocketUpgrade<EventServerMsg, SubscriberMsg>,
+        session: Session,
+        shutdown: broadcast::Sender<()>,
+        state: State<SharedState>,
+    ) -> impl IntoResponse {
+        let shutdown_rx = shutdown.subscribe();
+        let session_id = session.id().map(|id| id.to_string());
+        debug!("Event WebSocket upgrade request received.");
+        ws.on_upgrade(move |socket| websocket(socket, shutdown_rx, state, session_id))
+    }
+
+    route(
+        "/",
+        get(move |ws: WebSocketUpgrade<_, _>, session: Session| {
+            upgrade(ws, session, shutdown.clone(), state)
+        }),
+    )
+}
@@ -0,0 +1,25 @@
+use crate::app_state::SharedState;
+use crate::response::{AppJson, JsonResult};
+use crate::{routing::route, AppRouter};
+use axum::extract::State;
+use axum::{routing::get, Router};
+use dry_console_dto::history::RunRecord;
+
+pub fn router() -> AppRouter {
+    Router::new().merge(history())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/history/",
+    responses(
+        (status = OK, body = [RunRecord], description = "The most recent runs of library scripts, most recent first")
+    )
+)]
+fn history() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<Vec<RunRecord>> {
+        let state = state.read().await;
+        Ok(AppJson(state.history.recent().cloned().collect()))
+    }
+    route("/", get(handler))
+}
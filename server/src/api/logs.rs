@@ -0,0 +1,52 @@
+use crate::api::websocket::push_websocket;
+use crate::logs::LOG_BUFFER;
+use crate::{api::route, AppRouter};
+use crate::{app_state::SharedState, broadcast};
+use axum::extract::State;
+use axum::{response::IntoResponse, routing::get, Router};
+use axum_typed_websockets::{WebSocket, WebSocketUpgrade};
+use dry_console_dto::logs::LogServerMsg;
+use dry_console_dto::websocket::SubscriberMsg;
+use futures::stream::{self, StreamExt};
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::debug;
+
+pub fn router(shutdown: broadcast::Sender<()>, _state: State<SharedState>) -> AppRouter {
+    Router::new().merge(logs(shutdown))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/logs/",
+    responses(
+        (status = OK, description = "Open websocket connection to read the recent server logs, followed by new logs as they happen")
+    )
+)]
+fn logs(shutdown: broadcast::Sender<()>) -> AppRouter {
+    /// WebSocket connection handler
+    async fn websocket(
+        socket: WebSocket<LogServerMsg, SubscriberMsg>,
+        shutdown: broadcast::Receiver<()>,
+    ) {
+        let (backlog, live) = LOG_BUFFER.subscribe();
+        // If the client can't keep up, skip what it missed:
+        let live = BroadcastStream::new(live).filter_map(|event| async move { event.ok() });
+        let events = stream::iter(backlog).chain(live).map(LogServerMsg::Log);
+        push_websocket(socket, shutdown, events).await;
+    }
+
+    /// Upgrade HTTP connection to WebSocket
+    async fn upgrade(
+        ws: WebSocketUpgrade<LogServerMsg, SubscriberMsg>,
+        shutdown: broadcast::Sender<()>,
+    ) -> impl IntoResponse {
+        let shutdown_rx = shutdown.subscribe();
+        debug!("Log viewer WebSocket upgrade request received.");
+        ws.on_upgrade(move |socket| websocket(socket, shutdown_rx))
+    }
+
+    route(
+        "/",
+        get(move |ws: WebSocketUpgrade<_, _>| upgrade(ws, shutdown.clone())),
+    )
+}
@@ -0,0 +1,149 @@
+use crate::app_state::SharedState;
+use crate::config::is_valid_name;
+use crate::environment::{self, is_valid_var};
+use crate::response::{AppError, AppJson, JsonResult};
+use crate::{routing::route, AppRouter};
+use axum::extract::{Path, State};
+use axum::routing::{delete, get, put};
+use axum::{Json, Router};
+use dry_console_dto::profile::{EnvProfile, MASK};
+use std::collections::BTreeMap;
+
+pub fn router() -> AppRouter {
+    Router::new()
+        .merge(profiles())
+        .merge(save())
+        .merge(remove())
+        .merge(environment())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/profiles/",
+    responses(
+        (status = OK, body = [EnvProfile], description = "The environment profiles, with the values of their secrets masked")
+    )
+)]
+fn profiles() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<Vec<EnvProfile>> {
+        let state = state.read().await;
+        Ok(AppJson(
+            state
+                .config
+                .profiles
+                .iter()
+                .map(EnvProfile::masked)
+                .collect(),
+        ))
+    }
+    route("/", get(handler))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/profiles/{name}/",
+    request_body = EnvProfile,
+    responses(
+        (status = OK, body = EnvProfile, description = "Create or replace an environment profile, and save the config. Secrets that are still masked keep their values."),
+        (status = CONFLICT, description = "The name of the profile, or of one of its variables, is invalid")
+    ),
+    params(
+        ("name" = String, Path, description = "The name of the profile")
+    )
+)]
+fn save() -> AppRouter {
+    async fn handler(
+        Path(name): Path<String>,
+        State(state): State<SharedState>,
+        Json(mut profile): Json<EnvProfile>,
+    ) -> JsonResult<EnvProfile> {
+        if !is_valid_name(&name) {
+            return Err(AppError::Conflict(format!("Invalid profile name: {name}")));
+        }
+        if let Some(var) = profile.vars.keys().find(|var| !is_valid_var(var)) {
+            return Err(AppError::Conflict(format!("Invalid variable name: {var}")));
+        }
+        profile.name = name;
+        let mut state = state.write().await;
+        // The client only ever sees the masked secrets:
+        if let Some(old) = state.config.profile(&profile.name) {
+            for (var, value) in profile.vars.iter_mut() {
+                if let (MASK, Some(old_value)) = (value.as_str(), old.vars.get(var)) {
+                    *value = old_value.clone();
+                }
+            }
+        }
+        let masked = profile.masked();
+        let profiles = &mut state.config.profiles;
+        match profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(old) => *old = profile,
+            None => profiles.push(profile),
+        }
+        state.save_config().map_err(AppError::Io)?;
+        Ok(AppJson(masked))
+    }
+    route("/:name", put(handler))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/profiles/{name}/",
+    responses(
+        (status = OK, description = "Remove an environment profile, and save the config"),
+        (status = NOT_FOUND, description = "Profile not found"),
+        (status = CONFLICT, description = "A schedule runs with the profile")
+    ),
+    params(
+        ("name" = String, Path, description = "The name of the profile")
+    )
+)]
+fn remove() -> AppRouter {
+    async fn handler(Path(name): Path<String>, State(state): State<SharedState>) -> JsonResult<()> {
+        let mut state = state.write().await;
+        if let Some(schedule) = state
+            .config
+            .schedules
+            .iter()
+            .find(|s| s.profile.as_ref() == Some(&name))
+        {
+            return Err(AppError::Conflict(format!(
+                "The profile is used by the schedule {}.",
+                schedule.name
+            )));
+        }
+        let count = state.config.profiles.len();
+        state.config.profiles.retain(|p| p.name != name);
+        if state.config.profiles.len() == count {
+            return Err(AppError::NotFound);
+        }
+        state.save_config().map_err(AppError::Io)?;
+        Ok(AppJson(()))
+    }
+    route("/:name", delete(handler))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/profiles/{name}/env/",
+    responses(
+        (status = OK, body = BTreeMap<String, String>, description = "The environment that scripts run with, with this profile, with the values of secrets masked"),
+        (status = NOT_FOUND, description = "Profile not found")
+    ),
+    params(
+        ("name" = String, Path, description = "The name of the profile")
+    )
+)]
+fn environment() -> AppRouter {
+    async fn handler(
+        Path(name): Path<String>,
+        State(state): State<SharedState>,
+    ) -> JsonResult<BTreeMap<String, String>> {
+        let state = state.read().await;
+        let profile = state.config.profile(&name).ok_or(AppError::NotFound)?;
+        let base = environment::base_env(&state.opt.pass_env);
+        let none = BTreeMap::new();
+        let env = environment::effective_env(base, Some(profile), &none, &none);
+        Ok(AppJson(environment::masked_env(&env, Some(profile), &none)))
+    }
+    route("/:name/env", get(handler))
+}
@@ -0,0 +1,193 @@
+use crate::app_state::SharedState;
+use crate::response::{AppError, AppJson, JsonResult};
+use crate::scheduler;
+use crate::{routing::route, AppRouter};
+use axum::extract::{Path, State};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use dry_console_dto::schedule::{Schedule, ScheduleStatus};
+
+pub fn router() -> AppRouter {
+    Router::new()
+        .merge(schedules())
+        .merge(create())
+        .merge(remove())
+        .merge(pause())
+        .merge(resume())
+        .merge(run())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/schedules/",
+    responses(
+        (status = OK, body = [ScheduleStatus], description = "The scheduled scripts, and when they last ran and will next run")
+    )
+)]
+fn schedules() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<Vec<ScheduleStatus>> {
+        let state = state.read().await;
+        Ok(AppJson(
+            state
+                .config
+                .schedules
+                .iter()
+                .map(|schedule| scheduler::status(&state, schedule))
+                .collect(),
+        ))
+    }
+    route("/", get(handler))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/schedules/",
+    request_body = Schedule,
+    responses(
+        (status = OK, body = ScheduleStatus, description = "Add a schedule, and save the config"),
+        (status = CONFLICT, description = "The schedule is invalid, or one with the same name exists")
+    )
+)]
+fn create() -> AppRouter {
+    async fn handler(
+        State(state): State<SharedState>,
+        Json(schedule): Json<Schedule>,
+    ) -> JsonResult<ScheduleStatus> {
+        let mut state = state.write().await;
+        scheduler::validate(&schedule, &state.config).map_err(AppError::Conflict)?;
+        if state
+            .config
+            .schedules
+            .iter()
+            .any(|s| s.name == schedule.name)
+        {
+            return Err(AppError::Conflict(format!(
+                "There is already a schedule named {}.",
+                schedule.name
+            )));
+        }
+        state.config.schedules.push(schedule.clone());
+        state.save_config().map_err(AppError::Io)?;
+        state.schedules_changed.notify_one();
+        Ok(AppJson(scheduler::status(&state, &schedule)))
+    }
+    route("/", post(handler))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/schedules/{name}/",
+    responses(
+        (status = OK, description = "Remove a schedule, and save the config"),
+        (status = NOT_FOUND, description = "Schedule not found")
+    ),
+    params(
+        ("name" = String, Path, description = "The name of the schedule")
+    )
+)]
+fn remove() -> AppRouter {
+    async fn handler(Path(name): Path<String>, State(state): State<SharedState>) -> JsonResult<()> {
+        let mut state = state.write().await;
+        let count = state.config.schedules.len();
+        state.config.schedules.retain(|s| s.name != name);
+        if state.config.schedules.len() == count {
+            return Err(AppError::NotFound);
+        }
+        state.save_config().map_err(AppError::Io)?;
+        state.schedules_changed.notify_one();
+        Ok(AppJson(()))
+    }
+    route("/:name", delete(handler))
+}
+
+/// Pause or resume the named schedule, and save the config.
+async fn set_paused(state: SharedState, name: &str, paused: bool) -> JsonResult<ScheduleStatus> {
+    let mut state = state.write().await;
+    let schedule = state
+        .config
+        .schedules
+        .iter_mut()
+        .find(|s| s.name == name)
+        .ok_or(AppError::NotFound)?;
+    schedule.paused = paused;
+    let schedule = schedule.clone();
+    state.save_config().map_err(AppError::Io)?;
+    state.schedules_changed.notify_one();
+    Ok(AppJson(scheduler::status(&state, &schedule)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/schedules/{name}/pause/",
+    responses(
+        (status = OK, body = ScheduleStatus, description = "Stop a schedule from running when it is due"),
+        (status = NOT_FOUND, description = "Schedule not found")
+    ),
+    params(
+        ("name" = String, Path, description = "The name of the schedule")
+    )
+)]
+fn pause() -> AppRouter {
+    async fn handler(
+        Path(name): Path<String>,
+        State(state): State<SharedState>,
+    ) -> JsonResult<ScheduleStatus> {
+        set_paused(state, &name, true).await
+    }
+    route("/:name/pause", post(handler))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/schedules/{name}/resume/",
+    responses(
+        (status = OK, body = ScheduleStatus, description = "Run a paused schedule again when it is due"),
+        (status = NOT_FOUND, description = "Schedule not found")
+    ),
+    params(
+        ("name" = String, Path, description = "The name of the schedule")
+    )
+)]
+fn resume() -> AppRouter {
+    async fn handler(
+        Path(name): Path<String>,
+        State(state): State<SharedState>,
+    ) -> JsonResult<ScheduleStatus> {
+        set_paused(state, &name, false).await
+    }
+    route("/:name/resume", post(handler))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/schedules/{name}/run/",
+    responses(
+        (status = OK, body = String, description = "Run a schedule now (even if it is paused), returning the process id"),
+        (status = NOT_FOUND, description = "Schedule not found"),
+        (status = CONFLICT, description = "The schedule is still running")
+    ),
+    params(
+        ("name" = String, Path, description = "The name of the schedule")
+    )
+)]
+fn run() -> AppRouter {
+    async fn handler(
+        Path(name): Path<String>,
+        State(state): State<SharedState>,
+    ) -> JsonResult<String> {
+        let schedule = state
+            .read()
+            .await
+            .config
+            .schedules
+            .iter()
+            .find(|s| s.name == name)
+            .cloned()
+            .ok_or(AppError::NotFound)?;
+        let process_id = scheduler::run_scheduled(state, &schedule)
+            .await
+            .map_err(AppError::Conflict)?;
+        Ok(AppJson(process_id.to_string()))
+    }
+    route("/:name/run", post(handler))
+}
@@ -0,0 +1,139 @@
+use crate::app_state::SharedState;
+use crate::environment::is_valid_var;
+use crate::response::{AppError, AppJson, JsonResult};
+use crate::secrets::{open_vault, VaultError};
+use crate::{routing::route, AppRouter};
+use axum::extract::{Path, State};
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use dry_console_dto::secrets::{SecretValue, VaultPassphrase, VaultStatus};
+use tracing::info;
+
+pub fn router() -> AppRouter {
+    Router::new()
+        .merge(status())
+        .merge(unlock())
+        .merge(lock())
+        .merge(save())
+        .merge(remove())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/secrets/",
+    responses(
+        (status = OK, body = VaultStatus, description = "Is the vault unlocked, and the names (never the values) of its secrets")
+    )
+)]
+fn status() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<VaultStatus> {
+        Ok(AppJson(state.read().await.vault.status()))
+    }
+    route("/", get(handler))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/secrets/unlock/",
+    request_body = VaultPassphrase,
+    responses(
+        (status = OK, body = VaultStatus, description = "Unlock the vault until the server stops (or create it, with this passphrase, if it is confirmed)"),
+        (status = CONFLICT, description = "The passphrase is empty, wrong or unconfirmed, or the vault is invalid")
+    )
+)]
+fn unlock() -> AppRouter {
+    async fn handler(
+        State(state): State<SharedState>,
+        Json(passphrase): Json<VaultPassphrase>,
+    ) -> JsonResult<VaultStatus> {
+        let path = state
+            .read()
+            .await
+            .vault
+            .path()
+            .map(|p| p.to_path_buf())
+            .ok_or(VaultError::NoPath)?;
+        // Deriving the key is slow, on purpose:
+        let vault = tokio::task::spawn_blocking(move || {
+            open_vault(&path, &passphrase.passphrase, passphrase.confirm.as_deref())
+        })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))??;
+        let mut state = state.write().await;
+        state.vault.unlock(vault);
+        info!("The secrets vault was unlocked.");
+        Ok(AppJson(state.vault.status()))
+    }
+    route("/unlock", post(handler))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/secrets/lock/",
+    responses(
+        (status = OK, body = VaultStatus, description = "Lock the vault, forgetting its key")
+    )
+)]
+fn lock() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<VaultStatus> {
+        let mut state = state.write().await;
+        state.vault.lock();
+        info!("The secrets vault was locked.");
+        Ok(AppJson(state.vault.status()))
+    }
+    route("/lock", post(handler))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/secrets/{name}/",
+    request_body = SecretValue,
+    responses(
+        (status = OK, body = VaultStatus, description = "Add or change a secret, and save the vault"),
+        (status = CONFLICT, description = "The vault is locked, or the name is invalid")
+    ),
+    params(
+        ("name" = String, Path, description = "The name of the secret (the environment variable it is passed to scripts as)")
+    )
+)]
+fn save() -> AppRouter {
+    async fn handler(
+        Path(name): Path<String>,
+        State(state): State<SharedState>,
+        Json(secret): Json<SecretValue>,
+    ) -> JsonResult<VaultStatus> {
+        if !is_valid_var(&name) {
+            return Err(AppError::Conflict(format!("Invalid secret name: {name}")));
+        }
+        let mut state = state.write().await;
+        state.vault.set(&name, Some(secret.value))?;
+        Ok(AppJson(state.vault.status()))
+    }
+    route("/:name", put(handler))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/secrets/{name}/",
+    responses(
+        (status = OK, body = VaultStatus, description = "Remove a secret, and save the vault"),
+        (status = NOT_FOUND, description = "Secret not found"),
+        (status = CONFLICT, description = "The vault is locked")
+    ),
+    params(
+        ("name" = String, Path, description = "The name of the secret")
+    )
+)]
+fn remove() -> AppRouter {
+    async fn handler(
+        Path(name): Path<String>,
+        State(state): State<SharedState>,
+    ) -> JsonResult<VaultStatus> {
+        let mut state = state.write().await;
+        if !state.vault.set(&name, None)? {
+            return Err(AppError::NotFound);
+        }
+        Ok(AppJson(state.vault.status()))
+    }
+    route("/:name", delete(handler))
+}
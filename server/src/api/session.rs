@@ -1,7 +1,8 @@
 use crate::{
     api::auth::{Backend, Credentials},
     app_state::SharedState,
-    response::AppJson,
+    metrics::LOGIN_ATTEMPTS,
+    response::{AppError, AppJson},
     routing::route,
     AppRouter,
 };
@@ -15,6 +16,7 @@ use axum::{
 use axum_login::tower_sessions::Session;
 use axum_login::AuthSession;
 use axum_messages::Messages;
+use dry_console_dto::events::Event;
 use dry_console_dto::session::{SessionMessages, SessionState};
 use tracing::{debug, info, warn};
 
@@ -30,6 +32,14 @@ pub fn router(backend: Backend) -> Router<SharedState> {
         .with_state(s.0)
 }
 
+/// The path of the session cookie for the given --base-path.
+pub fn cookie_path(base_path: &str) -> String {
+    match base_path {
+        "" => "/".to_string(),
+        p => p.to_string(),
+    }
+}
+
 async fn is_new_login_allowed(state: SharedState) -> bool {
     let state = state.read().await;
     state.is_login_allowed()
@@ -82,6 +92,7 @@ fn login() -> AppRouter {
             let state = state.read().await;
             if !state.is_login_allowed() {
                 warn!("Prevented login attempt - the login service is disabled.");
+                LOGIN_ATTEMPTS.with_label_values(&["disabled"]).inc();
                 return (
                     StatusCode::SERVICE_UNAVAILABLE,
                     "The login service is currently disabled.",
@@ -92,6 +103,7 @@ fn login() -> AppRouter {
         //debug!("{:?}", creds);
         let user = match auth_session.authenticate(creds.clone()).await {
             Ok(Some(user)) => {
+                LOGIN_ATTEMPTS.with_label_values(&["success"]).inc();
                 {
                     let mut s = state.write().await;
                     // Successful login.
@@ -109,10 +121,12 @@ fn login() -> AppRouter {
             }
             Ok(None) => {
                 warn!("Attempted login with invalid username or password.");
+                LOGIN_ATTEMPTS.with_label_values(&["invalid"]).inc();
                 return StatusCode::UNAUTHORIZED.into_response();
             }
             Err(e) => {
                 debug!("{:?}", e);
+                LOGIN_ATTEMPTS.with_label_values(&["error"]).inc();
                 return StatusCode::INTERNAL_SERVER_ERROR.into_response();
             }
         };
@@ -141,8 +155,11 @@ fn login() -> AppRouter {
 fn logout() -> AppRouter {
     async fn handler(
         mut auth_session: AuthSession<Backend>,
+        session: Session,
         State(state): State<SharedState>,
     ) -> impl IntoResponse {
+        // The id is gone once the session is logged out:
+        let session_id = session.id();
         let status_code = match auth_session.logout().await {
             Ok(_) => StatusCode::OK,
             Err(e) => {
@@ -150,11 +167,24 @@ fn logout() -> AppRouter {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
         };
+        // Every other tab of this session is logged out too:
+        if let Some(id) = session_id {
+            state
+                .read()
+                .await
+                .events
+                .publish(Event::SessionRevoked(id.to_string()));
+        }
         // Set cookie to expire:
-        let headers = [(
-            header::SET_COOKIE,
-            HeaderValue::from_str("id=; Max-Age=0; Path=/; HttpOnly").unwrap(),
-        )];
+        let path = cookie_path(&state.read().await.opt.base_path());
+        let cookie = match HeaderValue::from_str(&format!("id=; Max-Age=0; Path={path}; HttpOnly"))
+        {
+            Ok(cookie) => cookie,
+            Err(e) => {
+                return AppError::Internal(format!("Invalid session cookie: {e}")).into_response()
+            }
+        };
+        let headers = [(header::SET_COOKIE, cookie)];
         (
             status_code,
             headers,
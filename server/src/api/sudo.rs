@@ -0,0 +1,130 @@
+use crate::api::websocket::push_websocket;
+use crate::response::{AppJson, JsonResult};
+use crate::{api::route, app_state::SharedState, broadcast, sudo, AppRouter};
+use axum::extract::State;
+use axum::{
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use axum_typed_websockets::{WebSocket, WebSocketUpgrade};
+use dry_console_dto::sudo::{SudoPassword, SudoServerMsg, SudoStatus};
+use dry_console_dto::websocket::SubscriberMsg;
+use futures::StreamExt;
+use tokio_stream::wrappers::WatchStream;
+use tracing::debug;
+
+pub fn router(shutdown: broadcast::Sender<()>, state: State<SharedState>) -> AppRouter {
+    Router::new()
+        .merge(status())
+        .merge(status_websocket(shutdown, state))
+        .merge(acquire())
+        .merge(password())
+        .merge(cancel())
+}
+
+async fn current_status(state: &SharedState) -> SudoStatus {
+    state.read().await.sudo_status.borrow().clone()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/sudo/",
+    responses(
+        (status = OK, description = "Sudo status", body = SudoStatus)
+    ),
+)]
+fn status() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<SudoStatus> {
+        Ok(AppJson(current_status(&state).await))
+    }
+    route("/", get(handler))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/sudo/status/",
+    responses(
+        (status = OK, description = "Open websocket connection to receive the sudo status, every time it changes")
+    )
+)]
+fn status_websocket(shutdown: broadcast::Sender<()>, state: State<SharedState>) -> AppRouter {
+    /// WebSocket connection handler
+    async fn websocket(
+        socket: WebSocket<SudoServerMsg, SubscriberMsg>,
+        shutdown: broadcast::Receiver<()>,
+        State(state): State<SharedState>,
+    ) {
+        let status = state.read().await.sudo_status.subscribe();
+        let updates = WatchStream::new(status).map(SudoServerMsg::Status);
+        push_websocket(socket, shutdown, updates).await;
+    }
+
+    /// Upgrade HTTP connection to WebSocket
+    async fn upgrade(
+        ws: WebSocketUpgrade<SudoServerMsg, SubscriberMsg>,
+        shutdown: broadcast::Sender<()>,
+        state: State<SharedState>,
+    ) -> impl IntoResponse {
+        let shutdown_rx = shutdown.subscribe();
+        debug!("Sudo status WebSocket upgrade request received.");
+        ws.on_upgrade(move |socket| websocket(socket, shutdown_rx, state))
+    }
+
+    route(
+        "/status/",
+        get(move |ws: WebSocketUpgrade<_, _>| upgrade(ws, shutdown.clone(), state)),
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/sudo/acquire/",
+    responses(
+        (status = OK, description = "Sudo is either waiting for the password, or has finished authenticating", body = SudoStatus),
+        (status = CONFLICT, description = "Sudo is disabled, or is already authenticating")
+    ),
+)]
+fn acquire() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<SudoStatus> {
+        sudo::acquire_sudo_askpass(state.clone()).await?;
+        Ok(AppJson(current_status(&state).await))
+    }
+    route("/acquire", post(handler))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/sudo/password/",
+    responses(
+        (status = OK, description = "Password passed to sudo", body = SudoStatus),
+        (status = CONFLICT, description = "Sudo is not waiting for a password")
+    ),
+    request_body = SudoPassword,
+)]
+fn password() -> AppRouter {
+    async fn handler(
+        State(state): State<SharedState>,
+        Json(password): Json<SudoPassword>,
+    ) -> JsonResult<SudoStatus> {
+        sudo::send_password(state.clone(), password.password).await?;
+        Ok(AppJson(current_status(&state).await))
+    }
+    route("/password", post(handler))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/sudo/cancel/",
+    responses(
+        (status = OK, description = "Authentication cancelled", body = SudoStatus),
+        (status = CONFLICT, description = "Sudo is not authenticating")
+    ),
+)]
+fn cancel() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<SudoStatus> {
+        sudo::cancel(state.clone()).await?;
+        Ok(AppJson(current_status(&state).await))
+    }
+    route("/cancel", post(handler))
+}
@@ -1,13 +1,12 @@
 use crate::broadcast;
+use crate::metrics::WebSocketGuard;
 use axum::extract::ws::CloseFrame;
 use axum_typed_websockets::{Message, WebSocket};
 use dry_console_dto::websocket::CloseCode;
 use dry_console_dto::websocket::WebSocketMessage;
-use std::future::Future;
-use std::pin::Pin;
-use std::sync::Arc;
+use futures::{Stream, StreamExt};
 use std::time::Duration;
-use tokio::sync::Mutex;
+use tokio::sync::mpsc;
 use tokio::time::Instant;
 
 use tracing::*;
@@ -21,138 +20,140 @@ pub struct WebSocketResponse {
     pub close_message: String,
 }
 
+/// Handle a websocket on which both sides may send at any time: each
+/// message from the client is passed to on_message, and every message
+/// sent to the outbox is forwarded to the client (eg. the output of
+/// many concurrent processes). Returns once either side closes it, or
+/// on_message asks to close it.
 pub async fn handle_websocket<T, U, F>(
-    socket: Arc<Mutex<Option<WebSocket<T, U>>>>,
+    mut socket: WebSocket<T, U>,
     mut shutdown: broadcast::Receiver<()>,
+    mut outbox: mpsc::Receiver<T>,
     mut on_message: F,
 ) where
     T: WebSocketMessage + 'static,
     U: WebSocketMessage + 'static + PartialEq,
-    F: FnMut(Message<U>) -> Pin<Box<dyn Future<Output = Option<WebSocketResponse>> + Send>>,
+    F: FnMut(U) -> Option<WebSocketResponse>,
 {
-    let last_ping = Arc::new(Mutex::new(None));
+    let _connection = WebSocketGuard::open();
     let mut ping_interval = tokio::time::interval(Duration::from_millis(PING_INTERVAL));
-    let mut ping_timeout: Option<Pin<Box<tokio::time::Sleep>>> = None;
-
-    let mut consecutive_missed_pongs = 0;
-    let mut close_code: Option<CloseCode>;
-    let mut close_message: Option<String>;
-
-    loop {
+    let mut last_ping: Option<Instant> = None;
+    let (close_code, close_message) = loop {
         tokio::select! {
-            _ = ping_interval.tick() => {
-                let mut socket_guard = socket.lock().await;
-                if consecutive_missed_pongs > 3 {
-                    close_code = Some(CloseCode::PolicyViolation);
-                    close_message = Some("Missed 3 consecutive pongs. Disconnecting...".to_string());
-                    debug!("{}", close_message.as_ref().unwrap());
-                    break;
+            // Answer the client (and ping it) before sending any more
+            // output, so a busy outbox can't starve the pings:
+            biased;
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Item(msg))) if msg == U::PONG => {
+                    match last_ping.take() {
+                        Some(instant) => {
+                            let report = T::ping_report(Instant::now().duration_since(instant));
+                            socket.send(Message::Item(report)).await.ok();
+                        }
+                        None => {
+                            debug!("Unexpected Pong.");
+                            break (CloseCode::UnsupportedData, "Unexpected Pong".to_string());
+                        }
+                    }
                 }
-
-                *last_ping.lock().await = Some(Instant::now());
-                if let Some(socket) = socket_guard.as_mut() {
-                    socket.send(Message::Item(T::PING)).await.ok();
-                    //debug!("Ping message sent!");
-                    ping_timeout = Some(Box::pin(tokio::time::sleep(Duration::from_secs(20))));
-                } else {
-                    close_code = Some(CloseCode::NormalClosure);
-                    close_message = Some("Socket closed".to_string());
-                    break;
+                Some(Ok(Message::Item(msg))) => {
+                    if let Some(response) = on_message(msg) {
+                        if response.close {
+                            debug!("Closing : {:?} {:?}", response.close_code, response.close_message);
+                            break (response.close_code, response.close_message);
+                        }
+                    }
                 }
-
-                consecutive_missed_pongs += 1;
-            },
-            _ = async {
-                if let Some(timeout) = &mut ping_timeout {
-                    timeout.await;
-                    true
-                } else {
-                    false
+                Some(Ok(_)) => {}
+                Some(Err(err)) => {
+                    debug!("Websocket closed after parse error: {}", err);
+                    break (CloseCode::InvalidFramePayloadData, format!("Error parsing message: {}", err));
                 }
-            } => {
-                if (*last_ping.lock().await).is_some() {
-                    close_code = Some(CloseCode::PolicyViolation);
-                    close_message = Some("Pong response not received in time".to_string());
-                    debug!("Pong response not received within 20s. Disconnecting...");
-                    break;
+                None => {
+                    debug!("Websocket closed by client.");
+                    return;
                 }
             },
-            msg = async {
-                let mut socket_guard = socket.lock().await;
-                if let Some(socket) = socket_guard.as_mut() {
-                    socket.recv().await
-                } else {
-                    None
-                }
-            } => {
-                match msg {
-                    Some(Ok(Message::Item(msg))) if msg == U::PONG => {
-                        //debug!("Pong message received!");
-                        let mut last_ping_guard = last_ping.lock().await;
-                        if let Some(instant) = *last_ping_guard {
-                            *last_ping_guard = None;
-                            if let Some(socket) = socket.lock().await.as_mut() {
-                                socket.send(Message::Item(T::ping_report(Instant::now().duration_since(instant)))).await.ok();
-                            }
-                            ping_timeout = None;
-                            consecutive_missed_pongs = 0;
-                        } else {
-                            close_code = Some(CloseCode::UnsupportedData);
-                            close_message = Some("Unexpected Pong".to_string());
-                            debug!("Unexpected Pong.");
-                            break;
-                        }
-                    },
-                    Some(Ok(item)) => {
-                        if let Some(response) = on_message(item).await {
-                            close_code = Some(response.close_code);
-                            close_message = Some(response.close_message);
-                            if response.close {
-                                debug!("Closing : {:?} {:?}", close_code, close_message);
-                                break;
-                            }
-                        }
-                    },
-                    Some(Err(err)) => {
-                        close_code = Some(CloseCode::InvalidFramePayloadData);
-                        close_message = Some(format!("Error parsing message: {}", err));
-                        debug!("Websocket closed after parse error: {}",err);
-                        break;
-                    },
-                    None => {
-                        close_code = Some(CloseCode::NormalClosure);
-                        close_message = Some("Connection closed by client.".to_string());
-                        debug!("Websocket closed by client.");
-                        break;
-                    }
+            _ = ping_interval.tick() => {
+                if last_ping.is_some_and(|p| p.elapsed() > Duration::from_millis(PING_INTERVAL * 3)) {
+                    debug!("Pong response not received in time. Disconnecting...");
+                    break (CloseCode::PolicyViolation, "Pong response not received in time".to_string());
                 }
+                last_ping.get_or_insert_with(Instant::now);
+                socket.send(Message::Item(T::PING)).await.ok();
             },
-            _ = shutdown.recv() => {
-                close_code = Some(CloseCode::GoingAway);
-                close_message = Some("Server is shutting down.".to_string());
-                break;
+            _ = shutdown.recv() => break (CloseCode::GoingAway, "Server is shutting down.".to_string()),
+            Some(msg) = outbox.recv() => {
+                if socket.send(Message::Item(msg)).await.is_err() {
+                    return;
+                }
             },
         }
-    }
+    };
 
     debug!("Disconnecting socket...");
     let close_frame = CloseFrame {
-        code: close_code.unwrap_or(CloseCode::NormalClosure).into(),
-        reason: close_message
-            .unwrap_or_else(|| "Goodbye.".to_string())
-            .into(),
+        code: close_code.into(),
+        reason: close_message.into(),
     };
+    socket.send(Message::Close(Some(close_frame))).await.ok();
+    info!("Closed websocket.");
+}
 
-    let mut socket_guard = socket.lock().await;
-    if let Some(mut socket_owned) = socket_guard.take() {
-        if let Err(err) = socket_owned.send(Message::Close(Some(close_frame))).await {
-            eprintln!("Error sending close frame: {:?}", err);
-        }
-
-        if let Err(err) = socket_owned.close().await {
-            eprintln!("Error closing the socket: {:?}", err);
+/// Handle a websocket that pushes every item of `updates` to the
+/// client, until either side closes it. Unlike handle_websocket, the
+/// client only ever needs to answer pings, so the socket is owned by
+/// this loop and does not need to be shared.
+pub async fn push_websocket<T, U, S>(
+    mut socket: WebSocket<T, U>,
+    mut shutdown: broadcast::Receiver<()>,
+    updates: S,
+) where
+    T: WebSocketMessage + 'static,
+    U: WebSocketMessage + 'static + PartialEq,
+    S: Stream<Item = T>,
+{
+    let _connection = WebSocketGuard::open();
+    let mut updates = std::pin::pin!(updates);
+    let mut ping_interval = tokio::time::interval(Duration::from_millis(PING_INTERVAL));
+    let mut last_ping: Option<Instant> = None;
+    let (close_code, close_message) = loop {
+        tokio::select! {
+            update = updates.next() => match update {
+                Some(update) => {
+                    if socket.send(Message::Item(update)).await.is_err() {
+                        return;
+                    }
+                }
+                None => break (CloseCode::NormalClosure, "No more updates."),
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Item(msg))) if msg == U::PONG => {
+                    if let Some(instant) = last_ping.take() {
+                        let report = T::ping_report(Instant::now().duration_since(instant));
+                        socket.send(Message::Item(report)).await.ok();
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(err)) => {
+                    debug!("Websocket closed after parse error: {}", err);
+                    break (CloseCode::InvalidFramePayloadData, "Error parsing message.");
+                }
+                None => return,
+            },
+            _ = ping_interval.tick() => {
+                if last_ping.is_some_and(|p| p.elapsed() > Duration::from_millis(PING_INTERVAL * 3)) {
+                    break (CloseCode::PolicyViolation, "Pong response not received in time.");
+                }
+                last_ping.get_or_insert_with(Instant::now);
+                socket.send(Message::Item(T::PING)).await.ok();
+            },
+            _ = shutdown.recv() => break (CloseCode::GoingAway, "Server is shutting down."),
         }
-    }
-
-    info!("Closed websocket.");
+    };
+    let close_frame = CloseFrame {
+        code: close_code.into(),
+        reason: close_message.into(),
+    };
+    socket.send(Message::Close(Some(close_frame))).await.ok();
 }
@@ -2,23 +2,32 @@ use crate::broadcast;
 use crate::{api::route, app_state::SharedState, response::AppError};
 use axum::extract::State;
 use axum::{extract::Path, response::IntoResponse, routing::get, Json, Router};
+use dry_console_dto::events::{DependencyChanged, Event};
 pub use dry_console_dto::workstation::{
-    WorkstationDependencyInfo, WorkstationPackage, WorkstationPackageManager, WorkstationState,
-    WorkstationUser,
+    Platform, WorkstationDependencyInfo, WorkstationPackage, WorkstationPackageManager,
+    WorkstationState, WorkstationUser,
 };
 use hostname::get as host_name_get;
 use semver::VersionReq;
 use serde::Serialize;
+use std::collections::HashMap;
 use std::{ffi::OsStr, str::FromStr};
 use strum::{AsRefStr, EnumIter, EnumProperty, EnumString, IntoEnumIterator};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{self, Duration};
 use utoipa::ToSchema;
 use uzers::{get_current_uid, get_user_by_uid};
 use which::which;
 
 pub mod command;
 pub mod command_execute;
+pub mod d_rymcg_tech;
 mod dependencies;
+pub mod diagnostics;
+pub mod facts;
 pub mod platform;
+pub mod trust;
+pub mod workflow;
 
 #[derive(Debug, Clone)]
 pub enum WorkstationError {
@@ -32,6 +41,11 @@ pub fn router(shutdown: broadcast::Sender<()>, state: State<SharedState>) -> Rou
         .merge(required_dependencies())
         .merge(dependencies())
         .merge(command::command())
+        .merge(d_rymcg_tech::d_rymcg_tech())
+        .merge(diagnostics::router())
+        .merge(facts::facts())
+        .merge(trust::router())
+        .merge(workflow::router())
         .merge(command_execute::main(shutdown, state))
 }
 
@@ -66,6 +80,48 @@ pub enum WorkstationDependency {
     curl,
 }
 
+/// How often to check if any dependency was installed, or removed,
+/// outside of dry_console (eg. from the workstation's own terminal).
+const DEPENDENCY_POLL_INTERVAL: u64 = 30;
+
+/// Which of the dependencies are installed, by name.
+pub fn installed_dependencies() -> HashMap<String, bool> {
+    WorkstationDependency::iter()
+        .map(|dep| (dep.get_name().to_string(), which(dep.get_name()).is_ok()))
+        .collect()
+}
+
+/// Task that publishes a DependencyChanged event whenever a dependency
+/// is installed or removed. Dependencies are checked again after every
+/// process finishes, and every DEPENDENCY_POLL_INTERVAL.
+pub async fn watch_dependencies(state: SharedState) {
+    let mut events = state.read().await.events.subscribe();
+    let mut poll = time::interval(Duration::from_secs(DEPENDENCY_POLL_INTERVAL));
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(Event::ProcessFinished(_)) | Err(RecvError::Lagged(_)) => {}
+                Ok(_) => continue,
+                Err(RecvError::Closed) => return,
+            },
+            _ = poll.tick() => {}
+        }
+        let installed = installed_dependencies();
+        let mut state = state.write().await;
+        for (name, installed) in &installed {
+            if state.installed_dependencies.get(name) != Some(installed) {
+                state
+                    .events
+                    .publish(Event::DependencyChanged(DependencyChanged {
+                        name: name.clone(),
+                        installed: *installed,
+                    }));
+            }
+        }
+        state.installed_dependencies = installed;
+    }
+}
+
 impl WorkstationDependency {
     fn get_version(&self) -> VersionReq {
         VersionReq::parse(self.get_str("Version").unwrap_or("*")).unwrap()
@@ -73,6 +129,27 @@ impl WorkstationDependency {
     fn get_name(&self) -> &str {
         self.get_str("Name").unwrap_or_else(|| self.as_ref())
     }
+    /// The packages that provide the dependency on the platform.
+    fn get_packages(
+        &self,
+        platform: Platform,
+    ) -> Result<Vec<WorkstationPackage>, WorkstationError> {
+        match self {
+            WorkstationDependency::git => dependencies::git::get_packages(platform),
+            WorkstationDependency::docker => dependencies::docker::get_packages(platform),
+            WorkstationDependency::bash => dependencies::bash::get_packages(platform),
+            WorkstationDependency::make => dependencies::make::get_packages(platform),
+            WorkstationDependency::ssh => dependencies::ssh::get_packages(platform),
+            WorkstationDependency::sed => dependencies::sed::get_packages(platform),
+            WorkstationDependency::xargs => dependencies::xargs::get_packages(platform),
+            WorkstationDependency::shred => dependencies::shred::get_packages(platform),
+            WorkstationDependency::openssl => dependencies::openssl::get_packages(platform),
+            WorkstationDependency::htpasswd => dependencies::htpasswd::get_packages(platform),
+            WorkstationDependency::jq => dependencies::jq::get_packages(platform),
+            WorkstationDependency::xdg_open => dependencies::xdg_open::get_packages(platform),
+            WorkstationDependency::curl => dependencies::curl::get_packages(platform),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, Serialize, ToSchema)]
@@ -132,7 +209,7 @@ fn workstation() -> Router<SharedState> {
     ),
 )]
 fn required_dependencies() -> Router<SharedState> {
-    async fn handler(State(state): State<SharedState>) -> impl IntoResponse {
+    async fn handler() -> impl IntoResponse {
         let deps: Vec<WorkstationDependencyInfo> = WorkstationDependency::iter()
             .map(|dep| WorkstationDependencyInfo {
                 name: dep.get_name().to_string(),
@@ -141,8 +218,6 @@ fn required_dependencies() -> Router<SharedState> {
                     .expect("failed to get package definitions"),
             })
             .collect();
-        let mut state = state.write().await;
-        state.missing_dependencies.clear();
         Json(&deps).into_response()
     }
     route("/dependencies/", get(handler))
@@ -159,10 +234,7 @@ fn required_dependencies() -> Router<SharedState> {
     )
 )]
 fn dependencies() -> Router<SharedState> {
-    async fn handler(
-        Path(name): Path<String>,
-        State(state): State<SharedState>,
-    ) -> impl IntoResponse {
+    async fn handler(Path(name): Path<String>) -> impl IntoResponse {
         match WorkstationDependency::from_str(&name.clone().replace('-', "_")).ok() {
             Some(dependency) => {
                 // Check if dependency is installed:
@@ -221,86 +293,11 @@ fn dependencies() -> Router<SharedState> {
                         }
                     }
                 }
-                match dependency {
-                    WorkstationDependency::git => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::git::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::docker => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::docker::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::bash => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::bash::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::ssh => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::ssh::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::make => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::make::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::sed => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::sed::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::xargs => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::xargs::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::shred => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::shred::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::openssl => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::openssl::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::htpasswd => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::htpasswd::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::jq => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::jq::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::xdg_open => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::xdg_open::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                    WorkstationDependency::curl => {
-                        packages.as_mut().unwrap().extend(
-                            dependencies::curl::get_packages(platform)
-                                .expect("did not get package definitions"),
-                        );
-                    }
-                }
+                packages.as_mut().unwrap().extend(
+                    dependency
+                        .get_packages(platform)
+                        .expect("did not get package definitions"),
+                );
                 let dep_state = WorkstationDependencyState {
                     name,
                     installed,
@@ -308,11 +305,6 @@ fn dependencies() -> Router<SharedState> {
                     version,
                     packages: packages.expect("failed to find packages definition"),
                 };
-                if !installed {
-                    let mut state = state.write().await;
-                    state.missing_dependencies.push(dep_state.clone());
-                    //debug!("missing_dependencies: {:?}", state.missing_dependencies);
-                }
                 Json(dep_state)
             }
             .into_response(),
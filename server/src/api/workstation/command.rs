@@ -1,18 +1,21 @@
 use crate::app_state::SharedState;
 use crate::response::{AppError, AppJson, JsonResult};
+use crate::shell_library::{RenderError, RenderedScript};
+use crate::template::render_template;
 use crate::{routing::route, AppRouter};
 use axum::extract::State;
 use axum::{extract::Path, routing::get};
 pub use dry_console_dto::script::ScriptEntry;
-use dry_console_dto::workstation::{Distribution, WorkstationPackageManager};
-use indoc::formatdoc;
-use itertools::Itertools;
-use std::collections::{HashMap, HashSet};
+use dry_console_dto::script::{is_template, script_body, ScriptRequirements, UnmetRequirement};
+use std::collections::HashMap;
 use std::str::FromStr;
 use strum::{AsRefStr, Display, EnumIter, EnumString, VariantNames};
 use ulid::Ulid;
+use which::which;
 
-use super::WorkstationDependencyState;
+use super::diagnostics::script_diagnostics;
+use super::facts::workstation_facts;
+use super::{platform, WorkstationDependency};
 
 #[derive(
     EnumString, VariantNames, Display, AsRefStr, EnumIter, PartialEq, Debug, Clone, Hash, Eq,
@@ -23,6 +26,25 @@ pub enum CommandLibrary {
     InstallDRymcgTech,
 }
 impl CommandLibrary {
+    /// The script as it is run: with the libraries it includes expanded,
+    /// and, if it is a template, rendered with the facts of the workstation.
+    pub async fn render(&self, state: &SharedState) -> Result<RenderedScript, RenderError> {
+        let (source, mut script) = {
+            let state = state.read().await;
+            let source = self.get_script(&state.command_id, &state.command_script);
+            let script = state
+                .shell_library
+                .render(&source)
+                .map_err(RenderError::Include)?;
+            (source, script)
+        };
+        if is_template(&source) {
+            script.source = render_template(&script.expanded, &workstation_facts(state).await)
+                .map_err(RenderError::Template)?;
+        }
+        Ok(script)
+    }
+
     pub async fn from_id(
         id: Ulid,
         command_library: HashMap<String, CommandLibrary>,
@@ -30,71 +52,113 @@ impl CommandLibrary {
         command_library.get(&id.to_string()).cloned()
     }
 }
-fn generate_install_commands(uninstalled_dependencies: &[WorkstationDependencyState]) -> String {
-    let mut package_map: HashMap<&str, HashSet<String>> = HashMap::new();
-
-    for dep in uninstalled_dependencies {
-        for pkg in &dep.packages {
-            let manager = match pkg.package_manager.clone() {
-                WorkstationPackageManager::Dnf => "dnf",
-                WorkstationPackageManager::Pacman => "pacman",
-                WorkstationPackageManager::Apt => "apt",
-                WorkstationPackageManager::Apk => "apk",
-            };
-            package_map
-                .entry(manager)
-                .or_default()
-                .insert(pkg.package_name.clone());
+/// Check the requirements declared in a script header against this
+/// workstation. Scripts are only run once this returns nothing.
+pub async fn unmet_requirements(
+    requirements: &ScriptRequirements,
+    state: &SharedState,
+) -> Vec<UnmetRequirement> {
+    let mut unmet: Vec<_> = requirements
+        .invalid_directives
+        .iter()
+        .map(|message| UnmetRequirement::InvalidDirective(message.clone()))
+        .collect();
+    let root = {
+        let state = state.read().await;
+        // (pkexec can not be used without prompting, see $DRY_SUDO.)
+        state.sudo_enabled && state.escalator.is_some_and(|e| e.script_prefix().is_some())
+    };
+    if requirements.sudo && !root {
+        unmet.push(UnmetRequirement::Sudo);
+    }
+    if !requirements.platforms.is_empty() {
+        let platform = platform::detect_platform();
+        let distribution = platform.distribution.to_string().to_lowercase();
+        let os_type = platform.os_type.to_string().to_lowercase();
+        if !requirements
+            .platforms
+            .iter()
+            .any(|p| *p == distribution || *p == os_type)
+        {
+            unmet.push(UnmetRequirement::Platform {
+                supported: requirements.platforms.clone(),
+                detected: distribution,
+            });
+        }
+    }
+    for name in &requirements.needs {
+        // Known dependencies may have a different program name (eg. xdg_open):
+        let program = WorkstationDependency::from_str(&name.replace('-', "_"))
+            .map(|d| d.get_name().to_string())
+            .unwrap_or_else(|_| name.clone());
+        if which(&program).is_err() {
+            unmet.push(UnmetRequirement::Dependency(name.clone()));
         }
     }
+    if !requirements.secrets.is_empty() {
+        let state = state.read().await;
+        match state.vault.is_unlocked() {
+            false => unmet.push(UnmetRequirement::VaultLocked),
+            true => {
+                let found = state.vault.lookup(&requirements.secrets);
+                for name in &requirements.secrets {
+                    if !found.contains_key(name) {
+                        unmet.push(UnmetRequirement::Secret(name.clone()));
+                    }
+                }
+            }
+        }
+    }
+    unmet
+}
 
-    let mut commands = Vec::<String>::new();
-    if let Some(packages) = package_map.get("dnf") {
-        commands.push(format!(
-            "sudo dnf install -y {}",
-            packages
-                .iter()
-                .sorted()
-                .cloned()
-                .collect::<Vec<String>>()
-                .join(" ")
-        ));
+/// Fill in the parts of a script entry that depend on this workstation:
+/// its unmet requirements (including approval), its rendering, and its
+/// diagnostics.
+async fn fill_in(script_entry: &mut ScriptEntry, command: &CommandLibrary, state: &SharedState) {
+    let rendered = command.render(state).await;
+    let script = match rendered {
+        Ok(script) => script,
+        Err(e) => {
+            script_entry.unmet_requirements =
+                unmet_requirements(&script_entry.requirements, state).await;
+            script_entry.unmet_requirements.push(e.into());
+            return;
+        }
+    };
+    if script_entry.template {
+        // The header may depend on the facts too:
+        let rendered_entry = match ScriptEntry::from_source(script.source.clone()) {
+            Ok(entry) => entry,
+            Err(e) => {
+                script_entry.unmet_requirements =
+                    unmet_requirements(&script_entry.requirements, state).await;
+                script_entry
+                    .unmet_requirements
+                    .push(UnmetRequirement::Template(e));
+                return;
+            }
+        };
+        script_entry.description = rendered_entry.description;
+        script_entry.requirements = rendered_entry.requirements;
+        script_entry.limits = rendered_entry.limits;
+        script_entry.locks = rendered_entry.locks;
     }
-    if let Some(packages) = package_map.get("pacman") {
-        commands.push(format!(
-            "sudo pacman -S --noconfirm {}",
-            packages
-                .iter()
-                .sorted()
-                .cloned()
-                .collect::<Vec<String>>()
-                .join(" ")
-        ));
+    script_entry.unmet_requirements = unmet_requirements(&script_entry.requirements, state).await;
+    if !script_entry.includes.is_empty() || script_entry.template {
+        script_entry.rendered = Some(script_body(&script.source));
     }
-    if let Some(packages) = package_map.get("apt") {
-        commands.push(format!(
-            "DEBIAN_FRONTEND=noninteractive apt-get install -y {}",
-            packages
-                .iter()
-                .sorted()
-                .cloned()
-                .collect::<Vec<String>>()
-                .join(" ")
-        ));
+    script_entry.diagnostics = script_diagnostics(&script, state).await;
+    if script_entry.has_errors() {
+        script_entry
+            .unmet_requirements
+            .push(UnmetRequirement::ScriptErrors(script_entry.error_count()));
     }
-    if let Some(packages) = package_map.get("apk") {
-        commands.push(format!(
-            "apk add --no-confirm {}",
-            packages
-                .iter()
-                .sorted()
-                .cloned()
-                .collect::<Vec<String>>()
-                .join(" ")
-        ));
+    if !state.read().await.trust.is_trusted(command, &script) {
+        script_entry
+            .unmet_requirements
+            .push(UnmetRequirement::Unapproved);
     }
-
-    commands.join("\n")
 }
 
 #[utoipa::path(
@@ -113,64 +177,19 @@ pub fn command() -> AppRouter {
         Path(command): Path<String>,
         State(state): State<SharedState>,
     ) -> JsonResult<ScriptEntry> {
-        // Special handling for scripts by name:
-        match command.as_str() {
-            "InstallDependencies" => {
-                let distribution;
-                {
-                    let state = state.write().await;
-                    distribution = state.platform.distribution.clone();
-                }
-                let package_manager = match distribution {
-                    Distribution::Fedora => WorkstationPackageManager::Dnf,
-                    _ => {
-                        return Err(AppError::Internal(
-                            "Unimplemented package manager for InstallDependencies script:"
-                                .to_string(),
-                        ))
-                    }
-                };
-                let script;
-                {
+        match CommandLibrary::from_str(&command) {
+            Ok(command) => {
+                let mut script_entry = {
                     let state = state.read().await;
-                    script = generate_install_commands(&state.missing_dependencies);
-                }
-                let script_entry = ScriptEntry::from_source(formatdoc! {"
-                    # # Install missing dependencies                    
-                    # This script is customized for {distribution} ({package_manager} package manager).
-                    {script}
-                "});
-                {
-                    let mut state = state.write().await;
-                    // debug!(
-                    //     "Inserting new command overlay: {}",
-                    //     script_entry.id.to_string()
-                    // );
-                    state.command_id.insert(
-                        CommandLibrary::InstallDependencies,
-                        script_entry.id.to_string(),
-                    );
-                    state
-                        .command_script
-                        .insert(script_entry.id.to_string(), script_entry.clone().script);
-                    state.command_library.insert(
-                        script_entry.id.to_string(),
-                        CommandLibrary::InstallDependencies,
-                    );
-                }
+                    ScriptEntry::from_source(
+                        command.get_script(&state.command_id, &state.command_script),
+                    )
+                    .map_err(|e| AppError::Internal(format!("{command}: {e}")))?
+                };
+                fill_in(&mut script_entry, &command, &state).await;
                 Ok(AppJson(script_entry))
             }
-            _ => match CommandLibrary::from_str(&command) {
-                // No special handling, return the static script:
-                Ok(command) => {
-                    let state = state.read().await;
-                    let script_entry = ScriptEntry::from_source(
-                        command.get_script(&state.command_id, &state.command_script),
-                    );
-                    Ok(AppJson(script_entry))
-                }
-                Err(_) => Err(AppError::NotFound),
-            },
+            Err(_) => Err(AppError::NotFound),
         }
     }
     route("/command/:command", get(handler))
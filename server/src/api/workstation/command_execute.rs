@@ -1,25 +1,80 @@
 use crate::api::websocket::{handle_websocket, WebSocketResponse};
-use crate::api::workstation::command::CommandLibrary;
+use crate::api::workstation::command::{unmet_requirements, CommandLibrary};
+use crate::api::workstation::diagnostics::script_diagnostics;
+use crate::api::workstation::workflow::run_workflow;
 use crate::app_state::SharedState;
 use crate::broadcast;
+use crate::environment;
+use crate::history::now_ms;
+use crate::limits;
+use crate::metrics::{ProcessGuard, PROCESS_EXITS};
+use crate::run_queue::Turn;
+use crate::secrets::Redactor;
+use crate::workflow_command::{CommandParser, Parsed, WorkflowCommand};
 use crate::{api::route, AppRouter};
 use axum::extract::State;
 use axum::{response::IntoResponse, routing::get, Router};
-use axum_typed_websockets::{Message, WebSocket, WebSocketUpgrade};
+use axum_typed_websockets::{WebSocket, WebSocketUpgrade};
+use dry_console_dto::events::{Event, ProcessFinished, ProcessStarted};
+use dry_console_dto::history::{RunRecord, RunTrigger};
+use dry_console_dto::script::{
+    script_locks, DiagnosticSeverity, ScriptLimits, ScriptRequirements, UnmetRequirement,
+};
 use dry_console_dto::websocket::{
-    ClientMsg, CloseCode, Process, ProcessComplete, ProcessOutput, ServerMsg, StreamType,
+    Capability, ClientMsg, CloseCode, Command, CompletionReason, Process, ProcessAnnotation,
+    ProcessComplete, ProcessError, ProcessGroup, ProcessOutput, ProcessProgress, ProcessQueued,
+    ProcessRejected, ProcessSetOutput, ServerEvent, ServerMsg, Signal, StreamType, Welcome,
+    PROTOCOL_VERSION,
 };
-use std::process::Stdio;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
-use tokio::sync::watch;
-use tokio::sync::Mutex;
+use dry_console_dto::workflow::parse_outputs;
+use futures::{stream, Stream};
+use nix::sys::signal::{killpg, Signal as NixSignal};
+use nix::unistd::Pid;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Child;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::WatchStream;
 use tokio_stream::StreamExt;
 use tracing::{debug, error, info};
 use ulid::Ulid;
-const TIMEOUT_INTERVAL: u64 = 2000;
+
+/// How many messages may be queued for a slow client, before the
+/// processes have to wait for it:
+const OUTBOX_SIZE: usize = 256;
+
+/// The most output read from a pipe at once:
+const CHUNK_SIZE: usize = 8192;
+
+/// Output is collected into batches, sent this often:
+const BATCH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The most output sent in one message:
+const BATCH_SIZE: usize = 64 * 1024;
+
+/// The most output held for a client that has fallen behind. Any more
+/// is dropped, rather than making the process wait for the client:
+const MAX_BUFFERED: usize = 1024 * 1024;
+
+/// How long to wait for the rest of the output, once a process has
+/// exited:
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How much of the end of the output is kept in the run history:
+const HISTORY_TAIL_SIZE: usize = 4096;
+
+/// How many control messages may be waiting for one process:
+pub const CONTROL_SIZE: usize = 8;
+
+/// The optional features of the command socket that this build supports:
+const CAPABILITIES: &[Capability] = &[Capability::Multiplex, Capability::Workflow];
 
 pub fn main(shutdown: broadcast::Sender<()>, state: State<SharedState>) -> AppRouter {
     Router::new().merge(command_execute(shutdown, state))
@@ -29,266 +84,132 @@ pub fn main(shutdown: broadcast::Sender<()>, state: State<SharedState>) -> AppRo
     get,
     path = "/api/workstation/command_execute/",
     responses(
-        (status = OK, description = "Open websocket connection to read executed command stdout")
+        (status = OK, description = "Open websocket connection to run any number of commands, and read their output")
     )
 )]
 fn command_execute(shutdown: broadcast::Sender<()>, state: State<SharedState>) -> AppRouter {
-    #[derive(PartialEq, Clone, Debug)]
-    enum SocketState {
-        AwaitingCommand,
-        RunningProcess,
-        Completed,
-    }
-
     /// WebSocket connection handler
     async fn websocket(
         socket: WebSocket<ServerMsg, ClientMsg>,
         shutdown: broadcast::Receiver<()>,
         State(shared_state): State<SharedState>,
     ) {
-        let state = Arc::new(Mutex::new(SocketState::AwaitingCommand));
-        let socket = Arc::new(Mutex::new(Some(socket))); // Ensure `socket` is Arc<Mutex<...>>
-
-        let (cancel_tx, cancel_rx) = watch::channel(false);
-
-        handle_websocket(socket.clone(), shutdown, move |msg| {
-            info!("WebSocket open!");
-            let state = state.clone();
-            let socket = socket.clone(); // Clone the Arc for use in the spawned task
-            let mut cancel_rx = cancel_rx.clone();
-            let cancel_tx = cancel_tx.clone();
-            let mut timeout_interval = tokio::time::interval(Duration::from_millis(TIMEOUT_INTERVAL));
-            let shared_state = shared_state.clone();
-            Box::pin(async move {
-                let mut state_ref = state.lock().await;
-                match *state_ref {
-                    SocketState::AwaitingCommand => match msg {
-                        Message::Item(ClientMsg::Command(command)) => {
-                            *state_ref = SocketState::RunningProcess;
-                            drop(state_ref); // Drop the lock on state to run the command
-                            let process_id = Ulid::new();
-                            let command_library = &shared_state.read().await.command_library.clone();
-                            let command = match CommandLibrary::from_id(command.id, command_library.clone()).await {
-                                Some(c) => c,
-                                None => {
-                                    error!("Failed to get script entry: {}", command.id);
-                                    return None;
-                                },
-                            };
-                            let script;
-                            {
-                                let shared_state = shared_state.read().await;
-                                //debug!("command_script: {:?}", shared_state.command_script.clone());
-                                script = command.get_script(&shared_state.command_id, &shared_state.command_script);
-                            }
-                            let mut process = Command::new("/bin/bash")
-                                .arg("-c")
-                                .arg(script)
-                                .stdout(Stdio::piped())
-                                .stderr(Stdio::piped())
-                                .spawn()
-                                .expect("Failed to start process");
-
-                            {
-                                let mut socket_ref = socket.lock().await;
-                                let socket_guard = socket_ref.as_mut().unwrap();
-                                socket_guard
-                                    .send(Message::Item(ServerMsg::Process(Process {
-                                        id: process_id,
-                                    })))
-                                    .await
-                                    .ok();
-                            }
-
-                            {
-                                let mut socket_ref = socket.lock().await;
-                                let socket_guard = socket_ref.as_mut().unwrap();
-                                socket_guard
-                                    .send(Message::Item(ServerMsg::ProcessOutput(ProcessOutput {
-                                        id: process_id,
-                                        stream: StreamType::Meta,
-                                        line: "## Connected. Running script ...".to_string()
-                                    })))
-                                    .await
-                                    .ok();
-                            }
-
-                            let stdout = process.stdout.take().expect("Failed to take stdout");
-                            let stderr = process.stderr.take().expect("Failed to take stderr");
-
-                            let stdout_reader = BufReader::new(stdout).lines();
-                            let stderr_reader = BufReader::new(stderr).lines();
-                            tokio::spawn({
-                                let socket = socket.clone(); // Clone the Arc again for the task
-                                async move {
-                                    let mut stdout_stream = tokio_stream::wrappers::LinesStream::new(stdout_reader).fuse();
-                                    let mut stderr_stream = tokio_stream::wrappers::LinesStream::new(stderr_reader).fuse();
-
-                                    let mut stdout_ended = false;
-                                    let mut stderr_ended = false;
-
-                                    loop {
-                                        tokio::select! {
-                                            _ = timeout_interval.tick() => {
-                                                if stdout_ended && stderr_ended {
-                                                    break;
-                                                }                                             },
-                                            stdout_line = stdout_stream.next(), if !stdout_ended => {
-                                                match stdout_line {
-                                                    Some(Ok(line_content)) => {
-                                                        let mut socket_ref = socket.lock().await;
-                                                        let socket_guard = socket_ref.as_mut().unwrap();
-                                                        socket_guard
-                                                            .send(Message::Item(ServerMsg::ProcessOutput(
-                                                                ProcessOutput {
-                                                                    stream: StreamType::Stdout,
-                                                                    id: process_id,
-                                                                    line: line_content,
-                                                                }
-                                                            )))
-                                                            .await
-                                                            .ok();
-                                                    }
-                                                    Some(Err(e)) => {
-                                                        eprintln!("Error reading stdout: {:?}", e);
-                                                    }
-                                                    None => {
-                                                        stdout_ended = true;
-                                                    }
-                                                }
-                                            }
-                                            stderr_line = stderr_stream.next(), if !stderr_ended => {
-                                                match stderr_line {
-                                                    Some(Ok(line_content)) => {
-                                                        let mut socket_ref = socket.lock().await;
-                                                        let socket_guard = socket_ref.as_mut().unwrap();
-                                                        socket_guard
-                                                            .send(Message::Item(ServerMsg::ProcessOutput(
-                                                                ProcessOutput {
-                                                                    stream: StreamType::Stderr,
-                                                                    id: process_id,
-                                                                    line: line_content,
-                                                                },
-                                                            )))
-                                                            .await
-                                                            .ok();
-                                                    }
-                                                    Some(Err(e)) => {
-                                                        eprintln!("Error reading stderr: {:?}", e);
-                                                    }
-                                                    None => {
-                                                        stderr_ended = true;
-                                                    }
-                                                }
-                                            }
-                                            _ = cancel_rx.changed() => {
-                                                if *cancel_rx.borrow() {
-                                                    process.kill().await.expect("Failed to kill process");
-                                                    let mut socket_ref = socket.lock().await;
-                                                    if let Some(mut socket_guard) = socket_ref.take() {
-                                                        socket_guard
-                                                            .send(Message::Item(ServerMsg::ProcessComplete(ProcessComplete {
-                                                                id: process_id,
-                                                                code: 128,
-                                                            })))
-                                                            .await
-                                                            .ok();
-                                                        if let Some(socket_guard) = socket_ref.take() {
-                                                            let _ = socket_guard.close().await;
-                                                            debug!("WebSocket closed!");
-                                                        } else {
-                                                            debug!("WebSocket already closed!");
-                                                        }
-                                                    }
-                                                    break;
-                                                }
-                                            }
-                                            else => {
-                                                if stdout_ended && stderr_ended {
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                    }
-
-                                    let status = process.wait().await.expect("Failed to wait on child process");
-
-                                    {
-                                        let mut socket_ref = socket.lock().await;
-                                        let socket_guard = socket_ref.as_mut().unwrap();
-                                        let line = match status.code().unwrap_or(128) {
-                                            0 => "## Complete.".to_string(),
-                                            code => format!("## Failed with code {}.", code)
-                                        };
-                                        socket_guard
-                                            .send(Message::Item(ServerMsg::ProcessOutput(ProcessOutput {
-                                                id: process_id,
-                                                stream: StreamType::Meta,
-                                                line
-                                            })))
-                                            .await
-                                            .ok();
-                                    }
-
-                                    let mut socket_ref = socket.lock().await;
-                                    if let Some(mut socket_guard) = socket_ref.take() {
-                                        socket_guard
-                                            .send(Message::Item(ServerMsg::ProcessComplete(ProcessComplete {
-                                                id: process_id,
-                                                code: status.code().unwrap_or(128),
-                                            })))
-                                            .await
-                                            .ok();
-                                        if let Some(socket_guard) = socket_ref.take() {
-                                            let _ = socket_guard.close().await;
-                                            debug!("WebSocket closed!");
-                                        } else {
-                                            debug!("WebSocket already closed!");
-                                        }
-                                    }
-
-                                    let mut state_ref = state.lock().await;
-                                    *state_ref = SocketState::Completed;
-                                }
-                            });
-
-                            None
-                        }
-                        Message::Item(ClientMsg::Cancel) => {
-                            let _ = cancel_tx.send(true);
-                            *state.lock().await = SocketState::Completed;
-                            None
-                        }
-                        r => Some(WebSocketResponse {
-                            close: true,
-                            close_code: CloseCode::UnsupportedData,
-                            close_message: format!("Received unexpected message: {r:?}"),
-                        }),
-                    },
-                    SocketState::RunningProcess => {
-                        match msg {
-                            Message::Item(ClientMsg::Cancel) => {
-                                let _ = cancel_tx.send(true);
-                                *state.lock().await = SocketState::Completed;
-                                None
-                            },
-                            m => {
-                                Some(WebSocketResponse {
-                                    close: true,
-                                    close_code: CloseCode::UnsupportedData,
-                                    close_message: format!("Received unexpected message: {:?}", m),
-                                })}
-                        }
-                    },
-                    SocketState::Completed => Some(WebSocketResponse {
-                        close: true,
-                        close_code: CloseCode::UnsupportedData,
-                        close_message: format!("Received unexpected message: {:?}", msg),
-                    }),
+        info!("WebSocket open!");
+        let (outbox, outbox_rx) = mpsc::channel(OUTBOX_SIZE);
+        let welcome = ServerMsg::Welcome(Welcome {
+            protocol_version: PROTOCOL_VERSION,
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities: CAPABILITIES.to_vec(),
+        });
+        outbox.send(welcome).await.ok();
+
+        // Events that are not about any one process:
+        let events = {
+            let outbox = outbox.clone();
+            let status = shared_state.read().await.sudo_status.subscribe();
+            tokio::spawn(async move {
+                let mut status = WatchStream::new(status);
+                while let Some(status) = status.next().await {
+                    let event = ServerMsg::Event(ServerEvent::SudoStatus(status));
+                    if outbox.send(event).await.is_err() {
+                        break;
+                    }
                 }
             })
+        };
+
+        // Dropping a control sender also cancels its process, so every
+        // process is stopped when the socket closes:
+        let mut processes: HashMap<Ulid, mpsc::Sender<ProcessControl>> = HashMap::new();
+        let mut greeted = false;
+        handle_websocket(socket, shutdown, outbox_rx, |msg| {
+            processes.retain(|_, control| !control.is_closed());
+            match msg {
+                ClientMsg::Hello(hello) if hello.protocol_version == PROTOCOL_VERSION => {
+                    info!(
+                        "Client version {} connected, with capabilities: {:?}",
+                        hello.client_version, hello.capabilities
+                    );
+                    greeted = true;
+                    None
+                }
+                // The client will see the mismatch in the Welcome, and ask to reload:
+                ClientMsg::Hello(hello) => Some(WebSocketResponse {
+                    close: true,
+                    close_code: CloseCode::ProtocolError,
+                    close_message: format!(
+                        "Unsupported protocol version {} (server supports {PROTOCOL_VERSION}).",
+                        hello.protocol_version
+                    ),
+                }),
+                _ if !greeted => Some(WebSocketResponse {
+                    close: true,
+                    close_code: CloseCode::ProtocolError,
+                    close_message: "Expected Hello before any other message.".to_string(),
+                }),
+                ClientMsg::Command(command) => {
+                    let process_id = command.process_id;
+                    if processes.contains_key(&process_id) {
+                        let error = ServerMsg::ProcessError(ProcessError {
+                            id: process_id,
+                            message: "The process id is already in use.".to_string(),
+                        });
+                        outbox.try_send(error).ok();
+                        return None;
+                    }
+                    let (control_tx, control_rx) = mpsc::channel(CONTROL_SIZE);
+                    processes.insert(process_id, control_tx);
+                    tokio::spawn(run_process(
+                        shared_state.clone(),
+                        command,
+                        BTreeMap::new(),
+                        outbox.clone(),
+                        control_rx,
+                        RunTrigger::Manual,
+                    ));
+                    None
+                }
+                ClientMsg::Workflow(run) => {
+                    let run_id = run.run_id;
+                    if processes.contains_key(&run_id) {
+                        let error = ServerMsg::ProcessError(ProcessError {
+                            id: run_id,
+                            message: "The run id is already in use.".to_string(),
+                        });
+                        outbox.try_send(error).ok();
+                        return None;
+                    }
+                    let (control_tx, control_rx) = mpsc::channel(CONTROL_SIZE);
+                    processes.insert(run_id, control_tx);
+                    tokio::spawn(run_workflow(
+                        shared_state.clone(),
+                        run,
+                        outbox.clone(),
+                        control_rx,
+                    ));
+                    None
+                }
+                ClientMsg::Cancel(cancel) => {
+                    if let Some(process) = processes.get(&cancel.id) {
+                        process.try_send(ProcessControl::Cancel).ok();
+                    }
+                    None
+                }
+                ClientMsg::Signal(signal) => {
+                    if let Some(process) = processes.get(&signal.id) {
+                        process.try_send(ProcessControl::Signal(signal.signal)).ok();
+                    }
+                    None
+                }
+                r => Some(WebSocketResponse {
+                    close: true,
+                    close_code: CloseCode::UnsupportedData,
+                    close_message: format!("Received unexpected message: {r:?}"),
+                }),
+            }
         })
-            .await;
+        .await;
+        events.abort();
     }
 
     /// Upgrade HTTP connection to WebSocket
@@ -307,3 +228,958 @@ fn command_execute(shutdown: broadcast::Sender<()>, state: State<SharedState>) -
         get(move |ws: WebSocketUpgrade<_, _>| upgrade(ws, shutdown.clone(), state)),
     )
 }
+
+/// How a process ended.
+pub struct ProcessResult {
+    pub code: i32,
+    pub reason: CompletionReason,
+    /// The outputs the script set with `::set-output` (redacted), or
+    /// wrote to $DRY_OUTPUT (which wins).
+    pub outputs: BTreeMap<String, String>,
+}
+
+/// The file a script may write its outputs to, one `NAME=value` per
+/// line ($DRY_OUTPUT). It is created before the script starts, so that
+/// no one else can create it first, in a directory that only the user
+/// can enter, which is removed when this is dropped.
+struct OutputFile {
+    dir: PathBuf,
+}
+
+impl OutputFile {
+    fn create(process_id: Ulid) -> io::Result<Self> {
+        let base = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        let dir = base.join(format!("dry_console-output-{process_id}"));
+        DirBuilder::new().mode(0o700).create(&dir)?;
+        let file = OutputFile { dir };
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(file.path())?;
+        Ok(file)
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join("outputs")
+    }
+
+    /// The outputs the script wrote.
+    fn read(&self) -> BTreeMap<String, String> {
+        fs::read_to_string(self.path())
+            .map(|source| parse_outputs(&source))
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Run a command from the library, with the extra environment
+/// variables in env, sending its output to the outbox, until it exits
+/// or is cancelled. Returns None if it could not be started.
+pub async fn run_process(
+    shared_state: SharedState,
+    command: Command,
+    env: BTreeMap<String, String>,
+    outbox: mpsc::Sender<ServerMsg>,
+    mut controls: mpsc::Receiver<ProcessControl>,
+    trigger: RunTrigger,
+) -> Option<ProcessResult> {
+    let process_id = command.process_id;
+    let send_error = |message: String| {
+        outbox.send(ServerMsg::ProcessError(ProcessError {
+            id: process_id,
+            message,
+        }))
+    };
+    let command_library = shared_state.read().await.command_library.clone();
+    let library_command = match CommandLibrary::from_id(command.id, command_library).await {
+        Some(c) => c,
+        None => {
+            error!("Failed to get script entry: {}", command.id);
+            send_error(format!("Unknown command: {}", command.id))
+                .await
+                .ok();
+            return None;
+        }
+    };
+    let rendered = library_command.render(&shared_state).await;
+    let escalator;
+    let cancel_timeout;
+    let default_limits;
+    let systemd_scope;
+    let events;
+    let base_env;
+    let profile;
+    let secrets;
+    let run_queue;
+    {
+        let shared_state = shared_state.read().await;
+        escalator = shared_state.escalator;
+        cancel_timeout = Duration::from_secs(shared_state.opt.cancel_timeout_seconds);
+        default_limits = shared_state.opt.script_limits();
+        systemd_scope = shared_state.systemd_scope;
+        events = shared_state.events.clone();
+        base_env = environment::base_env(&shared_state.opt.pass_env);
+        run_queue = shared_state.run_queue.clone();
+        profile = command
+            .profile
+            .as_ref()
+            .and_then(|name| shared_state.config.profile(name).cloned());
+        // (Any that are missing are unmet requirements.)
+        secrets = match &rendered {
+            Ok(script) => shared_state
+                .vault
+                .lookup(&ScriptRequirements::from_source(&script.source).secrets),
+            Err(_) => BTreeMap::new(),
+        };
+    }
+    let env = environment::effective_env(base_env, profile.as_ref(), &env, &secrets);
+    let redactor = Redactor::new(environment::secret_values(profile.as_ref(), &secrets));
+    let record = RunRecord {
+        id: process_id,
+        command: library_command.to_string(),
+        command_id: command.id,
+        trigger,
+        profile: command.profile.clone(),
+        env: environment::masked_env(&env, profile.as_ref(), &secrets),
+        started_ms: now_ms(),
+        finished_ms: None,
+        code: None,
+        reason: None,
+        unmet_requirements: Vec::new(),
+        outputs: BTreeMap::new(),
+        output_tail: Vec::new(),
+    };
+    let reject = |unmet_requirements: Vec<UnmetRequirement>| {
+        info!("Rejected {library_command}, unmet requirements: {unmet_requirements:?}");
+        let record = RunRecord {
+            finished_ms: Some(record.started_ms),
+            unmet_requirements: unmet_requirements.clone(),
+            ..record.clone()
+        };
+        let shared_state = shared_state.clone();
+        let outbox = outbox.clone();
+        async move {
+            shared_state.write().await.history.start(record);
+            outbox
+                .send(ServerMsg::ProcessRejected(ProcessRejected {
+                    id: process_id,
+                    command_id: command.id,
+                    unmet_requirements,
+                }))
+                .await
+        }
+    };
+    if let (Some(name), None) = (&command.profile, &profile) {
+        reject(vec![UnmetRequirement::UnknownProfile(name.clone())])
+            .await
+            .ok();
+        return None;
+    }
+    let script = match rendered {
+        Ok(script) => script,
+        Err(e) => {
+            reject(vec![e.into()]).await.ok();
+            return None;
+        }
+    };
+    let limits = ScriptLimits::from_source(&script.source).or(default_limits);
+    let mut unmet_requirements = unmet_requirements(
+        &ScriptRequirements::from_source(&script.source),
+        &shared_state,
+    )
+    .await;
+    let errors = script_diagnostics(&script, &shared_state)
+        .await
+        .iter()
+        .filter(|d| d.severity == DiagnosticSeverity::Error)
+        .count();
+    if errors > 0 {
+        unmet_requirements.push(UnmetRequirement::ScriptErrors(errors));
+    }
+    // Scripts that are not builtin are never run (as root or otherwise)
+    // until the user has approved this version of them:
+    if !shared_state
+        .read()
+        .await
+        .trust
+        .is_trusted(&library_command, &script)
+    {
+        unmet_requirements.push(UnmetRequirement::Unapproved);
+    }
+    if !unmet_requirements.is_empty() {
+        reject(unmet_requirements).await.ok();
+        return None;
+    }
+
+    // Wait for the locks of the script (and for room under the
+    // concurrency limit), unless the run is cancelled first:
+    let mut ticket = run_queue.enqueue(
+        process_id,
+        library_command.to_string(),
+        script_locks(&script.source),
+    );
+    loop {
+        let Turn::Waiting {
+            position,
+            waiting_for,
+        } = ticket.turn.borrow_and_update().clone()
+        else {
+            break;
+        };
+        outbox
+            .send(ServerMsg::ProcessQueued(ProcessQueued {
+                id: process_id,
+                position,
+                waiting_for,
+            }))
+            .await
+            .ok();
+        loop {
+            tokio::select! {
+                _ = ticket.turn.changed() => break,
+                control = controls.recv() => match control {
+                    // (There is no process to signal yet.)
+                    Some(ProcessControl::Signal(_)) => {}
+                    _ => {
+                        info!("Cancelled {library_command} while it was queued");
+                        send_error("Cancelled while waiting in the run queue.".to_string())
+                            .await
+                            .ok();
+                        return None;
+                    }
+                },
+            }
+        }
+    }
+    let record = RunRecord {
+        started_ms: now_ms(),
+        ..record
+    };
+
+    let output_file = match OutputFile::create(process_id) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Failed to create the output file: {e}");
+            send_error(format!("Failed to create the output file: {e}"))
+                .await
+                .ok();
+            return None;
+        }
+    };
+    // Scripts run commands as root via $DRY_SUDO, never a literal sudo:
+    let spawned = limits::script_command(&script.source, &limits, systemd_scope)
+        .env_clear()
+        .envs(env)
+        .env("DRY_OUTPUT", output_file.path())
+        .env(
+            "DRY_SUDO",
+            escalator
+                .and_then(|e| e.script_prefix())
+                .unwrap_or_else(|| "false".to_string()),
+        )
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Its own process group, so that signals reach all of its children:
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn();
+    let mut process = match spawned {
+        Ok(p) => p,
+        Err(e) => {
+            error!("Failed to start process: {e}");
+            send_error(format!("Failed to start process: {e}"))
+                .await
+                .ok();
+            return None;
+        }
+    };
+    let _running = ProcessGuard::start();
+    shared_state.write().await.history.start(record);
+    let mut output = OutputSender::new(process_id, outbox.clone(), redactor.clone());
+    outbox
+        .send(ServerMsg::Process(Process {
+            id: process_id,
+            command_id: command.id,
+        }))
+        .await
+        .ok();
+    events.publish(Event::ProcessStarted(ProcessStarted {
+        id: process_id,
+        command_id: command.id,
+    }));
+    output.meta("## Running script ...");
+    if limits::needs_scope(&limits) && !systemd_scope {
+        output.meta("## The CPU and memory limits are not enforced, because systemd-run --user is not available.");
+    }
+
+    let timeout = limits.timeout_seconds.map(Duration::from_secs);
+    let (stopping, status) = supervise(
+        &mut process,
+        &mut output,
+        &mut controls,
+        timeout,
+        cancel_timeout,
+    )
+    .await;
+    let (code, signal) = match status {
+        Ok(status) => match (status.code(), status.signal()) {
+            (Some(code), _) => (code, None),
+            (None, Some(signal)) => (
+                128 + signal,
+                NixSignal::try_from(signal)
+                    .ok()
+                    .map(|s| s.as_str().to_string()),
+            ),
+            (None, None) => (128, None),
+        },
+        Err(e) => {
+            error!("Failed to wait on child process: {e}");
+            (128, None)
+        }
+    };
+    PROCESS_EXITS
+        .with_label_values(&[library_command.as_ref(), &code.to_string()])
+        .inc();
+    let reason = stopping.unwrap_or(match signal {
+        Some(_) => CompletionReason::Killed,
+        None => CompletionReason::Exited,
+    });
+    let stopped_by = signal
+        .as_ref()
+        .map(|signal| format!(" ({signal})"))
+        .unwrap_or_default();
+    let line = match (reason, &signal, code) {
+        (CompletionReason::Cancelled, _, _) => format!("## Cancelled{stopped_by}."),
+        (CompletionReason::TimedOut, _, _) => format!("## Timed out{stopped_by}."),
+        (_, Some(signal), _) => format!("## Killed by {signal}."),
+        (_, None, 0) => "## Complete.".to_string(),
+        (_, None, code) => format!("## Failed with code {}.", code),
+    };
+    output.meta(&line);
+    output.finish().await;
+    let mut outputs = std::mem::take(&mut output.outputs);
+    outputs.extend(output_file.read());
+    drop(output_file);
+    let redacted_outputs: BTreeMap<_, _> = outputs
+        .iter()
+        .map(|(name, value)| (name.clone(), redactor.redact(value)))
+        .collect();
+    shared_state
+        .write()
+        .await
+        .history
+        .finish(process_id, |record| {
+            record.code = Some(code);
+            record.reason = Some(reason);
+            record.outputs = redacted_outputs.clone();
+            record.output_tail = output.tail();
+        });
+    events.publish(Event::ProcessFinished(ProcessFinished {
+        id: process_id,
+        command_id: command.id,
+        code,
+        reason,
+    }));
+    outbox
+        .send(ServerMsg::ProcessComplete(ProcessComplete {
+            id: process_id,
+            code,
+            signal,
+            reason,
+            outputs: redacted_outputs,
+        }))
+        .await
+        .ok();
+    Some(ProcessResult {
+        code,
+        reason,
+        outputs,
+    })
+}
+
+/// Pass on the output of a running process, and signal its process
+/// group as the controls ask, until it exits. It is stopped once it
+/// runs for longer than the timeout. Returns why it was stopped (if it
+/// was), and how it exited.
+async fn supervise(
+    process: &mut Child,
+    output: &mut OutputSender,
+    controls: &mut mpsc::Receiver<ProcessControl>,
+    timeout: Option<Duration>,
+    cancel_timeout: Duration,
+) -> (Option<CompletionReason>, io::Result<ExitStatus>) {
+    let stdout = read_chunks(process.stdout.take().unwrap()).map(|c| (StreamType::Stdout, c));
+    let stderr = read_chunks(process.stderr.take().unwrap()).map(|c| (StreamType::Stderr, c));
+    let mut chunks = Box::pin(stdout).merge(Box::pin(stderr));
+    let mut flush = tokio::time::interval(BATCH_INTERVAL);
+    let group = process.id().map(|pid| Pid::from_raw(pid as i32));
+    let signal_group = |signal: Signal| {
+        if let Some(group) = group {
+            if let Err(e) = killpg(group, nix_signal(signal)) {
+                debug!("Failed to send {signal} to process group {group}: {e}");
+            }
+        }
+    };
+    let mut controls_open = true;
+    // The end of the output is not the end of the process: it may have
+    // closed its stdout and stderr (eg. `exec >/dev/null`), and still
+    // be running.
+    let mut output_open = true;
+    // Why the process is being stopped, once it is:
+    let mut stopping: Option<CompletionReason> = None;
+    // While stopping, the next signal to send, if the process has not
+    // exited by the time the timer fires:
+    let mut next_signal = None;
+    let escalate = tokio::time::sleep(cancel_timeout);
+    tokio::pin!(escalate);
+    let watchdog = tokio::time::sleep(timeout.unwrap_or_default());
+    tokio::pin!(watchdog);
+    let status = loop {
+        let stop = tokio::select! {
+            status = process.wait() => break status,
+            chunk = chunks.next(), if output_open => {
+                match chunk {
+                    Some((stream, Ok(data))) => output.output(stream, data),
+                    Some((stream, Err(e))) => error!("Error reading {stream:?}: {e:?}"),
+                    None => {
+                        output.end_output();
+                        output_open = false;
+                    }
+                }
+                None
+            }
+            _ = flush.tick() => {
+                output.flush();
+                None
+            }
+            control = controls.recv(), if controls_open => match control {
+                Some(ProcessControl::Signal(signal)) => {
+                    output.meta(&format!("## Sending {signal} ..."));
+                    signal_group(signal);
+                    None
+                }
+                // Cancelled by the client, or the socket was closed:
+                control => {
+                    controls_open = control.is_some();
+                    Some(CompletionReason::Cancelled)
+                }
+            },
+            _ = &mut watchdog, if timeout.is_some() && stopping.is_none() => {
+                Some(CompletionReason::TimedOut)
+            }
+            _ = &mut escalate, if next_signal.is_some() => {
+                if let Some(signal) = next_signal.take() {
+                    output.meta(&format!("## Still running, sending {signal} ..."));
+                    signal_group(signal);
+                    if signal == Signal::Terminate {
+                        next_signal = Some(Signal::Kill);
+                        escalate
+                            .as_mut()
+                            .reset(tokio::time::Instant::now() + cancel_timeout);
+                    }
+                }
+                None
+            }
+        };
+        if let (Some(reason), None) = (stop, stopping) {
+            stopping = Some(reason);
+            let why = match reason {
+                CompletionReason::TimedOut => {
+                    format!("Timed out after {}s", timeout.unwrap_or_default().as_secs())
+                }
+                _ => "Cancelling".to_string(),
+            };
+            output.meta(&format!("## {why}, sending {} ...", Signal::Interrupt));
+            signal_group(Signal::Interrupt);
+            next_signal = Some(Signal::Terminate);
+            escalate
+                .as_mut()
+                .reset(tokio::time::Instant::now() + cancel_timeout);
+        }
+    };
+    // Don't leave anything behind that ignored the signals (the group
+    // lives on for as long as any of its processes do):
+    if stopping.is_some() {
+        signal_group(Signal::Kill);
+    }
+    // Its children may still hold the pipes open, so the rest of the
+    // output is only waited for a little while:
+    let drain = tokio::time::sleep(DRAIN_TIMEOUT);
+    tokio::pin!(drain);
+    while output_open {
+        tokio::select! {
+            chunk = chunks.next() => match chunk {
+                Some((stream, Ok(data))) => output.output(stream, data),
+                Some((stream, Err(e))) => error!("Error reading {stream:?}: {e:?}"),
+                None => {
+                    output.end_output();
+                    output_open = false;
+                }
+            },
+            _ = &mut drain => {
+                output.end_output();
+                output.meta("## The script exited, but its output is still held open (by a background process?)");
+                break;
+            }
+        }
+    }
+    (stopping, status)
+}
+
+/// Sent to a running process (or workflow run) by the websocket handler.
+pub enum ProcessControl {
+    Cancel,
+    Signal(Signal),
+}
+
+fn nix_signal(signal: Signal) -> NixSignal {
+    match signal {
+        Signal::Interrupt => NixSignal::SIGINT,
+        Signal::Terminate => NixSignal::SIGTERM,
+        Signal::Kill => NixSignal::SIGKILL,
+    }
+}
+
+/// Read a pipe in chunks, yielding whatever is available as soon as it
+/// is written, rather than waiting for a whole line.
+fn read_chunks<R>(reader: R) -> impl Stream<Item = io::Result<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    stream::unfold(Some(reader), |reader| async move {
+        let mut reader = reader?;
+        let mut buf = vec![0; CHUNK_SIZE];
+        match reader.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(buf), Some(reader)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+}
+
+/// Output (and messages) dropped while the client was behind.
+#[derive(Default)]
+struct Dropped {
+    lines: usize,
+    bytes: usize,
+    messages: usize,
+}
+
+/// Roughly how much a message adds to the queue, beyond its text:
+const MESSAGE_OVERHEAD: usize = 64;
+
+/// How much of MAX_BUFFERED a queued message takes up.
+fn queued_size(message: &ServerMsg) -> usize {
+    let text = |text: &Option<String>| text.as_ref().map_or(0, String::len);
+    MESSAGE_OVERHEAD
+        + match message {
+            // (Batches of output grow in the queue, so only their data
+            // is counted.)
+            ServerMsg::ProcessOutput(batch) => return batch.data.len(),
+            ServerMsg::ProcessProgress(progress) => text(&progress.message),
+            ServerMsg::ProcessGroup(group) => text(&group.title),
+            ServerMsg::ProcessAnnotation(annotation) => {
+                text(&annotation.title) + annotation.message.len()
+            }
+            ServerMsg::ProcessSetOutput(output) => output.name.len() + output.value.len(),
+            _ => 0,
+        }
+}
+
+/// Collects the output of one process into batches, keeping track of
+/// the offset of each stream, along with the commands found in its
+/// stdout (see WorkflowCommand), in order. Messages are only sent when
+/// there is room in the outbox, so a slow client never makes the
+/// process wait.
+struct OutputSender {
+    process_id: Ulid,
+    started: Instant,
+    offsets: HashMap<StreamType, u64>,
+    outbox: mpsc::Sender<ServerMsg>,
+    /// Messages waiting to be sent, oldest first:
+    queue: VecDeque<ServerMsg>,
+    buffered: usize,
+    dropped: Dropped,
+    /// The end of the output, of every stream (for the run history):
+    tail: VecDeque<u8>,
+    /// Redacts the secrets of the run from each stream:
+    redactor: Redactor,
+    redactors: HashMap<StreamType, Redactor>,
+    commands: CommandParser,
+    /// Set with `::set-output` (after redaction, like the rest of the
+    /// output):
+    outputs: BTreeMap<String, String>,
+}
+
+impl OutputSender {
+    fn new(process_id: Ulid, outbox: mpsc::Sender<ServerMsg>, redactor: Redactor) -> Self {
+        Self {
+            process_id,
+            started: Instant::now(),
+            offsets: HashMap::new(),
+            outbox,
+            queue: VecDeque::new(),
+            buffered: 0,
+            dropped: Dropped::default(),
+            tail: VecDeque::new(),
+            redactor,
+            redactors: HashMap::new(),
+            commands: CommandParser::default(),
+            outputs: BTreeMap::new(),
+        }
+    }
+
+    /// Some output of the script, with its secrets redacted.
+    fn output(&mut self, stream: StreamType, data: Vec<u8>) {
+        let data = self
+            .redactors
+            .entry(stream)
+            .or_insert_with(|| self.redactor.clone())
+            .push(data);
+        self.parse(stream, data);
+    }
+
+    /// The script's output has ended: push what the redactors (and the
+    /// command parser) held back.
+    fn end_output(&mut self) {
+        for (stream, mut redactor) in std::mem::take(&mut self.redactors) {
+            let data = redactor.finish();
+            self.parse(stream, data);
+        }
+        for parsed in self.commands.finish() {
+            self.parsed(parsed);
+        }
+    }
+
+    /// Find the commands in stdout.
+    fn parse(&mut self, stream: StreamType, data: Vec<u8>) {
+        match stream {
+            StreamType::Stdout => {
+                for parsed in self.commands.push(data) {
+                    self.parsed(parsed);
+                }
+            }
+            _ if !data.is_empty() => self.push(stream, data),
+            _ => {}
+        }
+    }
+
+    fn parsed(&mut self, parsed: Parsed) {
+        let id = self.process_id;
+        let message = match parsed {
+            Parsed::Output(data) => return self.push(StreamType::Stdout, data),
+            Parsed::Command(WorkflowCommand::SetOutput { name, value }) => {
+                self.outputs.insert(name.clone(), value.clone());
+                ServerMsg::ProcessSetOutput(ProcessSetOutput { id, name, value })
+            }
+            Parsed::Command(WorkflowCommand::Progress {
+                current,
+                total,
+                message,
+            }) => ServerMsg::ProcessProgress(ProcessProgress {
+                id,
+                current,
+                total,
+                message,
+            }),
+            Parsed::Command(WorkflowCommand::Group(title)) => {
+                ServerMsg::ProcessGroup(ProcessGroup {
+                    id,
+                    title: Some(title),
+                })
+            }
+            Parsed::Command(WorkflowCommand::EndGroup) => {
+                ServerMsg::ProcessGroup(ProcessGroup { id, title: None })
+            }
+            Parsed::Command(WorkflowCommand::Annotation {
+                level,
+                title,
+                message,
+            }) => {
+                let heading = match &title {
+                    Some(title) => format!("{level}: {title}"),
+                    None => level.to_string(),
+                };
+                self.keep(format!("{heading}: {message}\n").as_bytes());
+                ServerMsg::ProcessAnnotation(ProcessAnnotation {
+                    id,
+                    level,
+                    title,
+                    message,
+                })
+            }
+        };
+        self.push_message(message);
+    }
+
+    /// Keep the end of the output, for the run history.
+    fn keep(&mut self, data: &[u8]) {
+        self.tail.extend(data);
+        let excess = self.tail.len().saturating_sub(HISTORY_TAIL_SIZE);
+        self.tail.drain(..excess);
+    }
+
+    fn push(&mut self, stream: StreamType, data: Vec<u8>) {
+        self.keep(&data);
+        let offset = self.offsets.entry(stream).or_default();
+        let start = *offset;
+        *offset += data.len() as u64;
+        // Meta lines (eg. the status at the end, or the marker of the
+        // dropped output) are never dropped:
+        if stream != StreamType::Meta && self.buffered + data.len() > MAX_BUFFERED {
+            self.dropped.lines += data.iter().filter(|b| **b == b'\n').count();
+            self.dropped.bytes += data.len();
+            return;
+        }
+        self.push_dropped();
+        self.buffered += data.len();
+        match self.queue.back_mut() {
+            Some(ServerMsg::ProcessOutput(batch))
+                if batch.stream == stream
+                    && batch.offset + batch.data.len() as u64 == start
+                    && batch.data.len() + data.len() <= BATCH_SIZE =>
+            {
+                batch.data.extend(data)
+            }
+            _ => self
+                .queue
+                .push_back(ServerMsg::ProcessOutput(ProcessOutput {
+                    id: self.process_id,
+                    stream,
+                    elapsed: self.started.elapsed(),
+                    offset: start,
+                    data,
+                })),
+        }
+    }
+
+    /// Queue a message after the output before it. Progress that has
+    /// not been sent yet is replaced, rather than queued. Like output,
+    /// messages are dropped once the client is too far behind.
+    fn push_message(&mut self, message: ServerMsg) {
+        if let (Some(queued @ ServerMsg::ProcessProgress(_)), ServerMsg::ProcessProgress(_)) =
+            (self.queue.back_mut(), &message)
+        {
+            self.buffered -= queued_size(queued);
+            self.buffered += queued_size(&message);
+            *queued = message;
+            return;
+        }
+        let size = queued_size(&message);
+        if self.buffered + size > MAX_BUFFERED {
+            self.dropped.messages += 1;
+            return;
+        }
+        self.push_dropped();
+        self.buffered += size;
+        self.queue.push_back(message);
+    }
+
+    /// Mark the place of any output that was dropped.
+    fn push_dropped(&mut self) {
+        let dropped = std::mem::take(&mut self.dropped);
+        let what = match dropped.messages {
+            0 if dropped.bytes == 0 => return,
+            0 => format!(
+                "{} lines ({} bytes) of output were",
+                dropped.lines, dropped.bytes
+            ),
+            messages => format!(
+                "{} lines ({} bytes) of output, and {messages} progress, group, annotation or output messages, were",
+                dropped.lines, dropped.bytes
+            ),
+        };
+        self.meta(&format!(
+            "## [{what} dropped, because the client fell behind]"
+        ));
+    }
+
+    fn meta(&mut self, line: &str) {
+        self.push(StreamType::Meta, format!("{line}\n").into_bytes());
+    }
+
+    /// Take the next message off the queue.
+    fn pop(queue: &mut VecDeque<ServerMsg>, buffered: &mut usize) -> Option<ServerMsg> {
+        let message = queue.pop_front()?;
+        *buffered -= queued_size(&message);
+        Some(message)
+    }
+
+    /// Send as many messages as the outbox has room for.
+    fn flush(&mut self) {
+        if self.queue.is_empty() {
+            self.push_dropped();
+        }
+        while !self.queue.is_empty() {
+            let Ok(permit) = self.outbox.try_reserve() else {
+                break;
+            };
+            if let Some(message) = Self::pop(&mut self.queue, &mut self.buffered) {
+                permit.send(message);
+            }
+        }
+    }
+
+    /// The last lines of the output. (The first one may be incomplete.)
+    fn tail(&self) -> Vec<String> {
+        let (front, back) = self.tail.as_slices();
+        String::from_utf8_lossy(&[front, back].concat())
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Send everything that is left, waiting for the client if need be.
+    async fn finish(&mut self) {
+        self.push_dropped();
+        while let Some(message) = Self::pop(&mut self.queue, &mut self.buffered) {
+            self.outbox.send(message).await.ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::process::Command as ProcessCommand;
+
+    /// A script that closes its output, and then keeps on running.
+    fn spawn_silent_script() -> Child {
+        ProcessCommand::new("/bin/bash")
+            .arg("-c")
+            .arg("exec >/dev/null 2>&1; sleep 60")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_a_script_after_its_output_has_ended() {
+        let mut process = spawn_silent_script();
+        let (outbox, _outbox_rx) = mpsc::channel(OUTBOX_SIZE);
+        let mut output = OutputSender::new(Ulid::new(), outbox, Redactor::default());
+        // (The sender is kept, so it is the Cancel that stops it.)
+        let (control_tx, mut controls) = mpsc::channel(CONTROL_SIZE);
+        let cancel = control_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            cancel.send(ProcessControl::Cancel).await.ok();
+        });
+        let supervised = supervise(
+            &mut process,
+            &mut output,
+            &mut controls,
+            None,
+            Duration::from_secs(1),
+        );
+        let (stopping, status) = tokio::time::timeout(Duration::from_secs(10), supervised)
+            .await
+            .expect("The script was not stopped");
+        assert_eq!(stopping, Some(CompletionReason::Cancelled));
+        assert!(!status.unwrap().success());
+        drop(control_tx);
+    }
+
+    #[tokio::test]
+    async fn signals_reach_a_script_after_its_output_has_ended() {
+        let mut process = spawn_silent_script();
+        let (outbox, _outbox_rx) = mpsc::channel(OUTBOX_SIZE);
+        let mut output = OutputSender::new(Ulid::new(), outbox, Redactor::default());
+        let (control_tx, mut controls) = mpsc::channel(CONTROL_SIZE);
+        let signal = control_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            signal
+                .send(ProcessControl::Signal(Signal::Terminate))
+                .await
+                .ok();
+        });
+        let supervised = supervise(
+            &mut process,
+            &mut output,
+            &mut controls,
+            None,
+            Duration::from_secs(1),
+        );
+        let (stopping, status) = tokio::time::timeout(Duration::from_secs(10), supervised)
+            .await
+            .expect("The script was not stopped");
+        assert_eq!(stopping, None);
+        assert_eq!(status.unwrap().signal(), Some(NixSignal::SIGTERM as i32));
+        drop(control_tx);
+    }
+
+    #[tokio::test]
+    async fn timeout_stops_a_script_after_its_output_has_ended() {
+        let mut process = spawn_silent_script();
+        let (outbox, _outbox_rx) = mpsc::channel(OUTBOX_SIZE);
+        let mut output = OutputSender::new(Ulid::new(), outbox, Redactor::default());
+        let (_control_tx, mut controls) = mpsc::channel(CONTROL_SIZE);
+        let supervised = supervise(
+            &mut process,
+            &mut output,
+            &mut controls,
+            Some(Duration::from_millis(300)),
+            Duration::from_secs(1),
+        );
+        let (stopping, status) = tokio::time::timeout(Duration::from_secs(10), supervised)
+            .await
+            .expect("The script was not stopped");
+        assert_eq!(stopping, Some(CompletionReason::TimedOut));
+        assert!(!status.unwrap().success());
+    }
+
+    #[test]
+    fn messages_are_dropped_once_the_client_is_too_far_behind() {
+        // (The client never reads.)
+        let (outbox, _outbox_rx) = mpsc::channel(1);
+        let mut output = OutputSender::new(Ulid::new(), outbox, Redactor::default());
+        let warning = format!("::warning::{}\n", "x".repeat(1000));
+        for _ in 0..MAX_BUFFERED / 1000 {
+            output.output(StreamType::Stdout, warning.clone().into_bytes());
+        }
+        assert!(output.buffered <= MAX_BUFFERED);
+        assert!(output.dropped.messages > 0);
+        // The client is told what it missed:
+        output.push_dropped();
+        let Some(ServerMsg::ProcessOutput(notice)) = output.queue.back() else {
+            panic!("No notice of the dropped messages");
+        };
+        assert_eq!(notice.stream, StreamType::Meta);
+        let notice = String::from_utf8_lossy(&notice.data);
+        assert!(notice.contains("annotation"), "{notice}");
+    }
+
+    #[test]
+    fn progress_that_has_not_been_sent_is_replaced() {
+        let (outbox, _outbox_rx) = mpsc::channel(1);
+        let mut output = OutputSender::new(Ulid::new(), outbox, Redactor::default());
+        output.output(
+            StreamType::Stdout,
+            b"::progress 1/3::One\n::progress 2/3::Two\n".to_vec(),
+        );
+        assert_eq!(output.queue.len(), 1);
+        let buffered = output.buffered;
+        output.output(StreamType::Stdout, b"::progress 3/3::Three\n".to_vec());
+        assert_eq!(output.queue.len(), 1);
+        assert_eq!(output.buffered, buffered + 2);
+        assert!(matches!(
+            output.queue.back(),
+            Some(ServerMsg::ProcessProgress(progress)) if progress.current == 3
+        ));
+    }
+}
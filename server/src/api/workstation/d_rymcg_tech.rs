@@ -0,0 +1,162 @@
+use crate::app_state::SharedState;
+use crate::environment;
+use crate::response::{AppError, AppJson, JsonResult};
+use crate::{routing::route, AppRouter};
+use axum::extract::{Query, State};
+use axum::routing::get;
+use dry_console_dto::workstation::{DRymcgTechCheckout, DRymcgTechInstall};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use utoipa::IntoParams;
+
+/// Where the InstallDRymcgTech script clones d.rymcg.tech, relative to
+/// HOME, when ROOT_DIR is not set.
+const DEFAULT_ROOT_DIR: &str = "git/vendor/enigmacurry/d.rymcg.tech";
+
+/// The CLI, relative to the root of the checkout.
+const CLI: &str = "_scripts/user/d.rymcg.tech";
+
+#[derive(Deserialize, IntoParams)]
+struct InstallQuery {
+    /// The environment profile that the installer would run with.
+    profile: Option<String>,
+}
+
+/// Run git in the directory, returning the first line of its output if
+/// it succeeds.
+async fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .lines()
+        .next()
+        .map(str::to_string)
+        .filter(|line| !line.is_empty())
+}
+
+/// The checkout of d.rymcg.tech at the directory, if it is the top
+/// level of one. (A directory inside another repository, eg. a HOME
+/// that is kept in git, is not a checkout.)
+async fn detect_checkout(dir: &Path) -> Option<DRymcgTechCheckout> {
+    let path = git(dir, &["rev-parse", "--show-toplevel"]).await?;
+    let top = Path::new(&path);
+    let dir = tokio::fs::canonicalize(dir).await.ok()?;
+    if tokio::fs::canonicalize(top).await.ok()? != dir || !top.join(CLI).is_file() {
+        return None;
+    }
+    Some(DRymcgTechCheckout {
+        remote: git(top, &["remote", "get-url", "origin"]).await,
+        branch: git(top, &["symbolic-ref", "--short", "-q", "HEAD"]).await,
+        commit: git(top, &["rev-parse", "HEAD"]).await?,
+        cli: None,
+        path,
+    })
+}
+
+/// The d.rymcg.tech checkout at ROOT_DIR (as the installer would see it,
+/// with the profile), or else the one that the CLI on the PATH belongs
+/// to.
+async fn detect_install(env: &BTreeMap<String, String>) -> DRymcgTechInstall {
+    let root_dir = match env.get("ROOT_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            Path::new(env.get("HOME").map(String::as_str).unwrap_or("/")).join(DEFAULT_ROOT_DIR)
+        }
+    };
+    let cli = env.get("PATH").and_then(|path| {
+        which::which_in("d.rymcg.tech", Some(path), "/")
+            .ok()
+            .and_then(|cli| cli.canonicalize().ok())
+    });
+    let mut checkout = match detect_checkout(&root_dir).await {
+        Some(checkout) => Some(checkout),
+        // (The root of the checkout that the CLI is in.)
+        None => match cli
+            .as_ref()
+            .and_then(|cli| cli.ancestors().nth(Path::new(CLI).components().count()))
+        {
+            Some(dir) => detect_checkout(dir).await,
+            None => None,
+        },
+    };
+    if let Some(checkout) = checkout.as_mut() {
+        checkout.cli = cli
+            .filter(|cli| cli.starts_with(&checkout.path))
+            .map(|cli| cli.to_string_lossy().to_string());
+    }
+    DRymcgTechInstall {
+        root_dir: root_dir.to_string_lossy().to_string(),
+        checkout,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/workstation/d_rymcg_tech/",
+    responses(
+        (status = OK, body = DRymcgTechInstall, description = "Where d.rymcg.tech is installed, if it is"),
+        (status = NOT_FOUND, description = "Profile not found")
+    ),
+    params(InstallQuery)
+)]
+pub fn d_rymcg_tech() -> AppRouter {
+    async fn handler(
+        Query(query): Query<InstallQuery>,
+        State(state): State<SharedState>,
+    ) -> JsonResult<DRymcgTechInstall> {
+        let env = {
+            let state = state.read().await;
+            let profile = match &query.profile {
+                Some(name) => Some(state.config.profile(name).ok_or(AppError::NotFound)?),
+                None => None,
+            };
+            environment::effective_env(
+                environment::base_env(&state.opt.pass_env),
+                profile,
+                &BTreeMap::new(),
+                &BTreeMap::new(),
+            )
+        };
+        Ok(AppJson(detect_install(&env).await))
+    }
+    route("/d_rymcg_tech", get(handler))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ulid::Ulid;
+
+    #[tokio::test]
+    async fn only_the_top_level_of_a_checkout_is_detected() {
+        let top = std::env::temp_dir().join(format!("dry_console-test-{}", Ulid::new()));
+        let cli = top.join(CLI);
+        std::fs::create_dir_all(cli.parent().unwrap()).unwrap();
+        std::fs::write(&cli, "").unwrap();
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg("git init -q && git add -A && git -c user.name=test -c user.email=test@localhost commit -qm test")
+            .current_dir(&top)
+            .status()
+            .await
+            .unwrap();
+        assert!(status.success());
+        let checkout = detect_checkout(&top).await;
+        // Eg. a ROOT_DIR that is inside another repository:
+        let inside = detect_checkout(&top.join("_scripts")).await;
+        std::fs::remove_dir_all(&top).unwrap();
+        assert!(checkout.is_some_and(|checkout| checkout.cli.is_none()));
+        assert!(inside.is_none());
+    }
+}
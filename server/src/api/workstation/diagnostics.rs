@@ -0,0 +1,243 @@
+use crate::api::workstation::command::CommandLibrary;
+use crate::app_state::SharedState;
+use crate::response::{AppError, AppJson, JsonResult};
+use crate::shell_library::RenderedScript;
+use crate::{routing::route, AppRouter};
+use axum::extract::State;
+use axum::routing::post;
+use axum::{extract::Path, routing::get, Json, Router};
+use dry_console_common::token::generate_deterministic_ulid_from_seed;
+use dry_console_dto::script::{script_body, DiagnosticSeverity, ScriptDiagnostic};
+use serde::Deserialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+use ulid::Ulid;
+use utoipa::ToSchema;
+use which::which;
+
+pub fn router() -> AppRouter {
+    Router::new().merge(command_diagnostics()).merge(check())
+}
+
+/// Check a script (without its description) with `bash -n`, and with
+/// shellcheck if it is installed. The diagnostics are sorted by line.
+pub fn check_script(script: &str) -> Vec<ScriptDiagnostic> {
+    let mut diagnostics = bash_syntax(script);
+    if which("shellcheck").is_ok() {
+        diagnostics.extend(shellcheck(script));
+    }
+    diagnostics.sort_by_key(|d| (d.line, d.column, d.severity));
+    diagnostics
+}
+
+/// Run a program with the script on its stdin, returning its output.
+fn run_with_stdin(program: &str, args: &[&str], script: &str) -> Option<std::process::Output> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| warn!("Could not run {program}: {e}"))
+        .ok()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes()).ok();
+    }
+    child
+        .wait_with_output()
+        .map_err(|e| warn!("Could not run {program}: {e}"))
+        .ok()
+}
+
+/// The errors reported by `bash -n`, eg:
+///
+///   /bin/bash: line 4: syntax error near unexpected token `fi'
+///   /bin/bash: line 4: `fi fi'
+///
+/// (The second line just repeats the source, so it is left out.)
+fn bash_syntax(script: &str) -> Vec<ScriptDiagnostic> {
+    let Some(output) = run_with_stdin("/bin/bash", &["-n"], script) else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter_map(|line| {
+            let (_, line) = line.split_once(": line ")?;
+            let (line, message) = line.split_once(": ")?;
+            if message.starts_with('`') && message.ends_with('\'') {
+                return None;
+            }
+            Some(ScriptDiagnostic {
+                line: line.parse().ok()?,
+                column: None,
+                severity: DiagnosticSeverity::Error,
+                message: message.to_string(),
+                source: "bash".to_string(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct ShellcheckReport {
+    comments: Vec<ShellcheckComment>,
+}
+
+#[derive(Deserialize)]
+struct ShellcheckComment {
+    line: usize,
+    column: usize,
+    level: String,
+    code: u32,
+    message: String,
+}
+
+fn shellcheck(script: &str) -> Vec<ScriptDiagnostic> {
+    let Some(output) = run_with_stdin(
+        "shellcheck",
+        &["--shell=bash", "--format=json1", "-"],
+        script,
+    ) else {
+        return Vec::new();
+    };
+    match serde_json::from_slice::<ShellcheckReport>(&output.stdout) {
+        Ok(report) => report
+            .comments
+            .into_iter()
+            .map(|c| ScriptDiagnostic {
+                line: c.line,
+                column: Some(c.column),
+                severity: match c.level.as_str() {
+                    "error" => DiagnosticSeverity::Error,
+                    "warning" => DiagnosticSeverity::Warning,
+                    "info" => DiagnosticSeverity::Info,
+                    _ => DiagnosticSeverity::Style,
+                },
+                message: c.message,
+                source: format!("SC{}", c.code),
+            })
+            .collect(),
+        Err(e) => {
+            warn!("Could not parse the output of shellcheck: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// check_script, on a blocking thread, so that it does not hold up the
+/// runtime while bash and shellcheck run.
+async fn check_script_blocking(script: String) -> Vec<ScriptDiagnostic> {
+    tokio::task::spawn_blocking(move || check_script(&script))
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Could not check the script: {e}");
+            Vec::new()
+        })
+}
+
+/// The diagnostics of the last rendering of a script (see
+/// script_diagnostics).
+#[derive(Debug, Clone)]
+pub struct CheckedScript {
+    /// The hash of the rendered source that was checked:
+    rendered: Ulid,
+    /// Filled in once it has been checked:
+    diagnostics: Arc<OnceCell<Vec<ScriptDiagnostic>>>,
+}
+
+impl CheckedScript {
+    /// A script that has already been checked.
+    pub fn new(script: &RenderedScript, diagnostics: Vec<ScriptDiagnostic>) -> Self {
+        Self {
+            rendered: generate_deterministic_ulid_from_seed(&script.source),
+            diagnostics: Arc::new(OnceCell::new_with(Some(diagnostics))),
+        }
+    }
+}
+
+/// The diagnostics of a rendered script, checking it now if it has not
+/// been checked before. (A script that is requested again while it is
+/// being checked waits for the same check.) Only the last rendering of
+/// each version of a script is kept, as a template is rendered again
+/// whenever the facts change.
+pub async fn script_diagnostics(
+    script: &RenderedScript,
+    state: &SharedState,
+) -> Vec<ScriptDiagnostic> {
+    let rendered = generate_deterministic_ulid_from_seed(&script.source);
+    let id = script.id().to_string();
+    let cell = {
+        let mut state = state.write().await;
+        match state.script_diagnostics.get(&id) {
+            Some(checked) if checked.rendered == rendered => checked.diagnostics.clone(),
+            _ => {
+                let diagnostics = Arc::new(OnceCell::new());
+                let checked = CheckedScript {
+                    rendered,
+                    diagnostics: diagnostics.clone(),
+                };
+                state.script_diagnostics.insert(id, checked);
+                diagnostics
+            }
+        }
+    };
+    cell.get_or_init(|| check_script_blocking(script_body(&script.source)))
+        .await
+        .clone()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/workstation/command/{command}/diagnostics/",
+    responses(
+        (status = OK, body = Vec<ScriptDiagnostic>, description = "Check a command from the library again"),
+        (status = NOT_FOUND, description = "Command not found in the library"),
+        (status = CONFLICT, description = "The libraries the command includes could not be expanded, or its template could not be rendered")
+    ),
+    params(
+        ("command" = String, Path, description = "The name (id) of the command to check")
+    )
+)]
+pub fn command_diagnostics() -> AppRouter {
+    async fn handler(
+        Path(command): Path<String>,
+        State(state): State<SharedState>,
+    ) -> JsonResult<Vec<ScriptDiagnostic>> {
+        let command = CommandLibrary::from_str(&command).map_err(|_| AppError::NotFound)?;
+        let script = command.render(&state).await?;
+        state
+            .write()
+            .await
+            .script_diagnostics
+            .remove(&script.id().to_string());
+        let diagnostics = script_diagnostics(&script, &state).await;
+        info!("Checked {command}: {} diagnostics", diagnostics.len());
+        Ok(AppJson(diagnostics))
+    }
+    route("/command/:command/diagnostics", get(handler))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CheckScript {
+    /// The script source, without a description.
+    pub script: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/workstation/check_script/",
+    request_body = CheckScript,
+    responses(
+        (status = OK, body = Vec<ScriptDiagnostic>, description = "Check a script without running it")
+    )
+)]
+pub fn check() -> AppRouter {
+    async fn handler(Json(body): Json<CheckScript>) -> JsonResult<Vec<ScriptDiagnostic>> {
+        Ok(AppJson(check_script_blocking(body.script).await))
+    }
+    route("/check_script", post(handler))
+}
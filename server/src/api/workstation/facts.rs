@@ -0,0 +1,117 @@
+use crate::app_state::SharedState;
+use crate::response::{AppJson, JsonResult};
+use crate::{routing::route, AppRouter};
+use axum::extract::State;
+use axum::routing::get;
+use dry_console_dto::workstation::{
+    DependencyFact, Distribution, WorkstationFacts, WorkstationPackageManager,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use strum::IntoEnumIterator;
+use uzers::os::unix::UserExt;
+use uzers::{get_current_uid, get_user_by_uid};
+use which::which;
+
+use super::platform::detect_toolbox;
+use super::WorkstationDependency;
+
+/// The package manager of the distribution, or else the first one that
+/// is installed.
+fn detect_package_manager(distribution: &Distribution) -> Option<WorkstationPackageManager> {
+    match distribution {
+        Distribution::Fedora => Some(WorkstationPackageManager::Dnf),
+        Distribution::Arch => Some(WorkstationPackageManager::Pacman),
+        Distribution::Debian | Distribution::Ubuntu => Some(WorkstationPackageManager::Apt),
+        Distribution::Unsupported => [
+            (WorkstationPackageManager::Dnf, "dnf"),
+            (WorkstationPackageManager::Apt, "apt-get"),
+            (WorkstationPackageManager::Pacman, "pacman"),
+            (WorkstationPackageManager::Apk, "apk"),
+        ]
+        .into_iter()
+        .find(|(_, program)| which(program).is_ok())
+        .map(|(package_manager, _)| package_manager),
+    }
+}
+
+/// The facts that script templates are rendered with, as they are now.
+/// (The state is only locked to read from it, not while looking for the
+/// dependencies.)
+pub async fn workstation_facts(state: &SharedState) -> WorkstationFacts {
+    let (platform, sudo, escalator) = {
+        let state = state.read().await;
+        (state.platform.clone(), state.sudo_enabled, state.escalator)
+    };
+    let platform = &platform;
+    let package_manager = detect_package_manager(&platform.distribution);
+    let user = get_user_by_uid(get_current_uid());
+    let mut dependencies = BTreeMap::new();
+    let mut missing = Vec::new();
+    let mut missing_packages = BTreeSet::new();
+    for dependency in WorkstationDependency::iter() {
+        let name = dependency.get_name().to_string();
+        let path = which(&name)
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if path.is_empty() {
+            missing.push(name.clone());
+            // (There are none for unsupported platforms.)
+            let packages = dependency
+                .get_packages(platform.clone())
+                .unwrap_or_default();
+            missing_packages.extend(
+                packages
+                    .into_iter()
+                    .filter(|p| Some(&p.package_manager) == package_manager.as_ref())
+                    .map(|p| p.package_name),
+            );
+        }
+        dependencies.insert(
+            name,
+            DependencyFact {
+                installed: !path.is_empty(),
+                path,
+            },
+        );
+    }
+    WorkstationFacts {
+        os_type: platform.os_type.to_string().to_lowercase(),
+        distribution: platform.distribution.to_string().to_lowercase(),
+        release: platform.release.version.clone(),
+        variant: platform.release.variant_id.clone(),
+        kernel: platform.version.clone(),
+        package_manager: package_manager.map(|p| p.to_string()),
+        hostname: hostname::get()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        user: user
+            .as_ref()
+            .map(|user| user.name().to_string_lossy().to_string())
+            .unwrap_or_default(),
+        uid: get_current_uid(),
+        home: user
+            .as_ref()
+            .map(|user| user.home_dir().to_string_lossy().to_string())
+            .unwrap_or_default(),
+        sudo,
+        escalator: escalator.map(|e| e.to_string()),
+        toolbox: detect_toolbox(),
+        dependencies,
+        missing,
+        missing_packages: missing_packages.into_iter().collect(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/workstation/facts/",
+    responses(
+        (status = OK, body = WorkstationFacts, description = "The facts about the workstation that script templates are rendered with")
+    )
+)]
+pub fn facts() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<WorkstationFacts> {
+        Ok(AppJson(workstation_facts(&state).await))
+    }
+    route("/facts", get(handler))
+}
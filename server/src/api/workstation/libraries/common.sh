@@ -0,0 +1,55 @@
+# # Common helper functions
+#
+# Include these in a script with `# include: common`. (These are the
+# non-interactive functions from funcs.sh.)
+
+stderr(){ echo "$@" >/dev/stderr; }
+error(){ stderr "Error: $@"; }
+fault(){ test -n "$1" && error $1; stderr "Exiting."; exit 1; }
+exe() { (set -x; "$@"); }
+print_array(){ printf '%s\n' "$@"; }
+trim_trailing_whitespace() { sed -e 's/[[:space:]]*$//'; }
+trim_leading_whitespace() { sed -e 's/^[[:space:]]*//'; }
+trim_whitespace() { trim_leading_whitespace | trim_trailing_whitespace; }
+check_var(){
+    local __missing=false
+    local __vars="$@"
+    for __var in ${__vars}; do
+        if [[ -z "${!__var}" ]]; then
+            error "${__var} variable is missing."
+            __missing=true
+        fi
+    done
+    if [[ ${__missing} == true ]]; then
+        fault
+    fi
+}
+
+check_num(){
+    local var=$1
+    check_var var
+    if ! [[ ${!var} =~ ^[0-9]+$ ]] ; then
+        fault "${var} is not a number: '${!var}'"
+    fi
+}
+
+debug_var() {
+    local var=$1
+    check_var var
+    stderr "## DEBUG: ${var}=${!var}"
+}
+
+check_deps() {
+    missing=""
+    for var in "$@"; do
+        echo -n "Looking for ${var} ... " >/dev/stderr
+        if ! command -v "${var}" >/dev/null 2>&1; then
+            echo "Missing! No ${var} found in PATH." >/dev/stderr
+            missing="${missing} ${var}"
+        else
+            echo found $(which "${var}")
+        fi
+    done
+
+    if [[ -n "${missing}" ]]; then fault "Missing dependencies: ${missing}"; fi
+}
@@ -1,4 +1,80 @@
 # # Install d.rymcg.tech
+#
+# Clones [d.rymcg.tech](https://github.com/EnigmaCurry/d.rymcg.tech),
+# checks out a pinned ref, and puts the `d.rymcg.tech` CLI on the PATH.
+# It is configured with these environment variables (eg. from a profile):
+#
+#  * `ROOT_DIR` - where to clone it (default `~/git/vendor/enigmacurry/d.rymcg.tech`).
+#  * `GIT_URL` - the repository to clone, or the path of a local mirror.
+#  * `GIT_REF` - the branch, tag or commit to check out (default `master`).
+#  * `BIN_DIR` - where to link the CLI (default `~/.local/bin`).
+#
+# An existing checkout is fetched and moved to `GIT_REF`, unless it has
+# local changes (then it is kept as it is, with a warning), and the CLI
+# is set up again.
+#
+# include: common
+# needs: git
+# locks: d-rymcg-tech
 
-echo "TODO"
-exit 1
+set -e
+ROOT_DIR="${ROOT_DIR:-${HOME}/git/vendor/enigmacurry/d.rymcg.tech}"
+GIT_URL="${GIT_URL:-https://github.com/EnigmaCurry/d.rymcg.tech.git}"
+GIT_REF="${GIT_REF:-master}"
+BIN_DIR="${BIN_DIR:-${HOME}/.local/bin}"
+
+# Is ROOT_DIR the root of a checkout of d.rymcg.tech? (Not just inside
+# another repository, eg. a HOME that is kept in git.)
+is_checkout() {
+    local top
+    top="$(git -C "${ROOT_DIR}" rev-parse --show-toplevel 2>/dev/null)" &&
+        [[ "${top}" -ef "${ROOT_DIR}" ]] &&
+        [[ -f "${ROOT_DIR}/_scripts/user/d.rymcg.tech" ]]
+}
+
+checkout_ref() {
+    # A ref that is not a branch (a tag or a commit) leaves HEAD detached:
+    git -C "${ROOT_DIR}" -c advice.detachedHead=false checkout "${GIT_REF}"
+    # A branch that was checked out before may be behind its remote:
+    if git -C "${ROOT_DIR}" rev-parse -q --verify "@{upstream}" >/dev/null 2>&1; then
+        git -C "${ROOT_DIR}" merge --ff-only "@{upstream}"
+    fi
+}
+
+echo "::progress 0/3::Cloning"
+if is_checkout; then
+    echo "d.rymcg.tech is already installed at ${ROOT_DIR}"
+    echo "::progress 1/3::Checking out ${GIT_REF}"
+    echo "::group::Fetching ${GIT_REF} into ${ROOT_DIR}"
+    git -C "${ROOT_DIR}" fetch --tags origin
+    if [[ -n "$(git -C "${ROOT_DIR}" status --porcelain --untracked-files=no)" ]]; then
+        echo "::warning title=GIT_REF::${ROOT_DIR} has local changes, so it was kept at $(git -C "${ROOT_DIR}" rev-parse --short HEAD), instead of checking out ${GIT_REF}."
+    else
+        checkout_ref
+    fi
+    echo "::endgroup::"
+elif [[ -e "${ROOT_DIR}" ]] && [[ -n "$(ls -A "${ROOT_DIR}")" ]]; then
+    fault "${ROOT_DIR} already exists, and is not a checkout of d.rymcg.tech."
+else
+    echo "::group::Cloning ${GIT_URL} into ${ROOT_DIR}"
+    mkdir -p "$(dirname "${ROOT_DIR}")"
+    git clone "${GIT_URL}" "${ROOT_DIR}"
+    echo "::progress 1/3::Checking out ${GIT_REF}"
+    checkout_ref
+    echo "::endgroup::"
+fi
+
+echo "::progress 2/3::Setting up the CLI"
+CLI="${ROOT_DIR}/_scripts/user/d.rymcg.tech"
+test -x "${CLI}" || fault "The CLI was not found: ${CLI}"
+mkdir -p "${BIN_DIR}"
+ln -sfn "${CLI}" "${BIN_DIR}/d.rymcg.tech"
+echo "Linked ${BIN_DIR}/d.rymcg.tech -> ${CLI}"
+case ":${PATH}:" in
+    *":${BIN_DIR}:"*) ;;
+    *) echo "::warning title=PATH::${BIN_DIR} is not on the PATH, add it in your shell's profile (eg. ~/.bashrc)." ;;
+esac
+
+echo "::set-output name=ROOT_DIR::${ROOT_DIR}"
+echo "::set-output name=COMMIT::$(git -C "${ROOT_DIR}" rev-parse HEAD)"
+echo "::progress 3/3::Done"
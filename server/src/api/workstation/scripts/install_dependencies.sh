@@ -1,6 +1,30 @@
 # # Install missing dependencies
 #
-# This script is just a placeholder, and will be dynamically replaced
-# with one appropriate for the particular operating system.
+#% if package_manager
+# This script is customized for {{ distribution }} ({{ package_manager }} package manager).
+#% endif
+#
+# template: yes
+# locks: package-manager
+#% if missing_packages
+# requires: sudo
+#% endif
 
-sudo whoami
+#% if not missing
+echo "All of the dependencies are installed."
+#% elif not missing_packages
+echo "The dependencies can not be installed automatically on this platform ({{ distribution }}), please install them yourself: {{ missing | join(", ") }}" >/dev/stderr
+exit 1
+#% else
+: "${DRY_SUDO:={{ escalator or "sudo" }}}" # (For when the script is pasted into a terminal.)
+#% if package_manager == "dnf"
+$DRY_SUDO dnf install -y {{ missing_packages | quote }}
+#% elif package_manager == "apt"
+$DRY_SUDO apt-get update
+$DRY_SUDO env DEBIAN_FRONTEND=noninteractive apt-get install -y {{ missing_packages | quote }}
+#% elif package_manager == "pacman"
+$DRY_SUDO pacman -S --noconfirm {{ missing_packages | quote }}
+#% elif package_manager == "apk"
+$DRY_SUDO apk add {{ missing_packages | quote }}
+#% endif
+#% endif
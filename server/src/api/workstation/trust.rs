@@ -0,0 +1,74 @@
+use crate::api::workstation::command::CommandLibrary;
+use crate::app_state::SharedState;
+use crate::response::{AppError, AppJson, JsonResult};
+use crate::{routing::route, AppRouter};
+use axum::extract::State;
+use axum::routing::post;
+use axum::{extract::Path, routing::get, Json, Router};
+use dry_console_dto::trust::{ScriptApproval, ScriptReview};
+use std::str::FromStr;
+
+pub fn router() -> AppRouter {
+    Router::new().merge(review()).merge(approve())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/workstation/command/{command}/review/",
+    responses(
+        (status = OK, body = ScriptReview, description = "Compare a command from the library with the version of it that was last approved"),
+        (status = NOT_FOUND, description = "Command not found in the library"),
+        (status = CONFLICT, description = "The libraries the command includes could not be expanded, or its template could not be rendered")
+    ),
+    params(
+        ("command" = String, Path, description = "The name (id) of the command to review")
+    )
+)]
+pub fn review() -> AppRouter {
+    async fn handler(
+        Path(command): Path<String>,
+        State(state): State<SharedState>,
+    ) -> JsonResult<ScriptReview> {
+        let command = CommandLibrary::from_str(&command).map_err(|_| AppError::NotFound)?;
+        let script = command.render(&state).await?;
+        Ok(AppJson(state.read().await.trust.review(&command, &script)))
+    }
+    route("/command/:command/review", get(handler))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/workstation/command/{command}/approve/",
+    request_body = ScriptApproval,
+    responses(
+        (status = OK, body = ScriptReview, description = "Approve the reviewed version of a command from the library"),
+        (status = NOT_FOUND, description = "Command not found in the library"),
+        (status = CONFLICT, description = "The command has changed since it was reviewed, or it could not be rendered")
+    ),
+    params(
+        ("command" = String, Path, description = "The name (id) of the command to approve")
+    )
+)]
+pub fn approve() -> AppRouter {
+    async fn handler(
+        Path(command): Path<String>,
+        State(state): State<SharedState>,
+        Json(approval): Json<ScriptApproval>,
+    ) -> JsonResult<ScriptReview> {
+        let command = CommandLibrary::from_str(&command).map_err(|_| AppError::NotFound)?;
+        let script = command.render(&state).await?;
+        let mut state = state.write().await;
+        // Only the version that was shown to the user may be approved:
+        if state.trust.review(&command, &script).id != approval.id {
+            return Err(AppError::Conflict(
+                "The script has changed since it was reviewed.".to_string(),
+            ));
+        }
+        state
+            .trust
+            .approve(&command, &script)
+            .map_err(AppError::Io)?;
+        Ok(AppJson(state.trust.review(&command, &script)))
+    }
+    route("/command/:command/approve", post(handler))
+}
@@ -0,0 +1,297 @@
+use crate::api::workstation::command::CommandLibrary;
+use crate::api::workstation::command_execute::{run_process, ProcessControl, CONTROL_SIZE};
+use crate::app_state::SharedState;
+use crate::response::{AppError, AppJson, JsonResult};
+use crate::{routing::route, AppRouter};
+use axum::extract::State;
+use axum::{extract::Path, routing::get, Router};
+use dry_console_dto::history::RunTrigger;
+use dry_console_dto::websocket::{
+    Command, CompletionReason, ProcessError, RunWorkflow, ServerMsg, WorkflowComplete, WorkflowRun,
+    WorkflowStepStatus,
+};
+use dry_console_dto::workflow::{substitute, StepState, Workflow};
+use futures::FutureExt;
+use std::collections::{BTreeMap, HashMap};
+use std::panic::AssertUnwindSafe;
+use std::str::FromStr;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tracing::{error, info};
+use ulid::Ulid;
+
+include!(concat!(env!("OUT_DIR"), "/generated_workflow_library.rs"));
+
+pub fn router() -> AppRouter {
+    Router::new().merge(workflows()).merge(workflow())
+}
+
+/// Parse and check the builtin workflows. Any that are invalid are
+/// logged, and left out.
+pub fn load_workflows() -> BTreeMap<String, Workflow> {
+    let mut workflows = BTreeMap::new();
+    for (name, source) in WORKFLOW_SOURCES {
+        let workflow = serde_json::from_str::<Workflow>(source)
+            .map_err(|e| e.to_string())
+            .and_then(|mut workflow| {
+                workflow.name = name.to_string();
+                workflow.validate()?;
+                match workflow
+                    .steps
+                    .iter()
+                    .find(|step| CommandLibrary::from_str(&step.script).is_err())
+                {
+                    Some(step) => Err(format!("Unknown script: {}", step.script)),
+                    None => Ok(workflow),
+                }
+            });
+        match workflow {
+            Ok(workflow) => {
+                workflows.insert(name.to_string(), workflow);
+            }
+            Err(e) => error!("Invalid workflow {name}: {e}"),
+        }
+    }
+    workflows
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/workstation/workflows/",
+    responses(
+        (status = OK, body = Vec<Workflow>, description = "List the workflows")
+    ),
+)]
+fn workflows() -> AppRouter {
+    async fn handler(State(state): State<SharedState>) -> JsonResult<Vec<Workflow>> {
+        Ok(AppJson(
+            state.read().await.workflows.values().cloned().collect(),
+        ))
+    }
+    route("/workflows", get(handler))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/workstation/workflow/{name}/",
+    responses(
+        (status = OK, body = Workflow, description = "Get the definition of a workflow"),
+        (status = NOT_FOUND, description = "Workflow not found")
+    ),
+    params(
+        ("name" = String, Path, description = "The name of the workflow")
+    )
+)]
+fn workflow() -> AppRouter {
+    async fn handler(
+        Path(name): Path<String>,
+        State(state): State<SharedState>,
+    ) -> JsonResult<Workflow> {
+        match state.read().await.workflows.get(&name) {
+            Some(workflow) => Ok(AppJson(workflow.clone())),
+            None => Err(AppError::NotFound),
+        }
+    }
+    route("/workflow/:name", get(handler))
+}
+
+async fn send_status(
+    outbox: &mpsc::Sender<ServerMsg>,
+    run_id: Ulid,
+    step: &str,
+    state: StepState,
+    process_id: Option<Ulid>,
+) {
+    let status = ServerMsg::WorkflowStep(WorkflowStepStatus {
+        id: run_id,
+        step: step.to_string(),
+        state,
+        process_id,
+    });
+    outbox.send(status).await.ok();
+}
+
+/// Run a workflow, starting each step as soon as the steps it waits
+/// for have finished, until every step has finished (or was skipped).
+/// Cancelling the run cancels the running steps, and skips the rest.
+pub async fn run_workflow(
+    shared_state: SharedState,
+    run: RunWorkflow,
+    outbox: mpsc::Sender<ServerMsg>,
+    mut controls: mpsc::Receiver<ProcessControl>,
+) {
+    let run_id = run.run_id;
+    let (workflow, command_ids) = {
+        let state = shared_state.read().await;
+        let Some(workflow) = state.workflows.get(&run.name).cloned() else {
+            let error = ServerMsg::ProcessError(ProcessError {
+                id: run_id,
+                message: format!("Unknown workflow: {}", run.name),
+            });
+            outbox.send(error).await.ok();
+            return;
+        };
+        let command_ids: HashMap<String, Ulid> = workflow
+            .steps
+            .iter()
+            .filter_map(|step| {
+                let command = CommandLibrary::from_str(&step.script).ok()?;
+                let id = Ulid::from_string(state.command_id.get(&command)?).ok()?;
+                Some((step.script.clone(), id))
+            })
+            .collect();
+        (workflow, command_ids)
+    };
+    info!("Running workflow {} ({run_id})", workflow.name);
+    outbox
+        .send(ServerMsg::Workflow(WorkflowRun {
+            id: run_id,
+            workflow: workflow.clone(),
+        }))
+        .await
+        .ok();
+
+    let mut states = vec![StepState::Pending; workflow.steps.len()];
+    let mut process_ids: Vec<Option<Ulid>> = vec![None; workflow.steps.len()];
+    // The outputs of each finished step, by step id:
+    let mut outputs: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut running: HashMap<usize, mpsc::Sender<ProcessControl>> = HashMap::new();
+    let mut steps = JoinSet::new();
+    let mut cancelled = false;
+    let mut controls_open = true;
+    loop {
+        // Start (or skip) every step that is no longer waiting. Skipping
+        // a step may end the wait of another, so repeat until neither:
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, step) in workflow.steps.iter().enumerate() {
+                if states[index] != StepState::Pending {
+                    continue;
+                }
+                let dependencies: Vec<StepState> = workflow
+                    .dependencies(index)
+                    .into_iter()
+                    .filter_map(|id| workflow.steps.iter().position(|s| s.id == id))
+                    .map(|i| states[i])
+                    .collect();
+                if !dependencies.iter().all(StepState::is_finished) {
+                    continue;
+                }
+                changed = true;
+                let command_id = command_ids.get(&step.script);
+                let Some(command_id) =
+                    command_id.filter(|_| !cancelled && step.when.is_met(&dependencies))
+                else {
+                    states[index] = StepState::Skipped;
+                    send_status(&outbox, run_id, &step.id, StepState::Skipped, None).await;
+                    continue;
+                };
+                let env = step
+                    .params
+                    .iter()
+                    .map(|(name, value)| (name.clone(), substitute(value, &run.params, &outputs)))
+                    .collect();
+                let process_id = Ulid::new();
+                let (control_tx, control_rx) = mpsc::channel(CONTROL_SIZE);
+                running.insert(index, control_tx);
+                states[index] = StepState::Running;
+                process_ids[index] = Some(process_id);
+                // The client must know the process id before its messages arrive:
+                send_status(
+                    &outbox,
+                    run_id,
+                    &step.id,
+                    StepState::Running,
+                    Some(process_id),
+                )
+                .await;
+                let command = Command {
+                    id: *command_id,
+                    process_id,
+                    profile: run.profile.clone(),
+                };
+                let process = run_process(
+                    shared_state.clone(),
+                    command,
+                    env,
+                    outbox.clone(),
+                    control_rx,
+                    RunTrigger::Workflow(run_id),
+                );
+                steps.spawn(async move {
+                    // A step that panics has failed, rather than running forever:
+                    match AssertUnwindSafe(process).catch_unwind().await {
+                        Ok(result) => (index, result),
+                        Err(_) => {
+                            error!("Workflow step {index} panicked");
+                            (index, None)
+                        }
+                    }
+                });
+            }
+        }
+        if steps.is_empty() {
+            break;
+        }
+        tokio::select! {
+            Some(finished) = steps.join_next() => {
+                let (index, result) = match finished {
+                    Ok(finished) => finished,
+                    // Panics are caught above, so this is only when the
+                    // runtime is shutting down:
+                    Err(e) => {
+                        error!("Workflow step failed to run: {e}");
+                        continue;
+                    }
+                };
+                running.remove(&index);
+                let state = match &result {
+                    Some(r) if r.reason == CompletionReason::Cancelled => StepState::Cancelled,
+                    Some(r) if r.reason == CompletionReason::Exited && r.code == 0 => {
+                        StepState::Succeeded
+                    }
+                    _ => StepState::Failed,
+                };
+                let step = &workflow.steps[index];
+                if let Some(result) = result {
+                    outputs.insert(step.id.clone(), result.outputs);
+                }
+                states[index] = state;
+                send_status(&outbox, run_id, &step.id, state, process_ids[index]).await;
+            }
+            control = controls.recv(), if controls_open => match control {
+                Some(ProcessControl::Signal(signal)) => {
+                    for step in running.values() {
+                        step.try_send(ProcessControl::Signal(signal)).ok();
+                    }
+                }
+                // Cancelled by the client, or the socket was closed:
+                control => {
+                    controls_open = control.is_some();
+                    cancelled = true;
+                    for step in running.values() {
+                        step.try_send(ProcessControl::Cancel).ok();
+                    }
+                }
+            },
+        }
+    }
+
+    // A step that only runs on failure is skipped when nothing failed:
+    let succeeded = !cancelled
+        && states
+            .iter()
+            .all(|s| matches!(s, StepState::Succeeded | StepState::Skipped));
+    info!(
+        "Workflow {} ({run_id}) finished, succeeded: {succeeded}",
+        workflow.name
+    );
+    outbox
+        .send(ServerMsg::WorkflowComplete(WorkflowComplete {
+            id: run_id,
+            succeeded,
+        }))
+        .await
+        .ok();
+}
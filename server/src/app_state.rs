@@ -1,16 +1,34 @@
 use crate::api::auth::TOKEN_CACHE_NAME;
 use crate::api::token::generate_token;
 use crate::api::workstation::command::CommandLibrary;
+use crate::api::workstation::diagnostics::{check_script, CheckedScript};
+use crate::api::workstation::installed_dependencies;
 use crate::api::workstation::platform::detect_platform;
-use crate::api::workstation::WorkstationDependencyState;
+use crate::api::workstation::workflow::load_workflows;
+use crate::config::{default_config_path, Config};
+use crate::events::EventBus;
+use crate::history::{default_history_path, RunHistory};
+use crate::limits;
 use crate::response::AppError;
+use crate::run_queue::RunQueue;
+use crate::secrets::{default_vault_path, Vault};
+use crate::shell_library::{default_library_dir, ShellLibrary};
+use crate::sudo::{detect_escalator, Askpass};
+use crate::trust::{default_trust_store_path, TrustStore};
 use crate::Opt;
 use axum::body::Bytes;
+use dry_console_dto::events::Event;
+use dry_console_dto::script::{is_template, script_body, DiagnosticSeverity};
+use dry_console_dto::sudo::{Escalator, SudoStatus};
+use dry_console_dto::workflow::Workflow;
 use dry_console_dto::workstation::Platform;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::info;
+use tokio::sync::{watch, Notify, RwLock};
+use tracing::{info, warn};
 
 ////////////////////////////////////////////////////////////////////////////////
 // Global app state
@@ -22,11 +40,37 @@ pub struct AppState {
     pub cache: HashMap<String, Bytes>,
     pub login_allowed: bool,
     pub sudo_enabled: bool,
-    pub missing_dependencies: Vec<WorkstationDependencyState>,
+    /// The program used to run commands as root (see --escalator).
+    pub escalator: Option<Escalator>,
+    /// Can CPU and memory limits be enforced with systemd-run?
+    pub systemd_scope: bool,
+    pub sudo_status: Arc<watch::Sender<SudoStatus>>,
+    pub sudo_askpass: Option<Askpass>,
+    /// Which dependencies were installed when last checked (see watch_dependencies):
+    pub installed_dependencies: HashMap<String, bool>,
+    pub events: EventBus,
     pub platform: Platform,
     pub command_id: HashMap<CommandLibrary, String>,
     pub command_library: HashMap<String, CommandLibrary>,
     pub command_script: HashMap<String, String>,
+    /// The diagnostics of each script, by its id (see script_diagnostics):
+    pub script_diagnostics: HashMap<String, CheckedScript>,
+    pub shell_library: ShellLibrary,
+    /// The approved versions of the scripts that are not builtin:
+    pub trust: TrustStore,
+    pub workflows: BTreeMap<String, Workflow>,
+    /// The settings edited in the app (see --config):
+    pub config: Config,
+    pub config_path: Option<PathBuf>,
+    /// Wakes the scheduler when the schedules have changed:
+    pub schedules_changed: Arc<Notify>,
+    /// The schedules that have a run going (see run_scheduled):
+    pub running_schedules: HashSet<String>,
+    pub history: RunHistory,
+    /// The secrets that scripts may use, once it is unlocked:
+    pub vault: Vault,
+    /// The runs of library scripts, and those waiting for their locks:
+    pub run_queue: RunQueue,
 }
 impl AppState {
     pub fn cache_set(&mut self, key: &str, value: &Bytes) {
@@ -48,38 +92,105 @@ impl AppState {
     }
     pub fn disable_login(&mut self) {
         self.login_allowed = false;
+        self.events.publish(Event::LoginAllowed(false));
     }
     pub fn enable_login(&mut self) {
         self.login_allowed = true;
+        self.events.publish(Event::LoginAllowed(true));
+    }
+    pub fn save_config(&self) -> io::Result<()> {
+        self.config.save(self.config_path.as_deref())
     }
 }
 pub type SharedState = Arc<RwLock<AppState>>;
 
 pub fn create_shared_state(opt: &Opt) -> SharedState {
     let token = generate_token();
-    let url = format!("http://{0}:{1}/login#token:{token}", opt.addr, opt.port);
+    let url = format!(
+        "http://{0}:{1}{2}/login#token:{token}",
+        opt.addr,
+        opt.port,
+        opt.base_path()
+    );
     info!("\n\nLogin URL:\n{0}\n", url);
 
     let mut command_id = HashMap::<CommandLibrary, String>::new();
     let mut command_library = HashMap::<String, CommandLibrary>::new();
     let mut command_script = HashMap::<String, String>::new();
+    let mut script_diagnostics = HashMap::<String, CheckedScript>::new();
+    let shell_library = ShellLibrary::new(opt.library_dir.clone().or_else(default_library_dir));
     for (ulid, command_variant) in crate::STATIC_COMMAND_LIBRARY_MAP.iter() {
         command_id.insert(command_variant.clone(), ulid.clone());
         command_library.insert(ulid.clone(), command_variant.clone());
         let script = command_variant.get_script(&command_id, &command_script);
+        match shell_library.render(&script) {
+            // (Templates are checked once they are rendered with the facts.)
+            Ok(_) if is_template(&script) => {}
+            Ok(rendered) => {
+                let diagnostics = check_script(&script_body(&rendered.source));
+                for diagnostic in &diagnostics {
+                    match diagnostic.severity {
+                        DiagnosticSeverity::Error => warn!("{command_variant}: {diagnostic}"),
+                        _ => info!("{command_variant}: {diagnostic}"),
+                    }
+                }
+                script_diagnostics.insert(
+                    rendered.id().to_string(),
+                    CheckedScript::new(&rendered, diagnostics),
+                );
+            }
+            Err(e) => warn!("{command_variant}: {e}"),
+        }
         command_script.insert(ulid.clone(), script);
     }
 
+    let config_path = opt.config.clone().or_else(default_config_path);
+    let config = Config::load(config_path.as_deref());
+
+    let escalator = detect_escalator(opt.escalator.or(config.escalator));
+    match escalator {
+        Some(Escalator::Pkexec) => warn!("Root privileges are acquired via pkexec, which can not run commands without prompting, so scripts can not use it ($DRY_SUDO)."),
+        Some(e) => info!("Root privileges are acquired via {e}."),
+        None => info!("No privilege escalation program (sudo, doas, run0 or pkexec) was found."),
+    }
+
+    let systemd_scope = limits::detect_systemd_scope();
+    match (systemd_scope, limits::needs_scope(&opt.script_limits())) {
+        (true, _) => info!("Script CPU and memory limits are enforced with systemd-run."),
+        (false, true) => warn!("systemd-run --user is not available, so the default CPU and memory limits of scripts (--script-cpu, --script-memory) can not be enforced."),
+        (false, false) => {}
+    }
+
     Arc::new(RwLock::new(AppState {
         opt: opt.clone(),
         cache: HashMap::from([(TOKEN_CACHE_NAME.to_string(), Bytes::from(token))]),
         login_allowed: true,
         sudo_enabled: false,
-        missing_dependencies: Vec::<WorkstationDependencyState>::new(),
+        escalator,
+        systemd_scope,
+        sudo_status: Arc::new(watch::Sender::new(SudoStatus {
+            can_acquire: !opt.no_sudo && escalator.is_some_and(|e| e.supports_askpass()),
+            escalator,
+            ..Default::default()
+        })),
+        sudo_askpass: None,
+        installed_dependencies: installed_dependencies(),
+        events: EventBus::new(),
         command_id,
         command_library,
         command_script,
+        script_diagnostics,
+        shell_library,
+        trust: TrustStore::load(opt.trust_store.clone().or_else(default_trust_store_path)),
+        workflows: load_workflows(),
         platform: detect_platform(),
+        config,
+        config_path,
+        schedules_changed: Arc::new(Notify::new()),
+        running_schedules: HashSet::new(),
+        history: RunHistory::load(opt.history.clone().or_else(default_history_path)),
+        vault: Vault::new(opt.vault.clone().or_else(default_vault_path)),
+        run_queue: RunQueue::new(opt.max_concurrent_runs.map(NonZeroUsize::get)),
     }))
 }
 
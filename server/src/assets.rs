@@ -0,0 +1,242 @@
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+
+// Generated by build.rs, defines get_inline_files():
+include!(concat!(env!("OUT_DIR"), "/generated_includes.rs"));
+
+/// The frontend is built with this as its public URL (see Trunk.toml):
+const BASE_PATH_PLACEHOLDER: &str = "/__DRY_CONSOLE_BASE_PATH__/";
+
+/// File names are not content hashed (trunk --filehash false), so
+/// clients must revalidate every time, which is cheap with ETags:
+const CACHE_CONTROL: &str = "no-cache";
+
+/// A file embedded into the binary, along with its precompressed variants.
+#[derive(Clone, Copy, Debug)]
+pub struct StaticAsset {
+    pub path: &'static str,
+    pub content_type: &'static str,
+    /// Hex encoded SHA-256 (truncated) of the uncompressed content.
+    pub hash: &'static str,
+    pub identity: &'static [u8],
+    pub gzip: Option<&'static [u8]>,
+    pub brotli: Option<&'static [u8]>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+    Identity,
+}
+
+impl Encoding {
+    fn token(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Identity => "identity",
+        }
+    }
+}
+
+impl StaticAsset {
+    fn body(&self, encoding: Encoding) -> Option<&'static [u8]> {
+        match encoding {
+            Encoding::Brotli => self.brotli,
+            Encoding::Gzip => self.gzip,
+            Encoding::Identity => Some(self.identity),
+        }
+    }
+
+    /// Strong ETag, unique for each encoded representation.
+    fn etag(&self, encoding: Encoding) -> String {
+        match encoding {
+            Encoding::Identity => format!("\"{}\"", self.hash),
+            e => format!("\"{}-{}\"", self.hash, e.token()),
+        }
+    }
+
+    /// Pick the best available encoding allowed by the Accept-Encoding header.
+    fn negotiate(&self, accept_encoding: &str) -> Encoding {
+        // Order of preference, when the client weighs them equally:
+        [Encoding::Brotli, Encoding::Gzip, Encoding::Identity]
+            .into_iter()
+            .filter(|e| self.body(*e).is_some())
+            .map(|e| (e, quality(accept_encoding, e)))
+            .filter(|(_, q)| *q > 0.0)
+            .fold(None, |best: Option<(Encoding, f32)>, (e, q)| match best {
+                Some((_, best_q)) if best_q >= q => best,
+                _ => Some((e, q)),
+            })
+            .map(|(e, _)| e)
+            .unwrap_or(Encoding::Identity)
+    }
+
+    /// Does If-None-Match list any representation of this asset?
+    fn is_not_modified(&self, if_none_match: &str) -> bool {
+        if_none_match.split(',').map(str::trim).any(|tag| {
+            let tag = tag.trim_start_matches("W/");
+            tag == "*"
+                || [Encoding::Identity, Encoding::Gzip, Encoding::Brotli]
+                    .iter()
+                    .any(|e| tag == self.etag(*e))
+        })
+    }
+}
+
+/// Rewrite the placeholder public URL in index.html with the
+/// runtime base path, so that the frontend loads its assets (and
+/// finds its `<base>`) under the prefix. The precompressed variants
+/// are of the original content, so only the identity is served.
+pub fn with_base_path(asset: StaticAsset, base_path: &str) -> StaticAsset {
+    let html = String::from_utf8_lossy(asset.identity);
+    if !html.contains(BASE_PATH_PLACEHOLDER) {
+        return asset;
+    }
+    let html = html.replace(BASE_PATH_PLACEHOLDER, &format!("{base_path}/"));
+    let digest = Sha256::digest(html.as_bytes());
+    let hash: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+    // Created once at startup and needed for the life of the server:
+    StaticAsset {
+        hash: Box::leak(hash.into_boxed_str()),
+        identity: Box::leak(html.into_bytes().into_boxed_slice()),
+        gzip: None,
+        brotli: None,
+        ..asset
+    }
+}
+
+/// The q-value the client assigned to the encoding in Accept-Encoding.
+fn quality(accept_encoding: &str, encoding: Encoding) -> f32 {
+    let mut wildcard = None;
+    for entry in accept_encoding.split(',') {
+        let mut parts = entry.split(';').map(str::trim);
+        let coding = parts.next().unwrap_or_default().to_lowercase();
+        let q = parts
+            .find_map(|p| p.strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if coding == encoding.token() || (encoding == Encoding::Gzip && coding == "x-gzip") {
+            return q;
+        } else if coding == "*" {
+            wildcard = Some(q);
+        }
+    }
+    match (wildcard, encoding) {
+        (Some(q), _) => q,
+        // Identity is always acceptable unless explicitly refused, but
+        // (with the lowest q-value) only chosen when nothing else is:
+        (None, Encoding::Identity) => 0.001,
+        (None, _) => 0.0,
+    }
+}
+
+pub async fn serve(asset: StaticAsset, headers: HeaderMap) -> Response {
+    let accept_encoding = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let encoding = asset.negotiate(accept_encoding);
+    let etag = asset.etag(encoding);
+    let mut response = match headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(if_none_match) if asset.is_not_modified(if_none_match) => {
+            StatusCode::NOT_MODIFIED.into_response()
+        }
+        _ => {
+            let mut response = Response::new(Body::from(asset.body(encoding).unwrap()));
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(asset.content_type),
+            );
+            if encoding != Encoding::Identity {
+                response.headers_mut().insert(
+                    header::CONTENT_ENCODING,
+                    HeaderValue::from_static(encoding.token()),
+                );
+            }
+            response
+        }
+    };
+    let headers = response.headers_mut();
+    headers.insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(CACHE_CONTROL),
+    );
+    headers.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSET: StaticAsset = StaticAsset {
+        path: "/frontend.js",
+        content_type: "application/javascript",
+        hash: "abc123",
+        identity: b"identity",
+        gzip: Some(b"gzip"),
+        brotli: Some(b"brotli"),
+    };
+
+    #[test]
+    fn quality_of_listed_and_wildcard_encodings() {
+        assert_eq!(quality("gzip;q=0.5, br", Encoding::Gzip), 0.5);
+        assert_eq!(quality("gzip;q=0.5, br", Encoding::Brotli), 1.0);
+        assert_eq!(quality("x-gzip", Encoding::Gzip), 1.0);
+        assert_eq!(quality("GZIP", Encoding::Gzip), 1.0);
+        assert_eq!(quality("gzip, *;q=0.2", Encoding::Brotli), 0.2);
+        assert_eq!(quality("gzip", Encoding::Brotli), 0.0);
+        assert_eq!(quality("gzip", Encoding::Identity), 0.001);
+        assert_eq!(quality("identity;q=0", Encoding::Identity), 0.0);
+        assert_eq!(quality("identity, gzip", Encoding::Identity), 1.0);
+    }
+
+    #[test]
+    fn negotiate_prefers_brotli_then_gzip() {
+        assert_eq!(ASSET.negotiate("gzip, deflate, br"), Encoding::Brotli);
+        assert_eq!(ASSET.negotiate("gzip, deflate"), Encoding::Gzip);
+        assert_eq!(ASSET.negotiate("*"), Encoding::Brotli);
+        assert_eq!(ASSET.negotiate(""), Encoding::Identity);
+    }
+
+    #[test]
+    fn negotiate_follows_q_values() {
+        assert_eq!(ASSET.negotiate("br;q=0.5, gzip;q=0.8"), Encoding::Gzip);
+        assert_eq!(ASSET.negotiate("br;q=0, gzip;q=0"), Encoding::Identity);
+        assert_eq!(ASSET.negotiate("*;q=0, gzip"), Encoding::Gzip);
+        // Nothing acceptable is left, so it falls back to identity:
+        assert_eq!(ASSET.negotiate("identity;q=0"), Encoding::Identity);
+    }
+
+    #[test]
+    fn negotiate_skips_missing_variants() {
+        let asset = StaticAsset {
+            brotli: None,
+            ..ASSET
+        };
+        assert_eq!(asset.negotiate("br, gzip;q=0.1"), Encoding::Gzip);
+        let asset = StaticAsset {
+            gzip: None,
+            ..asset
+        };
+        assert_eq!(asset.negotiate("br, gzip"), Encoding::Identity);
+    }
+
+    #[test]
+    fn etags_match_any_representation() {
+        assert_eq!(ASSET.etag(Encoding::Identity), "\"abc123\"");
+        assert_eq!(ASSET.etag(Encoding::Gzip), "\"abc123-gzip\"");
+        assert!(ASSET.is_not_modified("\"abc123-br\""));
+        assert!(ASSET.is_not_modified("\"other\", W/\"abc123\""));
+        assert!(ASSET.is_not_modified("*"));
+        assert!(!ASSET.is_not_modified("\"abc124\""));
+    }
+}
@@ -0,0 +1,86 @@
+use dry_console_dto::profile::EnvProfile;
+use dry_console_dto::schedule::Schedule;
+use dry_console_dto::sudo::Escalator;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// The default config file: $XDG_CONFIG_HOME/dry_console/config.json
+pub fn default_config_path() -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix("dry_console")
+        .ok()
+        .map(|dirs| dirs.get_config_home().join("config.json"))
+}
+
+/// Replace the file in one go, so it is never half written. It is only
+/// readable by the user.
+pub fn write_file_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension("partial");
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&partial)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(partial, path)
+}
+
+/// Is it a valid name of a schedule or profile? (They are used in URLs.)
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The settings that are edited in the app (rather than given on the
+/// command line).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+    #[serde(default)]
+    pub profiles: Vec<EnvProfile>,
+    /// The program used to acquire root privileges (eg. "doas"), unless
+    /// --escalator is given. This one is only edited in the file, and
+    /// is read when the server starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalator: Option<Escalator>,
+}
+
+impl Config {
+    /// Load the config from path. If it can't be read, the defaults
+    /// are used (and it is not saved over until something changes).
+    pub fn load(path: Option<&Path>) -> Self {
+        match path.map(fs::read_to_string) {
+            Some(Ok(source)) => serde_json::from_str(&source).unwrap_or_else(|e| {
+                warn!("The config file is invalid, so the defaults are used: {e}");
+                Config::default()
+            }),
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                warn!("Could not read the config file, so the defaults are used: {e}");
+                Config::default()
+            }
+            _ => Config::default(),
+        }
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&EnvProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    pub fn save(&self, path: Option<&Path>) -> io::Result<()> {
+        match path {
+            Some(path) => write_file_atomically(path, &serde_json::to_vec_pretty(self)?),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use dry_console_dto::profile::{is_secret_name, mask_env, EnvProfile};
+use std::collections::BTreeMap;
+
+/// The variables of the server's environment that scripts inherit
+/// (besides those given with --pass-env, and the LC_* locale ones).
+/// Everything else (eg. DOCKER_HOST) must come from a profile:
+const BASE_ENV: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "LANG",
+    "LANGUAGE",
+    "TERM",
+    "TZ",
+    "XDG_RUNTIME_DIR",
+    "XDG_CONFIG_HOME",
+    "XDG_DATA_HOME",
+    "XDG_STATE_HOME",
+    "XDG_CACHE_HOME",
+    // For systemd-run --user:
+    "DBUS_SESSION_BUS_ADDRESS",
+    "SSH_AUTH_SOCK",
+];
+
+/// Is it a valid name of an environment variable?
+pub fn is_valid_var(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The sanitized environment of the server, that every script starts from.
+pub fn base_env(pass_env: &[String]) -> BTreeMap<String, String> {
+    std::env::vars()
+        .filter(|(name, _)| {
+            BASE_ENV.contains(&name.as_str())
+                || name.starts_with("LC_")
+                || pass_env.iter().any(|n| n == name)
+        })
+        .collect()
+}
+
+/// The environment of a run: the base environment, then the variables
+/// of the profile, then the run's own (eg. the params of a workflow
+/// step), then the secrets from the vault that the script uses.
+pub fn effective_env(
+    base: BTreeMap<String, String>,
+    profile: Option<&EnvProfile>,
+    env: &BTreeMap<String, String>,
+    secrets: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut effective = base;
+    if let Some(profile) = profile {
+        effective.extend(profile.vars.clone());
+    }
+    effective.extend(env.clone());
+    effective.extend(secrets.clone());
+    effective
+}
+
+/// The environment of a run, as it is recorded, with the values of its
+/// secrets masked.
+pub fn masked_env(
+    env: &BTreeMap<String, String>,
+    profile: Option<&EnvProfile>,
+    secrets: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    mask_env(env, |name| {
+        secrets.contains_key(name)
+            || match profile {
+                Some(profile) => profile.is_secret(name),
+                None => is_secret_name(name),
+            }
+    })
+}
+
+/// The values of the secrets of a run, that are redacted from its
+/// output: those from the vault, and those of its profile.
+pub fn secret_values<'a>(
+    profile: Option<&'a EnvProfile>,
+    secrets: &'a BTreeMap<String, String>,
+) -> impl Iterator<Item = &'a String> {
+    let profile_secrets = profile
+        .into_iter()
+        .flat_map(|p| p.vars.iter().filter(|(name, _)| p.is_secret(name)));
+    secrets
+        .values()
+        .chain(profile_secrets.map(|(_, value)| value))
+}
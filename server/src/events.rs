@@ -0,0 +1,30 @@
+use dry_console_dto::events::Event;
+use tokio::sync::broadcast;
+use tracing::debug;
+
+/// How many events a slow client may fall behind before it misses some.
+const EVENT_BUFFER_CAPACITY: usize = 256;
+
+/// The server-wide event bus: every event published here is pushed to
+/// all the clients of /api/events/.
+#[derive(Clone, Debug)]
+pub struct EventBus {
+    live: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (live, _) = broadcast::channel(EVENT_BUFFER_CAPACITY);
+        EventBus { live }
+    }
+
+    pub fn publish(&self, event: Event) {
+        debug!("Event: {event:?}");
+        // Nobody may be listening, which is fine:
+        let _ = self.live.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.live.subscribe()
+    }
+}
@@ -0,0 +1,131 @@
+use crate::config::write_file_atomically;
+use dry_console_dto::history::RunRecord;
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+use ulid::Ulid;
+
+/// How many runs are kept.
+const MAX_HISTORY: usize = 500;
+
+/// The default run history: $XDG_STATE_HOME/dry_console/history.jsonl
+pub fn default_history_path() -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix("dry_console")
+        .ok()
+        .map(|dirs| dirs.get_state_home().join("history.jsonl"))
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// The most recent runs of library scripts, oldest first. Each run is
+/// appended to the history file (one JSON record per line) once it has
+/// finished, so the history outlives the server.
+#[derive(Debug, Clone, Default)]
+pub struct RunHistory {
+    path: Option<PathBuf>,
+    records: VecDeque<RunRecord>,
+}
+
+impl RunHistory {
+    /// Load the most recent runs from the history file, rewriting it
+    /// if it has grown too long.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut records = VecDeque::new();
+        let mut lines = 0;
+        match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(source)) => {
+                for line in source.lines() {
+                    lines += 1;
+                    match serde_json::from_str::<RunRecord>(line) {
+                        Ok(record) => records.push_back(record),
+                        Err(e) => warn!("Skipping an invalid record in the run history: {e}"),
+                    }
+                    if records.len() > MAX_HISTORY {
+                        records.pop_front();
+                    }
+                }
+            }
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                warn!("Could not read the run history: {e}");
+            }
+            _ => {}
+        }
+        let history = Self { path, records };
+        if lines > 2 * MAX_HISTORY {
+            if let Err(e) = history.rewrite() {
+                warn!("Could not rewrite the run history: {e}");
+            }
+        }
+        history
+    }
+
+    fn rewrite(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut contents = Vec::new();
+        for record in &self.records {
+            serde_json::to_writer(&mut contents, record)?;
+            contents.push(b'\n');
+        }
+        write_file_atomically(path, &contents)
+    }
+
+    fn append(&self, record: &RunRecord) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(&line)
+    }
+
+    /// Record a run that has started (or was rejected, if it is already
+    /// finished).
+    pub fn start(&mut self, record: RunRecord) {
+        if record.finished_ms.is_some() {
+            if let Err(e) = self.append(&record) {
+                warn!("Could not save the run history: {e}");
+            }
+        }
+        self.records.push_back(record);
+        if self.records.len() > MAX_HISTORY {
+            self.records.pop_front();
+        }
+    }
+
+    /// Update the record of a run that has finished.
+    pub fn finish(&mut self, id: Ulid, update: impl FnOnce(&mut RunRecord)) {
+        let Some(record) = self.records.iter_mut().rev().find(|r| r.id == id) else {
+            return;
+        };
+        update(record);
+        record.finished_ms.get_or_insert_with(now_ms);
+        let record = record.clone();
+        if let Err(e) = self.append(&record) {
+            warn!("Could not save the run history: {e}");
+        }
+    }
+
+    /// The runs, most recent first.
+    pub fn recent(&self) -> impl Iterator<Item = &RunRecord> {
+        self.records.iter().rev()
+    }
+}
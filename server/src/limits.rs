@@ -0,0 +1,46 @@
+use dry_console_dto::script::ScriptLimits;
+use std::process::Stdio;
+use tokio::process::Command;
+use which::which;
+
+/// Can scripts be run in a transient systemd scope (of the user's own
+/// service manager), to limit their CPU and memory?
+pub fn detect_systemd_scope() -> bool {
+    which("systemd-run").is_ok()
+        && std::process::Command::new("systemd-run")
+            .args(["--user", "--scope", "--quiet", "--collect", "--", "true"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+}
+
+/// Does enforcing the limits need a systemd scope? (The timeout is
+/// enforced by the server itself.)
+pub fn needs_scope(limits: &ScriptLimits) -> bool {
+    limits.cpu_percent.is_some() || limits.memory_bytes.is_some()
+}
+
+/// The command that runs a script with bash: in a systemd scope with
+/// the CPU and memory limits, if there are any and systemd_scope is
+/// available.
+pub fn script_command(script: &str, limits: &ScriptLimits, systemd_scope: bool) -> Command {
+    let mut command = match systemd_scope && needs_scope(limits) {
+        true => {
+            let mut command = Command::new("systemd-run");
+            command.args(["--user", "--scope", "--quiet", "--collect"]);
+            if let Some(cpu) = limits.cpu_percent {
+                command.arg(format!("--property=CPUQuota={cpu}%"));
+            }
+            if let Some(memory) = limits.memory_bytes {
+                command.arg(format!("--property=MemoryMax={memory}"));
+            }
+            command.args(["--", "/bin/bash"]);
+            command
+        }
+        false => Command::new("/bin/bash"),
+    };
+    command.arg("-c").arg(script);
+    command
+}
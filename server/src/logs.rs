@@ -0,0 +1,231 @@
+use crate::Opt;
+use dry_console_dto::logs::{LogEvent, LogLevel};
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::field::MakeExt;
+use tracing_subscriber::fmt::format;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Number of recent events kept in memory for the log viewer.
+const RING_BUFFER_CAPACITY: usize = 2000;
+/// Number of rotated (daily) log files to keep.
+const MAX_LOG_FILES: usize = 7;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// In-memory ring buffer of recent events
+////////////////////////////////////////////////////////////////////////////////
+lazy_static! {
+    pub static ref LOG_BUFFER: LogBuffer = LogBuffer::new(RING_BUFFER_CAPACITY);
+}
+
+pub struct LogBuffer {
+    capacity: usize,
+    events: Mutex<VecDeque<LogEvent>>,
+    live: broadcast::Sender<LogEvent>,
+}
+
+impl LogBuffer {
+    fn new(capacity: usize) -> Self {
+        let (live, _) = broadcast::channel(capacity);
+        LogBuffer {
+            capacity,
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+            live,
+        }
+    }
+
+    fn push(&self, event: LogEvent) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+        // Nobody may be listening, which is fine:
+        let _ = self.live.send(event);
+    }
+
+    /// A copy of the buffered events, plus a receiver for all events
+    /// logged after them (none are missed or repeated in between).
+    pub fn subscribe(&self) -> (Vec<LogEvent>, broadcast::Receiver<LogEvent>) {
+        let events = self.events.lock().unwrap();
+        (events.iter().cloned().collect(), self.live.subscribe())
+    }
+}
+
+/// Tracing layer that copies every (enabled) event into LOG_BUFFER.
+struct RingBufferLayer;
+
+impl<S: Subscriber> Layer<S> for RingBufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        LOG_BUFFER.push(LogEvent {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            level: match *metadata.level() {
+                Level::ERROR => LogLevel::Error,
+                Level::WARN => LogLevel::Warn,
+                Level::INFO => LogLevel::Info,
+                Level::DEBUG => LogLevel::Debug,
+                Level::TRACE => LogLevel::Trace,
+            },
+            target: metadata.target().to_string(),
+            message: visitor.finish(),
+        });
+    }
+}
+
+/// Formats an event like the text formatter does: the message, then
+/// the remaining fields as `key=value`.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl MessageVisitor {
+    fn finish(self) -> String {
+        match (self.message.is_empty(), self.fields.is_empty()) {
+            (_, true) => self.message,
+            (true, false) => self.fields,
+            (false, false) => format!("{} {}", self.message, self.fields),
+        }
+    }
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            if !self.fields.is_empty() {
+                self.fields.push(' ');
+            }
+            let _ = write!(self.fields, "{}={value:?}", field.name());
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Subscriber setup
+////////////////////////////////////////////////////////////////////////////////
+
+/// The default log directory: $XDG_STATE_HOME/dry_console/logs
+pub fn default_log_dir() -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix("dry_console")
+        .ok()
+        .map(|dirs| dirs.get_state_home().join("logs"))
+}
+
+fn env_filter() -> EnvFilter {
+    EnvFilter::from_default_env()
+        // Suppress DEBUG logging for HTTP requests:
+        .add_directive("tower_http::trace=info".parse().unwrap())
+        .add_directive("axum::rejection=trace".parse().unwrap())
+}
+
+/// Install the global tracing subscriber: the console, the rotating
+/// log file (unless disabled), and the in-memory ring buffer. The
+/// returned guard flushes the log file when dropped, so it must be
+/// held for the life of the program.
+pub fn init(opt: &Opt) -> Option<WorkerGuard> {
+    let log_dir = match opt.no_log_file {
+        true => None,
+        false => opt.log_dir.clone().or_else(default_log_dir),
+    };
+    let mut file_error = None;
+    let (file_writer, guard) = match log_dir.as_ref().map(|dir| {
+        std::fs::create_dir_all(dir)?;
+        RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("dry_console")
+            .filename_suffix("log")
+            .max_log_files(MAX_LOG_FILES)
+            .build(dir)
+            .map_err(std::io::Error::other)
+    }) {
+        Some(Ok(appender)) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(writer), Some(guard))
+        }
+        Some(Err(e)) => {
+            file_error = Some(e);
+            (None, None)
+        }
+        None => (None, None),
+    };
+
+    let (console_text, console_json, file_text, file_json) = match opt.log_format {
+        LogFormat::Text => (
+            Some(tracing_subscriber::fmt::layer()),
+            None,
+            file_writer.map(|w| {
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    // Span fields are cached per field formatter type, so
+                    // the file needs its own to not reuse the colored ones:
+                    .fmt_fields(
+                        format::debug_fn(|writer, field, value| match field.name() {
+                            "message" => write!(writer, "{value:?}"),
+                            name => write!(writer, "{name}={value:?}"),
+                        })
+                        .delimited(" "),
+                    )
+                    .with_writer(w)
+            }),
+            None,
+        ),
+        LogFormat::Json => (
+            None,
+            Some(tracing_subscriber::fmt::layer().json()),
+            None,
+            file_writer.map(|w| tracing_subscriber::fmt::layer().json().with_writer(w)),
+        ),
+    };
+    tracing_subscriber::registry()
+        .with(env_filter())
+        .with(console_text)
+        .with(console_json)
+        .with(file_text)
+        .with(file_json)
+        .with(RingBufferLayer)
+        .init();
+
+    match (log_dir, file_error) {
+        (Some(dir), Some(e)) => {
+            tracing::error!("Could not open log file in {}: {}", dir.display(), e)
+        }
+        (Some(dir), None) => tracing::info!("Logging to {}", dir.display()),
+        _ => {}
+    }
+    guard
+}
@@ -1,21 +1,43 @@
 mod api;
 mod app_state;
+mod assets;
+mod config;
+mod environment;
+mod events;
+mod history;
+mod limits;
+mod logs;
+mod metrics;
 mod response;
 mod routing;
+mod run_queue;
+mod scheduler;
+mod secrets;
+mod shell_library;
 mod sudo;
+mod template;
+mod trust;
+mod workflow_command;
 
 use crate::api::auth::Backend;
 use api::workstation::platform::detect_toolbox;
 use app_state::SharedState;
 use axum::extract::State;
-use axum::http::{header, StatusCode};
-use axum::response::{Html, IntoResponse};
+use axum::http::HeaderMap;
+use axum::middleware;
 use axum::routing::{get, MethodRouter};
 use axum::Router;
 use clap::ArgAction;
 use clap::Parser;
+use dry_console_dto::script::{
+    parse_bytes, parse_cpu_percent, parse_duration_seconds, ScriptLimits,
+};
+use dry_console_dto::sudo::{Escalator, SudoState};
+use logs::LogFormat;
 use std::convert::Infallible;
 use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::process;
 use std::process::exit;
 use std::str::FromStr;
@@ -33,44 +55,9 @@ pub type AppMethodRouter = MethodRouter<SharedState, Infallible>;
 ////////////////////////////////////////////////////////////////////////////////
 // static assets
 ////////////////////////////////////////////////////////////////////////////////
-include!(concat!(env!("OUT_DIR"), "/generated_includes.rs"));
 include!(concat!(env!("OUT_DIR"), "/generated_command_library.rs"));
-const CLIENT_INDEX_HTML: &[u8] = include_bytes!("../../dist/index.html");
-const CLIENT_JS: &[u8] = include_bytes!("../../dist/frontend.js");
-const CLIENT_WASM: &[u8] = include_bytes!("../../dist/frontend_bg.wasm");
 const _SYSTEMD_UNIT: &[u8] = include_bytes!("../../systemd.service");
 
-async fn client_index_html() -> Html<&'static [u8]> {
-    Html(CLIENT_INDEX_HTML)
-}
-
-async fn client_js() -> impl IntoResponse {
-    (
-        StatusCode::OK,
-        [(header::CONTENT_TYPE, "application/javascript")],
-        CLIENT_JS,
-    )
-}
-
-async fn client_wasm() -> impl IntoResponse {
-    (
-        StatusCode::OK,
-        [(header::CONTENT_TYPE, "application/wasm")],
-        CLIENT_WASM,
-    )
-}
-
-async fn serve_inline_file(
-    content: &'static [u8],
-    content_type: &'static str,
-) -> impl IntoResponse {
-    (
-        StatusCode::OK,
-        [(header::CONTENT_TYPE, content_type)],
-        content,
-    )
-}
-
 ////////////////////////////////////////////////////////////////////////////////
 // Command line interface
 ////////////////////////////////////////////////////////////////////////////////
@@ -84,6 +71,18 @@ pub struct Opt {
     #[clap(short = 'l', long = "log", default_value = "info")]
     log_level: String,
 
+    /// set the log format
+    #[clap(long = "log-format", value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Directory for the (daily rotated) log files [default: $XDG_STATE_HOME/dry_console/logs]
+    #[clap(long = "log-dir")]
+    log_dir: Option<PathBuf>,
+
+    /// Disable logging to files
+    #[clap(long = "no-log-file", action = ArgAction::SetTrue)]
+    no_log_file: bool,
+
     /// set the listen addr
     #[clap(short = 'a', long = "addr", default_value = "127.0.0.1")]
     addr: String,
@@ -96,6 +95,14 @@ pub struct Opt {
     #[clap(long = "open")]
     open: bool,
 
+    /// Mount the app under this path prefix (eg. /dry_console), when running behind a reverse proxy
+    #[clap(long = "base-path", default_value = "", value_parser = parse_base_path)]
+    base_path: String,
+
+    /// Serve Prometheus metrics at /metrics (/healthz is always available)
+    #[clap(long = "metrics", action = ArgAction::SetTrue)]
+    metrics: bool,
+
     /// Acquire root privileges via sudo and maintain its session indefinitely (This feature is activated automatically if the host is a toolbox container)
     #[clap(long = "sudo", action = ArgAction::SetTrue)]
     sudo: bool,
@@ -104,6 +111,10 @@ pub struct Opt {
     #[clap(long = "no-sudo", action = ArgAction::SetTrue)]
     no_sudo: bool,
 
+    /// The program used to acquire root privileges: sudo, doas, run0 or pkexec (pkexec always prompts, so scripts can not use it) [default: the escalator of the config file, or else the first one installed]
+    #[clap(long = "escalator")]
+    escalator: Option<Escalator>,
+
     /// Timeout for sudo authentication, in seconds
     #[clap(long = "sudo-timeout-seconds", default_value = "60")]
     sudo_timeout_seconds: u64,
@@ -111,9 +122,85 @@ pub struct Opt {
     /// Refresh interval to keep sudo session alive, in seconds
     #[clap(long = "sudo-refresh-interval", default_value = "60")]
     sudo_refresh_interval: u64,
+
+    /// When a command is cancelled, how long to wait after sending SIGINT (and then SIGTERM) before sending the next signal, in seconds
+    #[clap(long = "cancel-timeout-seconds", default_value = "5")]
+    cancel_timeout_seconds: u64,
+
+    /// The longest a script may run (eg. 90s, 10m or 2h), unless it declares its own `# timeout:` [default: no limit]
+    #[clap(long = "script-timeout", value_parser = parse_duration_seconds)]
+    script_timeout: Option<u64>,
+
+    /// The CPU a script may use, as a percentage of one CPU (eg. 50% or 200%), unless it declares its own `# cpu:` [default: no limit]
+    #[clap(long = "script-cpu", value_parser = parse_cpu_percent)]
+    script_cpu: Option<u32>,
+
+    /// The memory a script may use (eg. 512M or 2G), unless it declares its own `# memory:` [default: no limit]
+    #[clap(long = "script-memory", value_parser = parse_bytes)]
+    script_memory: Option<u64>,
+
+    /// The file of approved (non-builtin) scripts [default: $XDG_DATA_HOME/dry_console/trusted_scripts.json]
+    #[clap(long = "trust-store")]
+    trust_store: Option<PathBuf>,
+
+    /// Directory of user shell libraries, that scripts may include (`# include: NAME` for NAME.sh) [default: $XDG_DATA_HOME/dry_console/libraries]
+    #[clap(long = "library-dir")]
+    library_dir: Option<PathBuf>,
+
+    /// The config file, of the settings edited in the app (eg. schedules) [default: $XDG_CONFIG_HOME/dry_console/config.json]
+    #[clap(long = "config")]
+    config: Option<PathBuf>,
+
+    /// The file of recent script runs [default: $XDG_STATE_HOME/dry_console/history.jsonl]
+    #[clap(long = "history")]
+    history: Option<PathBuf>,
+
+    /// Other variables of the server's environment that scripts inherit (eg. DOCKER_HOST,HTTP_PROXY), besides the basic ones (PATH, HOME, etc.) and those of their profile
+    #[clap(long = "pass-env", value_delimiter = ',')]
+    pass_env: Vec<String>,
+
+    /// The encrypted file of secrets that scripts may use (`# secrets: NAME`) [default: $XDG_DATA_HOME/dry_console/secrets.vault]
+    #[clap(long = "vault")]
+    vault: Option<PathBuf>,
+
+    /// The most scripts that may run at once. Any more wait in the run queue, as do scripts that need a lock (`# locks: NAME`) that another one holds [default: no limit]
+    #[clap(long = "max-concurrent-runs")]
+    max_concurrent_runs: Option<NonZeroUsize>,
+}
+
+/// Check --base-path: it is used in URLs, and in the Path of the session
+/// cookie, so it must start with a slash and must not contain a `;` or
+/// control characters.
+fn parse_base_path(value: &str) -> Result<String, String> {
+    if !value.is_empty() && !value.starts_with('/') {
+        return Err(format!("The base path must start with a /: {value}"));
+    }
+    if value.chars().any(|c| c.is_control() || c == ';') {
+        return Err(format!(
+            "The base path must not contain a ; or control characters: {value:?}"
+        ));
+    }
+    Ok(value.to_string())
 }
 
 impl Opt {
+    /// The normalized --base-path: either empty, or with a leading slash and no trailing slash.
+    pub fn base_path(&self) -> String {
+        match self.base_path.trim_matches('/') {
+            "" => String::new(),
+            p => format!("/{p}"),
+        }
+    }
+
+    /// The default limits of every script.
+    pub fn script_limits(&self) -> ScriptLimits {
+        ScriptLimits {
+            timeout_seconds: self.script_timeout,
+            cpu_percent: self.script_cpu,
+            memory_bytes: self.script_memory,
+        }
+    }
+
     fn resolve_sudo(&self) -> Option<bool> {
         if self.no_sudo {
             // Disable via --no-sudo explicitly
@@ -141,15 +228,8 @@ async fn main() {
             ),
         )
     }
-    // enable console logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                // Suppress DEBUG logging for HTTP requests:
-                .add_directive("tower_http::trace=info".parse().unwrap())
-                .add_directive("axum::rejection=trace".parse().unwrap()),
-        )
-        .init();
+    // enable console, file, and in-app logging
+    let _log_guard = logs::init(&opt);
 
     // Make sure this is not run as root
     if get_current_uid() == 0 {
@@ -158,52 +238,66 @@ async fn main() {
     }
 
     let shared_state = app_state::create_shared_state(&opt);
+    metrics::init();
 
     // Acquire root privilege only if configured to do so, unless the
     // host is detected to be a toolbox or distrobox container, in
     // which case the feature should be enabled by default:
-    match opt.resolve_sudo() {
-        Some(true) => {
-            warn!("Root access will now be requested via sudo:");
-            match sudo::acquire_sudo(opt.sudo_timeout_seconds).await {
-                Ok(_) => {
-                    tokio::spawn(async move {
-                        sudo::keep_sudo_session_alive(
-                            opt.sudo_refresh_interval,
-                            opt.sudo_timeout_seconds,
-                        )
-                        .await;
-                    });
-                }
+    let escalator = shared_state.read().await.escalator;
+    match (opt.resolve_sudo(), escalator) {
+        (Some(true), None) => {
+            error!("Root access was requested (--sudo), but no privilege escalation program (sudo, doas, run0 or pkexec) is installed.");
+            exit(1);
+        }
+        (Some(true), Some(escalator)) => {
+            warn!("Root access will now be requested via {escalator}:");
+            match sudo::acquire_sudo(escalator, opt.sudo_timeout_seconds).await {
+                Ok(_) => {}
                 Err(e) => {
-                    error!("Failed to acquire sudo authentication: {}", e);
+                    error!("Failed to acquire {escalator} authentication: {}", e);
                     exit(1);
                 }
             };
-            {
-                let mut state = shared_state.write().await;
-                state.sudo_enabled = true;
-            }
+            sudo::set_status(&shared_state, SudoState::Active, None).await;
         }
-        Some(false) => {}
-        None => {
-            if detect_toolbox() {
-                match sudo::acquire_sudo(2).await {
+        (Some(false), _) => {}
+        (None, escalator) => {
+            if let (true, Some(escalator)) = (detect_toolbox(), escalator) {
+                match sudo::acquire_sudo(escalator, 2).await {
                     Ok(_) => {
-                        warn!("A toolbox-like container was detected, therefore container level root access is acquired automatically via sudo.");
+                        warn!("A toolbox-like container was detected, therefore container level root access is acquired automatically via {escalator}.");
                     }
                     Err(e) => {
-                        error!("A toolbox-like container was detected, but there was an unexpected failure to acquire sudo privileges :: {}", e);
+                        error!("A toolbox-like container was detected, but there was an unexpected failure to acquire {escalator} privileges :: {}", e);
                         exit(1);
                     }
                 }
-                {
-                    let mut state = shared_state.write().await;
-                    state.sudo_enabled = true;
-                }
+                sudo::set_status(&shared_state, SudoState::Active, None).await;
             }
         }
     }
+    // (run0 and pkexec have no session to keep alive.)
+    let keepalive = escalator.filter(|e| e.caches_credentials());
+    if let (false, Some(escalator)) = (opt.no_sudo, keepalive) {
+        // Keeps sudo alive whenever it is Active, even if it is
+        // acquired later on (from the browser):
+        let state = shared_state.clone();
+        tokio::spawn(async move {
+            sudo::keep_sudo_session_alive(
+                state,
+                escalator,
+                opt.sudo_refresh_interval,
+                opt.sudo_timeout_seconds,
+            )
+            .await;
+        });
+    }
+
+    // Tells every client when a dependency is installed, or removed:
+    tokio::spawn(api::workstation::watch_dependencies(shared_state.clone()));
+
+    // Runs the scheduled scripts whenever they are due:
+    tokio::spawn(scheduler::run_schedules(shared_state.clone()));
 
     // Shutdown signal handler
     let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
@@ -221,23 +315,44 @@ async fn main() {
         opt.port,
     ));
 
-    info!("listening on http://{sock_addr}");
+    let base_path = opt.base_path();
+    info!("listening on http://{sock_addr}{base_path}/");
     let auth_backend = Backend::new(&shared_state);
-    let inline_files = get_inline_files();
-    let mut router = Router::new()
-        .layer(routing::SlashRedirectLayer)
-        .nest(
-            API_PREFIX,
-            api::router(auth_backend, shutdown_tx, State(shared_state.clone())),
-        )
-        .route("/", get(client_index_html))
-        .route("/frontend.js", get(client_js))
-        .route("/frontend_bg.wasm", get(client_wasm));
-    for (name, content, content_type) in inline_files {
-        router = router.route(name, get(move || serve_inline_file(content, content_type)));
+    let mut router = Router::new().layer(routing::SlashRedirectLayer).nest(
+        API_PREFIX,
+        api::router(
+            auth_backend,
+            shutdown_tx,
+            State(shared_state.clone()),
+            &base_path,
+        ),
+    );
+    let mut index_html = None;
+    for asset in assets::get_inline_files() {
+        if asset.path == "/index.html" {
+            let asset = assets::with_base_path(asset, &base_path);
+            index_html = Some(get(move |headers: HeaderMap| assets::serve(asset, headers)));
+        } else {
+            router = router.route(
+                asset.path,
+                get(move |headers: HeaderMap| assets::serve(asset, headers)),
+            );
+        }
+    }
+    // The SPA handles its own routes client side:
+    let index_html = index_html.expect("index.html was not embedded");
+    router = router
+        .merge(metrics::router(opt.metrics))
+        .route("/", index_html.clone())
+        .route("/*else", index_html.clone());
+    if !base_path.is_empty() {
+        // Everything is mounted under the prefix, including the API:
+        router = Router::new()
+            .nest(&base_path, router)
+            .route(&format!("{base_path}/"), index_html);
     }
     let mut router = router
-        .route("/*else", get(client_index_html))
+        .layer(middleware::from_fn(metrics::track_http))
         .layer(TraceLayer::new_for_http())
         .with_state(shared_state.clone());
 
@@ -262,7 +377,7 @@ async fn main() {
             .cache_get_string("token", "xxx");
     }
     if opt.open {
-        open::that(format!("http://{sock_addr}/login#token:{token}"))
+        open::that(format!("http://{sock_addr}{base_path}/login#token:{token}"))
             .expect("Couldn't open web browser.");
     }
 
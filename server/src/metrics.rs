@@ -0,0 +1,153 @@
+use crate::AppRouter;
+use axum::extract::{MatchedPath, Request};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+};
+use serde_json::json;
+use std::time::Instant;
+
+////////////////////////////////////////////////////////////////////////////////
+// Global metrics registry
+////////////////////////////////////////////////////////////////////////////////
+lazy_static! {
+    pub static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "dry_console_http_requests_total",
+        "Number of HTTP requests, by route",
+        &["method", "route", "status"]
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "dry_console_http_request_duration_seconds",
+        "HTTP request latency, by route",
+        &["method", "route"]
+    )
+    .unwrap();
+    pub static ref WEBSOCKET_CONNECTIONS: IntGauge = register_int_gauge!(
+        "dry_console_websocket_connections",
+        "Number of open websocket connections"
+    )
+    .unwrap();
+    pub static ref PROCESSES_RUNNING: IntGauge = register_int_gauge!(
+        "dry_console_processes_running",
+        "Number of scripts currently running"
+    )
+    .unwrap();
+    pub static ref PROCESS_EXITS: IntCounterVec = register_int_counter_vec!(
+        "dry_console_process_exits_total",
+        "Number of completed scripts, by script and exit code",
+        &["script", "code"]
+    )
+    .unwrap();
+    pub static ref SUDO_KEEPALIVE_FAILURES: IntCounter = register_int_counter!(
+        "dry_console_sudo_keepalive_failures_total",
+        "Number of times the sudo session could not be refreshed"
+    )
+    .unwrap();
+    pub static ref LOGIN_ATTEMPTS: IntCounterVec = register_int_counter_vec!(
+        "dry_console_login_attempts_total",
+        "Number of login attempts, by result",
+        &["result"]
+    )
+    .unwrap();
+}
+
+/// Register all metrics up front, so they are reported (as zero)
+/// before their first use.
+pub fn init() {
+    lazy_static::initialize(&HTTP_REQUESTS);
+    lazy_static::initialize(&HTTP_REQUEST_DURATION);
+    lazy_static::initialize(&WEBSOCKET_CONNECTIONS);
+    lazy_static::initialize(&PROCESSES_RUNNING);
+    lazy_static::initialize(&PROCESS_EXITS);
+    lazy_static::initialize(&SUDO_KEEPALIVE_FAILURES);
+    lazy_static::initialize(&LOGIN_ATTEMPTS);
+}
+
+/// Public (unauthenticated) monitoring routes, mounted at the root.
+/// /metrics is only available when enabled with --metrics.
+pub fn router(metrics_enabled: bool) -> AppRouter {
+    let router = Router::new().route("/healthz", get(healthz));
+    if metrics_enabled {
+        router.route("/metrics", get(metrics))
+    } else {
+        router
+    }
+}
+
+async fn healthz() -> impl IntoResponse {
+    Json(json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+    }))
+}
+
+async fn metrics() -> Response {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    match encoder.encode(&prometheus::gather(), &mut buffer) {
+        Ok(_) => ([(header::CONTENT_TYPE, encoder.format_type())], buffer).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Middleware to count and time every HTTP request. Requests are
+/// labeled by their route pattern (not the raw path), to keep the
+/// number of time series bounded.
+pub async fn track_http(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let response = next.run(request).await;
+    HTTP_REQUESTS
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[&method, &route])
+        .observe(start.elapsed().as_secs_f64());
+    response
+}
+
+/// Keeps the websocket connection gauge accurate, however the
+/// connection handler returns.
+pub struct WebSocketGuard;
+
+impl WebSocketGuard {
+    pub fn open() -> Self {
+        WEBSOCKET_CONNECTIONS.inc();
+        WebSocketGuard
+    }
+}
+
+impl Drop for WebSocketGuard {
+    fn drop(&mut self) {
+        WEBSOCKET_CONNECTIONS.dec();
+    }
+}
+
+/// Keeps the running process gauge accurate, however the process
+/// task finishes.
+pub struct ProcessGuard;
+
+impl ProcessGuard {
+    pub fn start() -> Self {
+        PROCESSES_RUNNING.inc();
+        ProcessGuard
+    }
+}
+
+impl Drop for ProcessGuard {
+    fn drop(&mut self) {
+        PROCESSES_RUNNING.dec();
+    }
+}
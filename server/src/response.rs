@@ -1,3 +1,5 @@
+use crate::secrets::VaultError;
+use crate::shell_library::RenderError;
 use axum::{
     extract::FromRequest,
     http::StatusCode,
@@ -38,6 +40,8 @@ pub enum AppError {
     StateMachineConflict(String),
     #[error("Not found")]
     NotFound,
+    #[error("Conflict: {0}")]
+    Conflict(String),
 }
 
 impl IntoResponse for AppError {
@@ -61,6 +65,7 @@ impl IntoResponse for AppError {
                 (StatusCode::BAD_REQUEST, "JSON validation error".to_string())
             }
             AppError::NotFound => (StatusCode::NOT_FOUND, "Object not found".to_string()),
+            AppError::Conflict(error) => (StatusCode::CONFLICT, error),
         };
         (status, AppJson(ErrorResponse { error: e, trace_id })).into_response()
     }
@@ -87,3 +92,18 @@ impl From<aper::NeverConflict> for AppError {
         Self::StateMachineConflict("State machine conflict".to_string())
     }
 }
+
+impl From<VaultError> for AppError {
+    fn from(err: VaultError) -> Self {
+        match err {
+            VaultError::Io(err) => Self::Io(err),
+            err => Self::Conflict(err.to_string()),
+        }
+    }
+}
+
+impl From<RenderError> for AppError {
+    fn from(err: RenderError) -> Self {
+        Self::Conflict(err.to_string())
+    }
+}
@@ -0,0 +1,292 @@
+use crate::history::now_ms;
+use dry_console_dto::queue::{QueuedRun, RunQueueStatus, RunningRun, CONCURRENCY_LIMIT};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use ulid::Ulid;
+
+/// Where a run is in the queue.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Turn {
+    Waiting {
+        /// 1 for the next run.
+        position: usize,
+        waiting_for: Vec<String>,
+    },
+    Running,
+}
+
+struct Waiting {
+    run: QueuedRun,
+    turn: watch::Sender<Turn>,
+}
+
+#[derive(Default)]
+struct Queue {
+    max_concurrent_runs: Option<usize>,
+    running: Vec<RunningRun>,
+    waiting: Vec<Waiting>,
+}
+
+impl Queue {
+    /// Start every run that can start now, and tell the rest where they
+    /// are. Runs start in the order they are queued, except that a run
+    /// may go ahead of those that are waiting for locks it does not
+    /// want.
+    fn schedule(&mut self) {
+        let mut held: BTreeSet<String> = self
+            .running
+            .iter()
+            .flat_map(|run| run.locks.iter().cloned())
+            .collect();
+        // The locks of the runs that are waiting ahead:
+        let mut wanted = BTreeSet::new();
+        let mut position = 0;
+        let mut index = 0;
+        while index < self.waiting.len() {
+            let run = &self.waiting[index].run;
+            let mut waiting_for: Vec<String> = run
+                .locks
+                .iter()
+                .filter(|lock| held.contains(*lock) || wanted.contains(*lock))
+                .cloned()
+                .collect();
+            if self
+                .max_concurrent_runs
+                .is_some_and(|max| self.running.len() >= max)
+            {
+                waiting_for.push(CONCURRENCY_LIMIT.to_string());
+            }
+            if waiting_for.is_empty() {
+                let Waiting { run, turn } = self.waiting.remove(index);
+                held.extend(run.locks.iter().cloned());
+                self.running.push(RunningRun {
+                    id: run.id,
+                    command: run.command,
+                    locks: run.locks,
+                    started_ms: now_ms(),
+                });
+                turn.send_replace(Turn::Running);
+                continue;
+            }
+            wanted.extend(run.locks.iter().cloned());
+            position += 1;
+            let waiting = &mut self.waiting[index];
+            waiting.run.waiting_for = waiting_for.clone();
+            let next = Turn::Waiting {
+                position,
+                waiting_for,
+            };
+            waiting.turn.send_if_modified(|turn| {
+                let changed = *turn != next;
+                *turn = next;
+                changed
+            });
+            index += 1;
+        }
+    }
+}
+
+/// Runs of library scripts wait here until the locks they declare
+/// (`# locks: NAME, ...`) are free, and there are fewer runs than the
+/// concurrency limit (see --max-concurrent-runs).
+#[derive(Clone, Default)]
+pub struct RunQueue {
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl fmt::Debug for RunQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Ok(queue) = self.queue.try_lock() else {
+            return f.write_str("RunQueue { <locked> }");
+        };
+        f.debug_struct("RunQueue")
+            .field("max_concurrent_runs", &queue.max_concurrent_runs)
+            .field("running", &queue.running.len())
+            .field("waiting", &queue.waiting.len())
+            .finish()
+    }
+}
+
+impl RunQueue {
+    pub fn new(max_concurrent_runs: Option<usize>) -> Self {
+        Self {
+            queue: Arc::new(Mutex::new(Queue {
+                max_concurrent_runs,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Put a run in the queue. It may start right away (see
+    /// Ticket::turn).
+    pub fn enqueue(&self, id: Ulid, command: String, locks: Vec<String>) -> Ticket {
+        let (turn, receiver) = watch::channel(Turn::Waiting {
+            position: 0,
+            waiting_for: Vec::new(),
+        });
+        let mut queue = self.queue.lock().unwrap();
+        queue.waiting.push(Waiting {
+            run: QueuedRun {
+                id,
+                command,
+                locks,
+                queued_ms: now_ms(),
+                waiting_for: Vec::new(),
+            },
+            turn,
+        });
+        queue.schedule();
+        Ticket {
+            queue: self.clone(),
+            id,
+            turn: receiver,
+        }
+    }
+
+    pub fn status(&self) -> RunQueueStatus {
+        let queue = self.queue.lock().unwrap();
+        RunQueueStatus {
+            max_concurrent_runs: queue.max_concurrent_runs,
+            running: queue.running.clone(),
+            queued: queue
+                .waiting
+                .iter()
+                .map(|waiting| waiting.run.clone())
+                .collect(),
+        }
+    }
+
+    /// Move the listed runs to the front of the queue, in that order.
+    pub fn reorder(&self, order: &[Ulid]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.waiting.sort_by_key(|waiting| {
+            order
+                .iter()
+                .position(|id| *id == waiting.run.id)
+                .unwrap_or(order.len())
+        });
+        queue.schedule();
+    }
+
+    /// The run has finished (or has given up waiting).
+    fn remove(&self, id: Ulid) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.running.retain(|run| run.id != id);
+        queue.waiting.retain(|waiting| waiting.run.id != id);
+        queue.schedule();
+    }
+}
+
+/// A run's place in the queue, until it is dropped (once the run has
+/// finished, or was cancelled while it waited), which frees its locks.
+pub struct Ticket {
+    queue: RunQueue,
+    id: Ulid,
+    pub turn: watch::Receiver<Turn>,
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.queue.remove(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enqueue(queue: &RunQueue, locks: &[&str]) -> Ticket {
+        let locks = locks.iter().map(|lock| lock.to_string()).collect();
+        queue.enqueue(Ulid::new(), "TestExampleOne".to_string(), locks)
+    }
+
+    fn turn(ticket: &Ticket) -> Turn {
+        ticket.turn.borrow().clone()
+    }
+
+    fn waiting(position: usize, waiting_for: &[&str]) -> Turn {
+        Turn::Waiting {
+            position,
+            waiting_for: waiting_for.iter().map(|lock| lock.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn runs_without_shared_locks_start_right_away() {
+        let queue = RunQueue::default();
+        let tickets = [
+            enqueue(&queue, &[]),
+            enqueue(&queue, &[]),
+            enqueue(&queue, &["db"]),
+            enqueue(&queue, &["web"]),
+        ];
+        assert!(tickets.iter().all(|ticket| turn(ticket) == Turn::Running));
+        assert_eq!(queue.status().running.len(), 4);
+    }
+
+    #[test]
+    fn a_run_waits_until_its_lock_is_free() {
+        let queue = RunQueue::default();
+        let first = enqueue(&queue, &["db"]);
+        let second = enqueue(&queue, &["db"]);
+        assert_eq!(turn(&first), Turn::Running);
+        assert_eq!(turn(&second), waiting(1, &["db"]));
+        assert_eq!(queue.status().queued[0].waiting_for, ["db"]);
+        drop(first);
+        assert_eq!(turn(&second), Turn::Running);
+        assert!(queue.status().queued.is_empty());
+    }
+
+    #[test]
+    fn a_run_goes_ahead_only_of_runs_that_want_other_locks() {
+        let queue = RunQueue::default();
+        let _running = enqueue(&queue, &["db"]);
+        let first = enqueue(&queue, &["db", "web"]);
+        // It does not want the locks of the run ahead of it:
+        let second = enqueue(&queue, &["cache"]);
+        // It would keep the run ahead of it from starting:
+        let third = enqueue(&queue, &["web"]);
+        assert_eq!(turn(&first), waiting(1, &["db"]));
+        assert_eq!(turn(&second), Turn::Running);
+        assert_eq!(turn(&third), waiting(2, &["web"]));
+    }
+
+    #[test]
+    fn runs_wait_for_room_under_the_concurrency_limit() {
+        let queue = RunQueue::new(Some(1));
+        let first = enqueue(&queue, &[]);
+        let second = enqueue(&queue, &[]);
+        assert_eq!(turn(&first), Turn::Running);
+        assert_eq!(turn(&second), waiting(1, &[CONCURRENCY_LIMIT]));
+        drop(first);
+        assert_eq!(turn(&second), Turn::Running);
+    }
+
+    #[test]
+    fn reordered_runs_start_in_the_new_order() {
+        let queue = RunQueue::new(Some(1));
+        let running = enqueue(&queue, &[]);
+        let first = enqueue(&queue, &[]);
+        let second = enqueue(&queue, &[]);
+        queue.reorder(&[second.id]);
+        assert_eq!(turn(&second), waiting(1, &[CONCURRENCY_LIMIT]));
+        assert_eq!(turn(&first), waiting(2, &[CONCURRENCY_LIMIT]));
+        drop(running);
+        assert_eq!(turn(&second), Turn::Running);
+        assert_eq!(turn(&first), waiting(1, &[CONCURRENCY_LIMIT]));
+    }
+
+    #[test]
+    fn a_run_that_gives_up_waiting_leaves_the_queue() {
+        let queue = RunQueue::default();
+        let _running = enqueue(&queue, &["db"]);
+        let first = enqueue(&queue, &["db", "web"]);
+        let second = enqueue(&queue, &["web"]);
+        assert_eq!(turn(&second), waiting(2, &["web"]));
+        drop(first);
+        assert_eq!(turn(&second), Turn::Running);
+        assert_eq!(queue.status().queued.len(), 0);
+    }
+}
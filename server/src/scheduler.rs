@@ -0,0 +1,156 @@
+use crate::api::workstation::command::CommandLibrary;
+use crate::api::workstation::command_execute::{run_process, CONTROL_SIZE};
+use crate::app_state::{AppState, SharedState};
+use crate::config::{is_valid_name, Config};
+use chrono::{DateTime, Local};
+use dry_console_dto::history::RunTrigger;
+use dry_console_dto::schedule::{Schedule, ScheduleStatus};
+use dry_console_dto::websocket::{Command, ServerMsg};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use ulid::Ulid;
+
+/// The longest the scheduler sleeps before looking at the clock again,
+/// so that a suspend or a change of the clock doesn't delay the runs:
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Parse a cron expression. The usual five fields (minute, hour, day of
+/// month, month and day of week) run at the start of the minute.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule, String> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {expression}"),
+        _ => expression.to_string(),
+    };
+    cron::Schedule::from_str(&expression)
+        .map_err(|e| format!("Invalid cron expression: {expression}: {e}"))
+}
+
+/// Check a new schedule: its name, cron expression, script and profile.
+pub fn validate(schedule: &Schedule, config: &Config) -> Result<(), String> {
+    if !is_valid_name(&schedule.name) {
+        return Err(format!("Invalid schedule name: {}", schedule.name));
+    }
+    parse_cron(&schedule.cron)?;
+    CommandLibrary::from_str(&schedule.script)
+        .map_err(|_| format!("Unknown script: {}", schedule.script))?;
+    if let Some(profile) = &schedule.profile {
+        config
+            .profile(profile)
+            .ok_or_else(|| format!("Unknown profile: {profile}"))?;
+    }
+    Ok(())
+}
+
+/// When the schedule is next due, after the given time (unless it is paused).
+fn next_run_after(schedule: &Schedule, after: &DateTime<Local>) -> Option<DateTime<Local>> {
+    match schedule.paused {
+        true => None,
+        false => parse_cron(&schedule.cron).ok()?.after(after).next(),
+    }
+}
+
+/// Is a run of the schedule still going? (It may still be waiting for
+/// its locks, before it is in the history.)
+pub fn is_running(state: &AppState, name: &str) -> bool {
+    state.running_schedules.contains(name)
+}
+
+pub fn status(state: &AppState, schedule: &Schedule) -> ScheduleStatus {
+    ScheduleStatus {
+        schedule: schedule.clone(),
+        next_run_ms: next_run_after(schedule, &Local::now())
+            .map(|next| next.timestamp_millis() as u64),
+        running: is_running(state, &schedule.name),
+        last_run: state
+            .history
+            .recent()
+            .find(
+                |record| matches!(&record.trigger, RunTrigger::Schedule(n) if *n == schedule.name),
+            )
+            .cloned(),
+    }
+}
+
+/// Start a run of the schedule's script, unless it is still running
+/// from before. Returns the process id. Its output goes only to the run
+/// history.
+pub async fn run_scheduled(state: SharedState, schedule: &Schedule) -> Result<Ulid, String> {
+    let command = CommandLibrary::from_str(&schedule.script)
+        .map_err(|_| format!("Unknown script: {}", schedule.script))?;
+    let command_id = {
+        let mut state = state.write().await;
+        let command_id = state
+            .command_id
+            .get(&command)
+            .and_then(|id| Ulid::from_string(id).ok())
+            .ok_or_else(|| format!("Unknown script: {}", schedule.script))?;
+        // Checked and marked at once, so that it can't be started twice:
+        if !state.running_schedules.insert(schedule.name.clone()) {
+            return Err(format!("{} is still running.", schedule.name));
+        }
+        command_id
+    };
+    let process_id = Ulid::new();
+    info!(
+        "Running schedule {} ({command}, {process_id})",
+        schedule.name
+    );
+    let (outbox, mut messages) = mpsc::channel(CONTROL_SIZE);
+    tokio::spawn(async move {
+        while let Some(message) = messages.recv().await {
+            if let ServerMsg::ProcessError(e) = message {
+                warn!("Scheduled run {process_id} failed: {}", e.message);
+            }
+        }
+    });
+    let env = schedule.params.clone();
+    let profile = schedule.profile.clone();
+    let name = schedule.name.clone();
+    tokio::spawn(async move {
+        // (The process is cancelled if its controls are closed.)
+        let (_controls, control_rx) = mpsc::channel(CONTROL_SIZE);
+        let command = Command {
+            id: command_id,
+            process_id,
+            profile,
+        };
+        let trigger = RunTrigger::Schedule(name.clone());
+        run_process(state.clone(), command, env, outbox, control_rx, trigger).await;
+        state.write().await.running_schedules.remove(&name);
+    });
+    Ok(process_id)
+}
+
+/// Run each schedule whenever it is due, until the server stops.
+pub async fn run_schedules(state: SharedState) {
+    let changed = state.read().await.schedules_changed.clone();
+    let mut last_checked = Local::now();
+    loop {
+        let schedules = state.read().await.config.schedules.clone();
+        let next = schedules
+            .iter()
+            .filter_map(|s| next_run_after(s, &last_checked))
+            .min();
+        let sleep = match next {
+            Some(next) => (next - Local::now()).to_std().unwrap_or_default(),
+            None => MAX_SLEEP,
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(sleep.min(MAX_SLEEP)) => {}
+            // Look again, from the same time, at the changed schedules:
+            _ = changed.notified() => continue,
+        }
+        let now = Local::now();
+        for schedule in &schedules {
+            if next_run_after(schedule, &last_checked).is_none_or(|next| next > now) {
+                continue;
+            }
+            if let Err(e) = run_scheduled(state.clone(), schedule).await {
+                warn!("Skipping schedule {}: {e}", schedule.name);
+            }
+        }
+        last_checked = now;
+    }
+}
@@ -0,0 +1,460 @@
+use crate::config::write_file_atomically;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use dry_console_dto::secrets::VaultStatus;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+/// Replaces the values of secrets in the output of scripts:
+pub const REDACTED: &str = "****";
+
+/// Shorter values are not redacted, or else they would be found all
+/// over the output:
+const MIN_REDACTED_LENGTH: usize = 4;
+
+const VAULT_VERSION: u32 = 1;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const SALT_LENGTH: usize = 16;
+const KEY_LENGTH: usize = 32;
+const AAD: &[u8] = b"dry_console vault v1";
+
+/// The default vault: $XDG_DATA_HOME/dry_console/secrets.vault
+pub fn default_vault_path() -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix("dry_console")
+        .ok()
+        .map(|dirs| dirs.get_data_home().join("secrets.vault"))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VaultError {
+    #[error("The vault is locked.")]
+    Locked,
+    #[error("The passphrase is wrong (or the vault is corrupt).")]
+    WrongPassphrase,
+    #[error("The passphrase is empty.")]
+    EmptyPassphrase,
+    #[error("The passphrase was not confirmed (the confirmation is different).")]
+    Unconfirmed,
+    #[error("The vault is invalid: {0}")]
+    Invalid(String),
+    #[error("There is no vault file (see --vault).")]
+    NoPath,
+    #[error("{0}")]
+    Io(#[from] io::Error),
+}
+
+/// The vault file: the secrets (a JSON object of names and values),
+/// encrypted with ChaCha20-Poly1305, with a key derived from the
+/// passphrase with PBKDF2-HMAC-SHA256.
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The key, and the decrypted secrets, of an unlocked vault.
+#[derive(Clone)]
+pub struct UnlockedVault {
+    key: [u8; KEY_LENGTH],
+    salt: Vec<u8>,
+    iterations: u32,
+    secrets: BTreeMap<String, String>,
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    iterations: u32,
+) -> Result<[u8; KEY_LENGTH], VaultError> {
+    let iterations =
+        NonZeroU32::new(iterations).ok_or_else(|| VaultError::Invalid("no iterations".into()))?;
+    let mut key = [0; KEY_LENGTH];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    Ok(key)
+}
+
+fn aead_key(key: &[u8; KEY_LENGTH]) -> LessSafeKey {
+    LessSafeKey::new(
+        UnboundKey::new(&CHACHA20_POLY1305, key).expect("the key has the right length"),
+    )
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, VaultError> {
+    BASE64
+        .decode(value)
+        .map_err(|e| VaultError::Invalid(e.to_string()))
+}
+
+/// Unlock the vault at path, or create an empty one if there is none
+/// (only if the passphrase is confirmed, so that a typo does not lock
+/// the secrets away).
+/// (This is slow, on purpose, so it should not be run on the runtime.)
+pub fn open_vault(
+    path: &Path,
+    passphrase: &str,
+    confirm: Option<&str>,
+) -> Result<UnlockedVault, VaultError> {
+    if passphrase.is_empty() {
+        return Err(VaultError::EmptyPassphrase);
+    }
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if confirm != Some(passphrase) {
+                return Err(VaultError::Unconfirmed);
+            }
+            let mut salt = vec![0; SALT_LENGTH];
+            SystemRandom::new()
+                .fill(&mut salt)
+                .map_err(|_| VaultError::Invalid("no random numbers".into()))?;
+            let vault = UnlockedVault {
+                key: derive_key(passphrase, &salt, PBKDF2_ITERATIONS)?,
+                salt,
+                iterations: PBKDF2_ITERATIONS,
+                secrets: BTreeMap::new(),
+            };
+            vault.save(path)?;
+            return Ok(vault);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let file: VaultFile =
+        serde_json::from_str(&source).map_err(|e| VaultError::Invalid(e.to_string()))?;
+    if file.version != VAULT_VERSION {
+        return Err(VaultError::Invalid(format!(
+            "unknown version {}",
+            file.version
+        )));
+    }
+    let salt = decode(&file.salt)?;
+    let key = derive_key(passphrase, &salt, file.iterations)?;
+    let nonce = Nonce::try_assume_unique_for_key(&decode(&file.nonce)?)
+        .map_err(|_| VaultError::Invalid("the nonce is the wrong length".into()))?;
+    let mut data = decode(&file.ciphertext)?;
+    let plaintext = aead_key(&key)
+        .open_in_place(nonce, Aad::from(AAD), &mut data)
+        .map_err(|_| VaultError::WrongPassphrase)?;
+    let secrets =
+        serde_json::from_slice(plaintext).map_err(|e| VaultError::Invalid(e.to_string()))?;
+    Ok(UnlockedVault {
+        key,
+        salt,
+        iterations: file.iterations,
+        secrets,
+    })
+}
+
+impl UnlockedVault {
+    /// Encrypt the secrets (with a new nonce), and write the vault file.
+    fn save(&self, path: &Path) -> Result<(), VaultError> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| VaultError::Invalid("no random numbers".into()))?;
+        let mut data = serde_json::to_vec(&self.secrets).map_err(io::Error::from)?;
+        aead_key(&self.key)
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(AAD),
+                &mut data,
+            )
+            .map_err(|_| VaultError::Invalid("could not encrypt".into()))?;
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            iterations: self.iterations,
+            salt: BASE64.encode(&self.salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(data),
+        };
+        let contents = serde_json::to_vec_pretty(&file).map_err(io::Error::from)?;
+        Ok(write_file_atomically(path, &contents)?)
+    }
+}
+
+/// The secrets that scripts may use (see ScriptRequirements::secrets).
+/// The vault is locked when the server starts, and stays unlocked
+/// until it is locked again, or the server stops.
+#[derive(Clone, Default)]
+pub struct Vault {
+    path: Option<PathBuf>,
+    unlocked: Option<UnlockedVault>,
+}
+
+impl fmt::Debug for Vault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Vault")
+            .field("path", &self.path)
+            .field("unlocked", &self.unlocked.is_some())
+            .finish()
+    }
+}
+
+impl Vault {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            unlocked: None,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn status(&self) -> VaultStatus {
+        VaultStatus {
+            exists: self.path.as_ref().is_some_and(|path| path.exists()),
+            unlocked: self.is_unlocked(),
+            names: self
+                .unlocked
+                .iter()
+                .flat_map(|vault| vault.secrets.keys().cloned())
+                .collect(),
+        }
+    }
+
+    /// Keep the vault unlocked (see open_vault).
+    pub fn unlock(&mut self, vault: UnlockedVault) {
+        self.unlocked = Some(vault);
+    }
+
+    pub fn lock(&mut self) {
+        self.unlocked = None;
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.unlocked.is_some()
+    }
+
+    /// The values of the named secrets that are in the vault (none, if
+    /// it is locked).
+    pub fn lookup(&self, names: &[String]) -> BTreeMap<String, String> {
+        let Some(vault) = &self.unlocked else {
+            return BTreeMap::new();
+        };
+        names
+            .iter()
+            .filter_map(|name| Some((name.clone(), vault.secrets.get(name)?.clone())))
+            .collect()
+    }
+
+    /// Add or change a secret, or remove it (if value is None), and
+    /// save the vault. The change is kept only if it was saved, so that
+    /// scripts never get a secret that would be gone after a restart.
+    pub fn set(&mut self, name: &str, value: Option<String>) -> Result<bool, VaultError> {
+        let path = self.path.as_ref().ok_or(VaultError::NoPath)?;
+        let mut vault = self.unlocked.clone().ok_or(VaultError::Locked)?;
+        let existed = match value {
+            Some(value) => vault.secrets.insert(name.to_string(), value).is_some(),
+            None => vault.secrets.remove(name).is_some(),
+        };
+        vault.save(path)?;
+        self.unlocked = Some(vault);
+        Ok(existed)
+    }
+}
+
+/// Replaces the values of secrets with REDACTED, in a stream of output
+/// that arrives in chunks. The end of a chunk that may be the start of
+/// a secret is held back until the next one.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    /// Longest first, so that a secret that contains another is
+    /// redacted as a whole:
+    secrets: Vec<Vec<u8>>,
+    held: Vec<u8>,
+}
+
+impl Redactor {
+    pub fn new<'a>(values: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut secrets: Vec<Vec<u8>> = values
+            .into_iter()
+            .filter(|value| value.len() >= MIN_REDACTED_LENGTH)
+            .map(|value| value.as_bytes().to_vec())
+            .collect();
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        secrets.dedup();
+        Self {
+            secrets,
+            held: Vec::new(),
+        }
+    }
+
+    /// Redact data, except for the end that may be the start of a
+    /// secret, if hold is true.
+    fn scan(&self, data: &[u8], hold: bool) -> (Vec<u8>, Vec<u8>) {
+        let mut redacted = Vec::with_capacity(data.len());
+        let mut i = 0;
+        while i < data.len() {
+            let rest = &data[i..];
+            if hold
+                && self
+                    .secrets
+                    .iter()
+                    .any(|s| s.len() > rest.len() && s.starts_with(rest))
+            {
+                return (redacted, rest.to_vec());
+            }
+            match self.secrets.iter().find(|s| rest.starts_with(s)) {
+                Some(secret) => {
+                    redacted.extend_from_slice(REDACTED.as_bytes());
+                    i += secret.len();
+                }
+                None => {
+                    redacted.push(data[i]);
+                    i += 1;
+                }
+            }
+        }
+        (redacted, Vec::new())
+    }
+
+    /// The next chunk of the stream, redacted.
+    pub fn push(&mut self, data: Vec<u8>) -> Vec<u8> {
+        if self.secrets.is_empty() {
+            return data;
+        }
+        let mut pending = std::mem::take(&mut self.held);
+        pending.extend(data);
+        let (redacted, held) = self.scan(&pending, true);
+        self.held = held;
+        redacted
+    }
+
+    /// The end of the stream, redacted.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let held = std::mem::take(&mut self.held);
+        self.scan(&held, false).0
+    }
+
+    /// Redact a whole text (eg. the outputs of a script).
+    pub fn redact(&self, text: &str) -> String {
+        String::from_utf8_lossy(&self.scan(text.as_bytes(), false).0).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ulid::Ulid;
+
+    fn redactor(secrets: &[&str]) -> Redactor {
+        let secrets: Vec<String> = secrets.iter().map(|s| s.to_string()).collect();
+        Redactor::new(&secrets)
+    }
+
+    /// Redact the chunks as a stream, and join the output.
+    fn stream(redactor: &mut Redactor, chunks: &[&str]) -> String {
+        let mut output: Vec<u8> = chunks
+            .iter()
+            .flat_map(|chunk| redactor.push(chunk.as_bytes().to_vec()))
+            .collect();
+        output.extend(redactor.finish());
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn a_secret_in_one_chunk_is_redacted() {
+        let mut redactor = redactor(&["hunter2"]);
+        assert_eq!(
+            stream(&mut redactor, &["the password is hunter2.\n"]),
+            "the password is ****.\n"
+        );
+    }
+
+    #[test]
+    fn a_secret_split_across_chunks_is_redacted() {
+        let mut redactor = redactor(&["hunter2"]);
+        assert_eq!(
+            stream(
+                &mut redactor,
+                &["password: hun", "te", "r2, again: h", "unter2\n"]
+            ),
+            "password: ****, again: ****\n"
+        );
+    }
+
+    #[test]
+    fn the_held_start_of_a_secret_is_output_if_it_is_not_one() {
+        let mut redactor = redactor(&["hunter2"]);
+        assert_eq!(redactor.push(b"a hunt".to_vec()), b"a ");
+        assert_eq!(redactor.push(b"er".to_vec()), b"");
+        assert_eq!(redactor.push(b"er\n".to_vec()), b"hunterer\n");
+        // The stream may end in the start of a secret:
+        assert_eq!(redactor.push(b"hunter".to_vec()), b"");
+        assert_eq!(redactor.finish(), b"hunter");
+    }
+
+    #[test]
+    fn the_longest_secret_is_redacted_as_a_whole() {
+        let mut redactor = redactor(&["abcd", "abcdefgh"]);
+        assert_eq!(
+            stream(&mut redactor, &["abcde", "fgh abcd abcdefg"]),
+            "**** **** ****efg"
+        );
+    }
+
+    #[test]
+    fn short_values_are_not_redacted() {
+        let mut redactor = redactor(&["abc"]);
+        assert_eq!(stream(&mut redactor, &["abc ab", "c"]), "abc abc");
+        assert_eq!(redactor.redact("abc"), "abc");
+    }
+
+    #[test]
+    fn the_passphrase_must_not_be_empty() {
+        let path = std::env::temp_dir().join(format!("dry_console-test-{}.vault", Ulid::new()));
+        assert!(matches!(
+            open_vault(&path, "", Some("")),
+            Err(VaultError::EmptyPassphrase)
+        ));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn a_new_vault_needs_the_passphrase_confirmed() {
+        let path = std::env::temp_dir().join(format!("dry_console-test-{}.vault", Ulid::new()));
+        for confirm in [None, Some("pasphrase")] {
+            assert!(matches!(
+                open_vault(&path, "passphrase", confirm),
+                Err(VaultError::Unconfirmed)
+            ));
+        }
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn a_secret_that_was_not_saved_is_not_kept() {
+        // The vault file cannot be written, its directory is a file:
+        let file = std::env::temp_dir().join(format!("dry_console-test-{}", Ulid::new()));
+        fs::write(&file, "").unwrap();
+        let mut vault = Vault::new(Some(file.join("secrets.vault")));
+        vault.unlock(UnlockedVault {
+            key: [0; KEY_LENGTH],
+            salt: vec![0; SALT_LENGTH],
+            iterations: 1,
+            secrets: BTreeMap::from([("KEPT".to_string(), "value".to_string())]),
+        });
+        assert!(vault.set("ADDED", Some("value".to_string())).is_err());
+        assert!(vault.set("KEPT", None).is_err());
+        assert_eq!(vault.status().names, ["KEPT"]);
+        fs::remove_file(file).unwrap();
+    }
+}
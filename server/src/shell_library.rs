@@ -0,0 +1,277 @@
+use dry_console_common::token::generate_deterministic_ulid_from_seed;
+use dry_console_dto::script::{script_includes, UnmetRequirement};
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use ulid::Ulid;
+
+include!(concat!(env!("OUT_DIR"), "/generated_shell_library.rs"));
+
+/// The default directory of user libraries: $XDG_DATA_HOME/dry_console/libraries
+pub fn default_library_dir() -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix("dry_console")
+        .ok()
+        .map(|dirs| dirs.get_data_home().join("libraries"))
+}
+
+/// A script, with the libraries it includes expanded in place.
+#[derive(Debug, Clone)]
+pub struct RenderedScript {
+    pub source: String,
+    /// The source before it was rendered as a template (the same as
+    /// source, if the script is not one).
+    pub expanded: String,
+    /// Is the script builtin, and so is every library it includes?
+    pub builtin: bool,
+}
+
+impl RenderedScript {
+    /// The id of this version of the script: a hash of the expanded
+    /// source, so that it does not change with the facts that a template
+    /// is rendered with.
+    pub fn id(&self) -> Ulid {
+        generate_deterministic_ulid_from_seed(&self.expanded)
+    }
+}
+
+/// Why a script could not be rendered.
+#[derive(Debug, Clone)]
+pub enum RenderError {
+    /// The included libraries could not be found, or include each other.
+    Include(String),
+    /// The script is a template, that could not be rendered.
+    Template(String),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Include(message) | RenderError::Template(message) => {
+                write!(f, "{message}")
+            }
+        }
+    }
+}
+
+impl From<RenderError> for UnmetRequirement {
+    fn from(error: RenderError) -> Self {
+        match error {
+            RenderError::Include(message) => UnmetRequirement::Include(message),
+            RenderError::Template(message) => UnmetRequirement::Template(message),
+        }
+    }
+}
+
+/// The shell libraries that scripts may include (`# include: NAME`):
+/// the builtin ones (src/api/workstation/libraries), then the user's
+/// own (NAME.sh in the library directory).
+#[derive(Debug, Clone, Default)]
+pub struct ShellLibrary {
+    dir: Option<PathBuf>,
+}
+
+/// An included library, without its header.
+struct Included {
+    name: String,
+    body: String,
+    builtin: bool,
+}
+
+/// The header of a script or library: the comment lines at the top.
+fn split_header(source: &str) -> (&str, &str) {
+    let header_length = source
+        .split_inclusive('\n')
+        .take_while(|line| line.starts_with('#'))
+        .map(str::len)
+        .sum();
+    source.split_at(header_length)
+}
+
+impl ShellLibrary {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    /// The source of a library, and whether it is builtin.
+    fn find(&self, name: &str) -> Result<(String, bool), String> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!("Invalid library name: {name}"));
+        }
+        if let Some((_, source)) = SHELL_LIBRARY_SOURCES.iter().find(|(n, _)| *n == name) {
+            return Ok((source.to_string(), true));
+        }
+        let Some(dir) = &self.dir else {
+            return Err(format!("Unknown library: {name}"));
+        };
+        match std::fs::read_to_string(dir.join(format!("{name}.sh"))) {
+            Ok(source) => Ok((source, false)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(format!("Unknown library: {name}"))
+            }
+            Err(e) => Err(format!("Could not read library {name}: {e}")),
+        }
+    }
+
+    /// Add the library, after the libraries it includes (each of them
+    /// only once), to included. stack is the chain of libraries that
+    /// led to it, to detect cycles.
+    fn include(
+        &self,
+        name: &str,
+        stack: &mut Vec<String>,
+        included: &mut Vec<Included>,
+    ) -> Result<(), String> {
+        if stack.iter().any(|n| n == name) {
+            return Err(format!("Include cycle: {} -> {name}", stack.join(" -> ")));
+        }
+        if included.iter().any(|i| i.name == name) {
+            return Ok(());
+        }
+        let (source, builtin) = self.find(name)?;
+        stack.push(name.to_string());
+        for dependency in script_includes(&source) {
+            self.include(&dependency, stack, included)?;
+        }
+        stack.pop();
+        included.push(Included {
+            name: name.to_string(),
+            body: split_header(&source).1.trim().to_string(),
+            builtin,
+        });
+        Ok(())
+    }
+
+    /// Expand the libraries that the script (the full source) includes,
+    /// between its header and its body.
+    pub fn render(&self, source: &str) -> Result<RenderedScript, String> {
+        let id = generate_deterministic_ulid_from_seed(source);
+        let builtin = crate::STATIC_COMMAND_LIBRARY_MAP.contains_key(&id.to_string());
+        let mut included = Vec::new();
+        for name in script_includes(source) {
+            self.include(&name, &mut Vec::new(), &mut included)?;
+        }
+        if included.is_empty() {
+            return Ok(RenderedScript {
+                source: source.to_string(),
+                expanded: source.to_string(),
+                builtin,
+            });
+        }
+        let (header, body) = split_header(source);
+        let mut rendered = header.to_string();
+        for library in &included {
+            let origin = match library.builtin {
+                true => "builtin",
+                false => "user",
+            };
+            // (Not comments, which would be taken as part of the header.)
+            rendered.push_str(&format!(": \"include {} ({origin})\"\n", library.name));
+            rendered.push_str(&library.body);
+            rendered.push_str(&format!("\n: \"end include {}\"\n", library.name));
+        }
+        rendered.push_str(body);
+        Ok(RenderedScript {
+            expanded: rendered.clone(),
+            source: rendered,
+            builtin: builtin && included.iter().all(|i| i.builtin),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ulid::Ulid;
+
+    /// A directory of user libraries, removed when dropped.
+    struct TestLibraries(PathBuf);
+
+    impl TestLibraries {
+        fn new(libraries: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("dry_console-test-{}", Ulid::new()));
+            std::fs::create_dir(&dir).unwrap();
+            for (name, source) in libraries {
+                std::fs::write(dir.join(format!("{name}.sh")), source).unwrap();
+            }
+            Self(dir)
+        }
+
+        fn library(&self) -> ShellLibrary {
+            ShellLibrary::new(Some(self.0.clone()))
+        }
+    }
+
+    impl Drop for TestLibraries {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn a_script_without_includes_is_unchanged() {
+        let source = "# Test\necho hi\n";
+        let rendered = ShellLibrary::default().render(source).unwrap();
+        assert_eq!(rendered.source, source);
+    }
+
+    #[test]
+    fn libraries_are_included_once_after_their_own_includes() {
+        let libraries = TestLibraries::new(&[
+            ("first", "# include: second\nfirst() { :; }\n"),
+            ("second", "# The second\nsecond() { :; }\n"),
+        ]);
+        let source = "# Test\n# include: first, second\necho hi\n";
+        let rendered = libraries.library().render(source).unwrap();
+        assert_eq!(rendered.source.matches("second() { :; }").count(), 1);
+        let first = rendered.source.find("first() { :; }").unwrap();
+        let second = rendered.source.find("second() { :; }").unwrap();
+        assert!(second < first);
+        assert!(rendered
+            .source
+            .starts_with("# Test\n# include: first, second\n"));
+        assert!(rendered.source.ends_with("echo hi\n"));
+        assert!(!rendered.builtin);
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let libraries = TestLibraries::new(&[
+            ("one", "# include: two\n"),
+            ("two", "# include: three\n"),
+            ("three", "# include: one\n"),
+        ]);
+        let error = libraries
+            .library()
+            .render("# Test\n# include: one\necho hi\n")
+            .unwrap_err();
+        assert_eq!(error, "Include cycle: one -> two -> three -> one");
+    }
+
+    #[test]
+    fn a_library_that_includes_itself_is_a_cycle() {
+        let libraries = TestLibraries::new(&[("self", "# include: self\n")]);
+        let error = libraries
+            .library()
+            .render("# Test\n# include: self\necho hi\n")
+            .unwrap_err();
+        assert_eq!(error, "Include cycle: self -> self");
+    }
+
+    #[test]
+    fn unknown_and_invalid_libraries_are_errors() {
+        let libraries = TestLibraries::new(&[]);
+        let library = libraries.library();
+        assert!(library
+            .render("# Test\n# include: missing\n")
+            .unwrap_err()
+            .contains("Unknown library: missing"));
+        assert!(library
+            .render("# Test\n# include: ../etc/passwd\n")
+            .unwrap_err()
+            .contains("Invalid library name"));
+    }
+}
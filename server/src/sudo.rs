@@ -1,12 +1,47 @@
-use std::io::{self, ErrorKind};
+use crate::app_state::SharedState;
+use crate::metrics::SUDO_KEEPALIVE_FAILURES;
+use crate::response::AppError;
+use dry_console_dto::events::Event;
+use dry_console_dto::sudo::{Escalator, SudoState, SudoStatus};
+use nix::errno::Errno;
+use nix::sys::stat::Mode;
+use nix::unistd::mkfifo;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use strum::IntoEnumIterator;
 use tokio::process::Command;
+use tokio::sync::Notify;
 use tokio::time::{self, Duration};
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use ulid::Ulid;
+use which::which;
 
-/// Acquire sudo, blocking on authentication, but timeout eventually.
-pub async fn acquire_sudo(timeout: u64) -> io::Result<()> {
-    let command_future = Command::new("sudo")
+/// How often to check if sudo has asked for a password.
+const ASKPASS_POLL_INTERVAL: u64 = 200;
+
+/// Pick the escalator to use: the configured one (--escalator, or the
+/// config file) if it is installed, otherwise the first one that is
+/// installed.
+pub fn detect_escalator(configured: Option<Escalator>) -> Option<Escalator> {
+    let installed = |e: &Escalator| which(e.as_ref()).is_ok();
+    match configured {
+        Some(e) if installed(&e) => Some(e),
+        Some(e) => {
+            warn!("The configured escalator ({e}) is not installed.");
+            None
+        }
+        None => Escalator::iter().find(installed),
+    }
+}
+
+/// Acquire root, blocking on authentication, but timeout eventually.
+pub async fn acquire_sudo(escalator: Escalator, timeout: u64) -> io::Result<()> {
+    let command_future = Command::new(escalator.as_ref())
         .arg("whoami")
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -15,40 +50,307 @@ pub async fn acquire_sudo(timeout: u64) -> io::Result<()> {
 
     match time::timeout(Duration::from_secs(timeout), command_future).await {
         Ok(Ok(output)) if output.status.success() => Ok(()),
-        Ok(Ok(_)) => Err(io::Error::new(
-            ErrorKind::Other,
-            "failed to authenticate with sudo",
+        Ok(Ok(_)) => Err(io::Error::other(format!(
+            "failed to authenticate with {escalator}"
+        ))),
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(io::Error::new(
+            ErrorKind::TimedOut,
+            format!("{escalator} command timed out"),
         )),
+    }
+}
+
+/// Refresh the root session, without ever prompting for a password.
+async fn refresh_sudo(escalator: Escalator, timeout: u64) -> io::Result<()> {
+    let command_future = Command::new(escalator.as_ref())
+        .args(escalator.non_interactive_args().unwrap_or_default())
+        .arg("whoami")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .status();
+
+    match time::timeout(Duration::from_secs(timeout), command_future).await {
+        Ok(Ok(status)) if status.success() => Ok(()),
+        Ok(Ok(_)) => Err(io::Error::other(format!(
+            "the {escalator} session has expired"
+        ))),
         Ok(Err(e)) => Err(e),
         Err(_) => Err(io::Error::new(
             ErrorKind::TimedOut,
-            "sudo command timed out",
+            format!("{escalator} command timed out"),
         )),
     }
 }
 
-/// Thread continually keeps the sudo session active by running whoami every 60s.
-/// If authentication fails, terminate thread.
+/// Update the sudo status, and notify all subscribers.
+/// AppState.sudo_enabled is only true while the status is Active.
+pub async fn set_status(state: &SharedState, sudo_state: SudoState, message: Option<String>) {
+    let mut state = state.write().await;
+    state.sudo_enabled = sudo_state == SudoState::Active;
+    let can_acquire = !state.opt.no_sudo && state.escalator.is_some_and(|e| e.supports_askpass());
+    let escalator = state.escalator;
+    let status = SudoStatus {
+        state: sudo_state,
+        message,
+        can_acquire,
+        escalator,
+    };
+    state.events.publish(Event::SudoStatus(status.clone()));
+    state.sudo_status.send_replace(status);
+}
+
+/// Task that keeps the sudo session active by refreshing it every
+/// refresh_interval, for as long as it is Active. If the refresh
+/// fails, the status becomes Expired, and the task waits for sudo to
+/// be re-acquired (see acquire_sudo_askpass) before it continues.
 ///
-///   #[tokio::main]
-///   async fn main() {
-///       tokio::spawn(async {
-///           keep_sudo_session_alive().await;
-///       });
-///   }
-pub async fn keep_sudo_session_alive(refresh_interval: u64, sudo_timeout: u64) {
+///   tokio::spawn(async move {
+///       keep_sudo_session_alive(state, Escalator::Sudo, 60, 60).await;
+///   });
+pub async fn keep_sudo_session_alive(
+    state: SharedState,
+    escalator: Escalator,
+    refresh_interval: u64,
+    sudo_timeout: u64,
+) {
+    let mut status = state.read().await.sudo_status.subscribe();
     loop {
-        match acquire_sudo(sudo_timeout).await {
+        if status
+            .wait_for(|s| s.state == SudoState::Active)
+            .await
+            .is_err()
+        {
+            return;
+        }
+        time::sleep(Duration::from_secs(refresh_interval)).await;
+        if status.borrow().state != SudoState::Active {
+            continue;
+        }
+        match refresh_sudo(escalator, sudo_timeout).await {
             Ok(_) => {
                 info!(
-                    "Root (sudo) authentication successful. The session will be refreshed in {refresh_interval}s."
+                    "Root ({escalator}) authentication successful. The session will be refreshed in {refresh_interval}s."
                 );
-                time::sleep(Duration::from_secs(refresh_interval)).await;
             }
             Err(e) => {
-                error!("Failed to acquire sudo authentication: {:?}", e);
-                break;
+                error!("Failed to acquire {escalator} authentication: {:?}", e);
+                SUDO_KEEPALIVE_FAILURES.inc();
+                set_status(&state, SudoState::Expired, Some(e.to_string())).await;
             }
         }
     }
 }
+
+/// An in-progress `sudo -A` authentication. The askpass helper reads
+/// the password from a FIFO, which is written by send_password.
+#[derive(Clone, Debug)]
+pub struct Askpass {
+    dir: PathBuf,
+    /// Incremented each time sudo runs the askpass helper:
+    asked: Arc<AtomicUsize>,
+    /// Incremented each time the user sends a password:
+    answered: Arc<AtomicUsize>,
+    cancel: Arc<Notify>,
+}
+
+impl Askpass {
+    fn create() -> io::Result<Self> {
+        let base = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        let dir = base.join(format!("dry_console-askpass-{}", Ulid::new()));
+        DirBuilder::new().mode(0o700).create(&dir)?;
+        let askpass = Askpass {
+            dir,
+            asked: Arc::new(AtomicUsize::new(0)),
+            answered: Arc::new(AtomicUsize::new(0)),
+            cancel: Arc::new(Notify::new()),
+        };
+        mkfifo(&askpass.fifo(), Mode::S_IRUSR | Mode::S_IWUSR).map_err(io::Error::from)?;
+        // sudo runs this once per password attempt:
+        fs::write(
+            askpass.helper(),
+            format!(
+                "#!/bin/sh\necho >> '{asked}'\nexec cat '{fifo}'\n",
+                asked = askpass.dir.join("asked").display(),
+                fifo = askpass.fifo().display(),
+            ),
+        )?;
+        fs::set_permissions(askpass.helper(), fs::Permissions::from_mode(0o700))?;
+        Ok(askpass)
+    }
+
+    fn fifo(&self) -> PathBuf {
+        self.dir.join("password")
+    }
+
+    fn helper(&self) -> PathBuf {
+        self.dir.join("askpass.sh")
+    }
+
+    /// Update the asked count from the helper's log.
+    fn poll_asked(&self) -> usize {
+        let asked = fs::read(self.dir.join("asked"))
+            .map(|b| b.iter().filter(|c| **c == b'\n').count())
+            .unwrap_or(0);
+        self.asked.store(asked, Ordering::SeqCst);
+        asked
+    }
+}
+
+impl Drop for Askpass {
+    fn drop(&mut self) {
+        // The last copy removes the files:
+        if Arc::strong_count(&self.cancel) == 1 {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+/// Re-acquire sudo, asking the user (in the browser) for their
+/// password via an askpass helper. Returns once sudo is either
+/// waiting for the password, or has finished.
+pub async fn acquire_sudo_askpass(state: SharedState) -> Result<(), AppError> {
+    let (askpass, mut child, timeout, previous) = {
+        // Hold the lock, so only one authentication runs at a time:
+        let mut state = state.write().await;
+        if state.opt.no_sudo {
+            return Err(AppError::Conflict(
+                "sudo is disabled (--no-sudo)".to_string(),
+            ));
+        }
+        match state.escalator {
+            Some(e) if e.supports_askpass() => {}
+            Some(e) => {
+                return Err(AppError::Conflict(format!(
+                    "{e} can not be authenticated from the browser, restart dry_console with --sudo instead"
+                )))
+            }
+            None => {
+                return Err(AppError::Conflict(
+                    "no privilege escalation program (sudo, doas, run0 or pkexec) is installed"
+                        .to_string(),
+                ))
+            }
+        }
+        if state.sudo_askpass.is_some() {
+            return Err(AppError::Conflict(
+                "sudo is already authenticating".to_string(),
+            ));
+        }
+        let askpass = Askpass::create().map_err(AppError::Io)?;
+        let child = Command::new("sudo")
+            .args(["-A", "whoami"])
+            .env("SUDO_ASKPASS", askpass.helper())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(AppError::Io)?;
+        state.sudo_askpass = Some(askpass.clone());
+        let previous = state.sudo_status.borrow().state;
+        (askpass, child, state.opt.sudo_timeout_seconds, previous)
+    };
+    set_status(&state, SudoState::Authenticating, None).await;
+
+    let (started_tx, started_rx) = tokio::sync::oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let mut started_tx = Some(started_tx);
+        let deadline = time::sleep(Duration::from_secs(timeout));
+        tokio::pin!(deadline);
+        let mut poll = time::interval(Duration::from_millis(ASKPASS_POLL_INTERVAL));
+        let result = loop {
+            tokio::select! {
+                status = child.wait() => break match status {
+                    Ok(status) if status.success() => Ok(()),
+                    Ok(_) => Err("Authentication failed.".to_string()),
+                    Err(e) => Err(e.to_string()),
+                },
+                _ = poll.tick() => {
+                    let asked = askpass.poll_asked();
+                    let answered = askpass.answered.load(Ordering::SeqCst);
+                    if asked > answered && state.read().await.sudo_status.borrow().state != SudoState::AwaitingPassword {
+                        let message = (answered > 0).then(|| "Sorry, try again.".to_string());
+                        set_status(&state, SudoState::AwaitingPassword, message).await;
+                        if let Some(tx) = started_tx.take() {
+                            let _ = tx.send(());
+                        }
+                    }
+                },
+                _ = &mut deadline => break Err("Timed out waiting for the password.".to_string()),
+                _ = askpass.cancel.notified() => break Err("Authentication was cancelled.".to_string()),
+            }
+        };
+        let _ = child.kill().await;
+        state.write().await.sudo_askpass = None;
+        match result {
+            Ok(_) => {
+                info!("Root (sudo) authentication was re-acquired.");
+                set_status(&state, SudoState::Active, None).await;
+            }
+            Err(e) => {
+                warn!("Failed to re-acquire sudo authentication: {}", e);
+                // Sudo was never acquired, so it has not expired either:
+                let sudo_state = match previous {
+                    SudoState::Disabled => SudoState::Disabled,
+                    _ => SudoState::Expired,
+                };
+                set_status(&state, sudo_state, Some(e)).await;
+            }
+        }
+        drop(askpass);
+        if let Some(tx) = started_tx.take() {
+            let _ = tx.send(());
+        }
+    });
+    let _ = started_rx.await;
+    Ok(())
+}
+
+/// Pass the user's password to the waiting askpass helper.
+pub async fn send_password(state: SharedState, password: String) -> Result<(), AppError> {
+    let askpass = state
+        .read()
+        .await
+        .sudo_askpass
+        .clone()
+        .ok_or_else(|| AppError::Conflict("sudo is not authenticating".to_string()))?;
+    if askpass.asked.load(Ordering::SeqCst) <= askpass.answered.load(Ordering::SeqCst) {
+        return Err(AppError::Conflict(
+            "sudo is not waiting for a password".to_string(),
+        ));
+    }
+    // Never block: the helper has the FIFO open for reading by now,
+    // otherwise this fails with ENXIO.
+    let mut fifo = OpenOptions::new()
+        .write(true)
+        .custom_flags(nix::fcntl::OFlag::O_NONBLOCK.bits())
+        .open(askpass.fifo())
+        .map_err(
+            |e| match Errno::from_raw(e.raw_os_error().unwrap_or_default()) {
+                Errno::ENXIO => {
+                    AppError::Conflict("sudo is not waiting for a password".to_string())
+                }
+                _ => AppError::Io(e),
+            },
+        )?;
+    askpass.answered.fetch_add(1, Ordering::SeqCst);
+    set_status(&state, SudoState::Authenticating, None).await;
+    fifo.write_all(format!("{password}\n").as_bytes())
+        .map_err(AppError::Io)
+}
+
+/// Abort an in-progress authentication.
+pub async fn cancel(state: SharedState) -> Result<(), AppError> {
+    match &state.read().await.sudo_askpass {
+        Some(askpass) => {
+            askpass.cancel.notify_one();
+            Ok(())
+        }
+        None => Err(AppError::Conflict("sudo is not authenticating".to_string())),
+    }
+}
@@ -0,0 +1,53 @@
+use dry_console_dto::workstation::WorkstationFacts;
+use minijinja::syntax::SyntaxConfig;
+use minijinja::value::{Value, ValueKind};
+use minijinja::{Environment, UndefinedBehavior};
+
+/// The `quote` filter: quote a fact for the shell, eg. `cd {{ home | quote }}`
+/// (a list becomes separate words, and none an empty one). Facts are not
+/// quoted otherwise.
+fn quote(value: Value) -> String {
+    fn quote_word(word: &str) -> String {
+        format!("'{}'", word.replace('\'', r#"'\''"#))
+    }
+    match (value.kind(), value.try_iter()) {
+        (ValueKind::Seq, Ok(items)) => items
+            .map(|item| quote_word(&item.to_string()))
+            .collect::<Vec<_>>()
+            .join(" "),
+        (ValueKind::None, _) => quote_word(""),
+        _ => quote_word(&value.to_string()),
+    }
+}
+
+/// Templates use the Jinja syntax (see minijinja), except that comments
+/// are `{## ... ##}`, as `{#` is common in shell (eg. `${#array[@]}`).
+/// Statements may also be written on lines of their own, starting with
+/// `#%`, so that the template is still a valid script:
+///
+///   #% for name in missing
+///   echo "{{ name }} is not installed."
+///   #% endfor
+fn environment() -> Environment<'static> {
+    let mut environment = Environment::new();
+    environment.set_syntax(
+        SyntaxConfig::builder()
+            .comment_delimiters("{##", "##}")
+            .line_statement_prefix("#%")
+            .build()
+            .expect("the template syntax is valid"),
+    );
+    // A misspelled fact is an error, rather than an empty string:
+    environment.set_undefined_behavior(UndefinedBehavior::Strict);
+    environment.set_keep_trailing_newline(true);
+    environment.add_filter("quote", quote);
+    environment
+}
+
+/// Render a script template (the full source) with the facts of the
+/// workstation.
+pub fn render_template(source: &str, facts: &WorkstationFacts) -> Result<String, String> {
+    environment()
+        .render_str(source, facts)
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,107 @@
+use crate::api::workstation::command::CommandLibrary;
+use crate::config::write_file_atomically;
+use crate::shell_library::RenderedScript;
+use dry_console_dto::trust::{line_diff, ScriptReview};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use tracing::{info, warn};
+use ulid::Ulid;
+
+/// The default trust store: $XDG_DATA_HOME/dry_console/trusted_scripts.json
+pub fn default_trust_store_path() -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix("dry_console")
+        .ok()
+        .map(|dirs| dirs.get_data_home().join("trusted_scripts.json"))
+}
+
+/// A version of a script that the user has approved.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ApprovedScript {
+    id: Ulid,
+    source: String,
+}
+
+/// The scripts that may be run as root: the builtin ones (compiled
+/// into the server, including only builtin libraries), and the versions
+/// of every other script that the user has approved, trust on first
+/// use. Script ids are derived from a hash of their source, with the
+/// libraries it includes expanded (see RenderedScript::id), so any change
+/// to a script, or to a user library that it includes, needs approving
+/// again. (Templates are approved as they are, before they are rendered.)
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    path: Option<PathBuf>,
+    /// The approved versions of each script, by name, oldest first:
+    approved: BTreeMap<String, Vec<ApprovedScript>>,
+}
+
+impl TrustStore {
+    /// Load the trust store from path. If it can't be read, nothing is
+    /// trusted but the builtin scripts.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let approved = match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(source)) => serde_json::from_str(&source).unwrap_or_else(|e| {
+                warn!("The trust store is invalid, so no scripts are approved: {e}");
+                BTreeMap::new()
+            }),
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                warn!("Could not read the trust store, so no scripts are approved: {e}");
+                BTreeMap::new()
+            }
+            _ => BTreeMap::new(),
+        };
+        Self { path, approved }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        match &self.path {
+            Some(path) => write_file_atomically(path, &serde_json::to_vec_pretty(&self.approved)?),
+            None => Ok(()),
+        }
+    }
+
+    /// May the script be run? (See RenderedScript::builtin.)
+    pub fn is_trusted(&self, command: &CommandLibrary, script: &RenderedScript) -> bool {
+        let id = script.id();
+        script.builtin
+            || self
+                .approved
+                .get(command.as_ref())
+                .is_some_and(|versions| versions.iter().any(|v| v.id == id))
+    }
+
+    pub fn review(&self, command: &CommandLibrary, script: &RenderedScript) -> ScriptReview {
+        let last_approved = self
+            .approved
+            .get(command.as_ref())
+            .and_then(|versions| versions.last());
+        ScriptReview {
+            command: command.to_string(),
+            id: script.id(),
+            source: script.expanded.clone(),
+            trusted: self.is_trusted(command, script),
+            approved_id: last_approved.map(|v| v.id),
+            diff: line_diff(
+                last_approved.map(|v| v.source.as_str()).unwrap_or_default(),
+                &script.expanded,
+            ),
+        }
+    }
+
+    /// Approve this version of the script, and save the trust store.
+    pub fn approve(&mut self, command: &CommandLibrary, script: &RenderedScript) -> io::Result<()> {
+        let id = script.id();
+        let versions = self.approved.entry(command.to_string()).or_default();
+        // Keep each version once, as the most recently approved:
+        versions.retain(|v| v.id != id);
+        versions.push(ApprovedScript {
+            id,
+            source: script.expanded.clone(),
+        });
+        info!("Approved {command} ({id}).");
+        self.save()
+    }
+}
@@ -0,0 +1,316 @@
+use dry_console_dto::websocket::AnnotationLevel;
+
+/// A line that may be a command is held back until it ends, unless it
+/// gets longer than this (and then it is just output):
+const MAX_COMMAND_LENGTH: usize = 64 * 1024;
+
+/// A command that a script writes to stdout, on a line of its own, in
+/// the style of GitHub Actions' workflow commands:
+///
+///   ::set-output name=VERSION::1.2.3
+///   ::progress 40/100::Downloading images
+///   ::group::Building the images
+///   ::endgroup::
+///   ::warning::The disk is nearly full
+///   ::error title=Build failed::The image could not be built
+///
+/// In values, `%25`, `%0D` and `%0A` stand for `%`, CR and LF (and, in
+/// properties, `%3A` and `%2C` for `:` and `,`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkflowCommand {
+    SetOutput {
+        name: String,
+        value: String,
+    },
+    Progress {
+        current: u64,
+        total: u64,
+        message: Option<String>,
+    },
+    Group(String),
+    EndGroup,
+    Annotation {
+        level: AnnotationLevel,
+        title: Option<String>,
+        message: String,
+    },
+}
+
+fn unescape_value(value: &str) -> String {
+    value
+        .replace("%0D", "\r")
+        .replace("%0A", "\n")
+        .replace("%25", "%")
+}
+
+fn unescape_property(value: &str) -> String {
+    unescape_value(&value.replace("%3A", ":").replace("%2C", ","))
+}
+
+/// The `key=value,...` properties of a command.
+fn property(properties: &str, key: &str) -> Option<String> {
+    properties
+        .split(',')
+        .filter_map(|property| property.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, value)| unescape_property(value))
+}
+
+/// Parse a line of output (without its line ending), if it is a
+/// command. Anything else (even if it starts with `::`) is output.
+pub fn parse_workflow_command(line: &str) -> Option<WorkflowCommand> {
+    let line = line.strip_prefix("::")?;
+    let (head, value) = line.split_once("::").unwrap_or((line, ""));
+    let (name, properties) = head.split_once(' ').unwrap_or((head, ""));
+    let value = unescape_value(value);
+    match name {
+        "set-output" => Some(WorkflowCommand::SetOutput {
+            name: property(properties, "name").filter(|name| !name.is_empty())?,
+            value,
+        }),
+        "progress" => {
+            let (current, total) = properties.trim().split_once('/')?;
+            Some(WorkflowCommand::Progress {
+                current: current.parse().ok()?,
+                total: total.parse().ok().filter(|total| *total > 0)?,
+                message: Some(value).filter(|message| !message.is_empty()),
+            })
+        }
+        "group" => Some(WorkflowCommand::Group(value)),
+        "endgroup" => Some(WorkflowCommand::EndGroup),
+        "warning" | "error" => Some(WorkflowCommand::Annotation {
+            level: match name {
+                "warning" => AnnotationLevel::Warning,
+                _ => AnnotationLevel::Error,
+            },
+            title: property(properties, "title"),
+            message: value,
+        }),
+        _ => None,
+    }
+}
+
+/// Some stdout, or a command that was found in it.
+#[derive(Debug, PartialEq)]
+pub enum Parsed {
+    Output(Vec<u8>),
+    Command(WorkflowCommand),
+}
+
+/// Finds the commands in the stdout of a script, as it arrives in
+/// chunks. Lines that may be commands are held back until they end,
+/// and the rest of the output passes straight through.
+#[derive(Debug, Default)]
+pub struct CommandParser {
+    held: Vec<u8>,
+    /// The output so far ends in the middle of a line that is not a
+    /// command:
+    mid_line: bool,
+}
+
+impl CommandParser {
+    /// The next chunk of stdout, split into output and commands (in
+    /// the order that they were written).
+    pub fn push(&mut self, data: Vec<u8>) -> Vec<Parsed> {
+        let mut pending = std::mem::take(&mut self.held);
+        pending.extend(data);
+        let mut parsed = Vec::new();
+        let mut output = Vec::new();
+        let mut rest = &pending[..];
+        while !rest.is_empty() {
+            let end = rest.iter().position(|b| *b == b'\n');
+            if self.mid_line {
+                let (line, next) = rest.split_at(end.map_or(rest.len(), |end| end + 1));
+                output.extend_from_slice(line);
+                self.mid_line = end.is_none();
+                rest = next;
+                continue;
+            }
+            if !rest.starts_with(b"::") {
+                if b"::".starts_with(rest) {
+                    // Too short to tell yet:
+                    self.held = rest.to_vec();
+                    break;
+                }
+                self.mid_line = true;
+                continue;
+            }
+            let Some(end) = end else {
+                if rest.len() > MAX_COMMAND_LENGTH {
+                    self.mid_line = true;
+                    continue;
+                }
+                self.held = rest.to_vec();
+                break;
+            };
+            let line = String::from_utf8_lossy(&rest[..end]);
+            match parse_workflow_command(line.trim_end_matches('\r')) {
+                Some(command) => {
+                    if !output.is_empty() {
+                        parsed.push(Parsed::Output(std::mem::take(&mut output)));
+                    }
+                    parsed.push(Parsed::Command(command));
+                }
+                None => output.extend_from_slice(&rest[..=end]),
+            }
+            rest = &rest[end + 1..];
+        }
+        if !output.is_empty() {
+            parsed.push(Parsed::Output(output));
+        }
+        parsed
+    }
+
+    /// The end of stdout: the last line is a command even if it was not
+    /// ended with a newline.
+    pub fn finish(&mut self) -> Vec<Parsed> {
+        let held = std::mem::take(&mut self.held);
+        if held.is_empty() {
+            return Vec::new();
+        }
+        let line = String::from_utf8_lossy(&held).into_owned();
+        vec![match parse_workflow_command(line.trim_end_matches('\r')) {
+            Some(command) => Parsed::Command(command),
+            None => Parsed::Output(held),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse the chunks as a stream, joining output that is next to
+    /// other output.
+    fn parse(chunks: &[&str]) -> Vec<Parsed> {
+        let mut parser = CommandParser::default();
+        let mut parsed: Vec<Parsed> = Vec::new();
+        let all = chunks
+            .iter()
+            .flat_map(|chunk| parser.push(chunk.as_bytes().to_vec()))
+            .collect::<Vec<_>>();
+        for next in all.into_iter().chain(parser.finish()) {
+            match (parsed.last_mut(), next) {
+                (Some(Parsed::Output(output)), Parsed::Output(data)) => output.extend(data),
+                (_, next) => parsed.push(next),
+            }
+        }
+        parsed
+    }
+
+    fn output(text: &str) -> Parsed {
+        Parsed::Output(text.as_bytes().to_vec())
+    }
+
+    fn warning(message: &str) -> Parsed {
+        Parsed::Command(WorkflowCommand::Annotation {
+            level: AnnotationLevel::Warning,
+            title: None,
+            message: message.to_string(),
+        })
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        assert_eq!(
+            parse_workflow_command("::set-output name=VERSION::1.2.3"),
+            Some(WorkflowCommand::SetOutput {
+                name: "VERSION".to_string(),
+                value: "1.2.3".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_workflow_command("::progress 40/100::Downloading images"),
+            Some(WorkflowCommand::Progress {
+                current: 40,
+                total: 100,
+                message: Some("Downloading images".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_workflow_command("::group::Building"),
+            Some(WorkflowCommand::Group("Building".to_string()))
+        );
+        assert_eq!(
+            parse_workflow_command("::endgroup::"),
+            Some(WorkflowCommand::EndGroup)
+        );
+        assert_eq!(
+            parse_workflow_command("::error title=Build%3A failed::Line one%0Aline two, 100%25"),
+            Some(WorkflowCommand::Annotation {
+                level: AnnotationLevel::Error,
+                title: Some("Build: failed".to_string()),
+                message: "Line one\nline two, 100%".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_commands_are_output() {
+        for line in [
+            "echo ::warning::not at the start",
+            "::unknown::command",
+            "::set-output::no name",
+            "::progress 1/0::no total",
+            "::progress many::no numbers",
+        ] {
+            assert_eq!(parse_workflow_command(line), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn output_and_commands_are_kept_in_order() {
+        assert_eq!(
+            parse(&["one\n::warning::two\nthree\n::warning::four\n"]),
+            [
+                output("one\n"),
+                warning("two"),
+                output("three\n"),
+                warning("four")
+            ]
+        );
+    }
+
+    #[test]
+    fn a_command_split_across_chunks_is_parsed() {
+        assert_eq!(
+            parse(&["before\n:", ":warn", "ing::spl", "it\r\nafter\n"]),
+            [output("before\n"), warning("split"), output("after\n")]
+        );
+    }
+
+    #[test]
+    fn a_command_in_the_middle_of_a_line_is_output() {
+        assert_eq!(
+            parse(&["no newline yet ", "::warning::not a command\n"]),
+            [output("no newline yet ::warning::not a command\n")]
+        );
+    }
+
+    #[test]
+    fn the_last_line_may_be_a_command_without_a_newline() {
+        assert_eq!(
+            parse(&["done\n::warning::the end"]),
+            [output("done\n"), warning("the end")]
+        );
+        assert_eq!(parse(&["done\n:"]), [output("done\n:")]);
+    }
+
+    #[test]
+    fn a_line_too_long_to_be_a_command_is_output() {
+        let mut parser = CommandParser::default();
+        let long = format!("::warning::{}", "x".repeat(MAX_COMMAND_LENGTH));
+        assert_eq!(
+            parser.push(long.clone().into_bytes()),
+            [Parsed::Output(long.into_bytes())]
+        );
+        // The rest of the line is output too:
+        assert_eq!(
+            parser.push(b"x\n::endgroup::\n".to_vec()),
+            [
+                Parsed::Output(b"x\n".to_vec()),
+                Parsed::Command(WorkflowCommand::EndGroup)
+            ]
+        );
+    }
+}